@@ -1,42 +1,155 @@
+use proc_macro2::Ident;
 use quote::quote;
-use syn::Meta::List;
-use syn::{Attribute, NestedMeta};
-use syn::{DeriveInput, Path};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parenthesized, Token};
+use syn::{Attribute, DeriveInput, Path, Type};
+
+use crate::contract::type_in_types_crate;
+use crate::schema::schema_type_name;
 
 pub fn get_app_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
     let container = Container::from_attrs(input.attrs);
     let contract_exec_branches = container.contracts.iter().map(|contract| {
         quote! {
-            if id == <#contract as ckboots::OnChain>::_id().expect("should have an id") {
+            if id == #contract::_id() {
                 let ids = #contract::_get_args_ids();
-                let args = ids.into_iter().map(|id| {
+                let mut args: Vec<Vec<u8>> = Vec::with_capacity(ids.len());
+                for id in ids {
                     if id == "user_input" {
-                        &user_input
+                        args.push(user_input.clone());
                     } else {
-                        let data = self._manager.get_by_id(id).expect(&format!("cannot find the {}, perhaps it is not registered", id));
-                        data
+                        let key = keys
+                            .iter()
+                            .find(|(k_id, _)| *k_id == id)
+                            .map_or(&[][..], |(_, key)| key.as_slice());
+                        args.push(self._get_state(id, key)?);
                     }
-                }).collect();
-                let exec = #contract::new(args);
-                return exec.run();
+                }
+                let exec = #contract::new(args.iter().map(|a| a.as_slice()).collect())?;
+                let mut result = exec.run();
+                result.keys = keys;
+                return Ok(result);
             }
         }
     });
+    let types = &container.types;
+    let default_states = quote! {
+        let mut states: Vec<(&'static str, u8, Vec<u8>)> = vec![];
+        #(if let Some(id) = <#types as ckboots::OnChain>::_id() {
+            states.push((
+                id,
+                <#types as ckboots::OnChain>::_version(),
+                <#types as ckboots::OnChain>::_to_bytes(&<#types as ckboots::OnChain>::_default()),
+            ));
+        })*
+        states
+    };
     let ident = input.ident;
 
-    let generate_contracts = if cfg!(feature = "contract-generator") {
-        let type_str = container.types.iter().map(|p| {
-            quote! {
-                #p::__get_code_str__()
+    let cell_to_json = if cfg!(feature = "serde") {
+        quote! {
+            // Decode the data of a state cell into JSON with the registered
+            // type at its wrapper idx, which is the position of its id in the
+            // sorted ids, see `build_genesis_tx`
+            pub fn _cell_to_json(cell_data: &[u8]) -> Result<ckboots::serde_json::Value, ckboots::JsonError> {
+                let wrapper = ckboots::decode::<ckboots::OnChainWrapper>(cell_data)?;
+                let mut ids: Vec<&'static str> = vec![#(<#types as ckboots::OnChain>::_id()),*]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                ids.sort();
+                let id = ids.get(wrapper.idx as usize).copied();
+                #(if id.is_some() && <#types as ckboots::OnChain>::_id() == id {
+                    return ckboots::decode_to_json::<#types>(wrapper.version, &wrapper.data);
+                })*
+                Err(ckboots::JsonError::UnknownIdx(wrapper.idx))
             }
-        });
-        let types = quote! {
-            let type_strs = vec![
-                #(#type_str),*
-            ];
+        }
+    } else {
+        quote! {}
+    };
+
+    let molecule_decls = if cfg!(feature = "contract-generator") {
+        let type_names = container.types.iter().map(schema_type_name);
+        quote! {
+            // The `.mol` declarations of the registered types, see `write_schema`
+            pub fn _molecule_decls() -> Vec<String> {
+                use ckboots::__CodeStr__;
+                let mut decls: Vec<String> = vec![];
+                #(if <#types as ckboots::__CodeStr__>::__is_molecule__() {
+                    <#types as ckboots::__CodeStr__>::__get_molecule_decls__(&mut decls);
+                } else {
+                    ckboots::generators::contract::declare_default_encoding(
+                        &mut decls,
+                        #type_names,
+                        <#types as ckboots::OnChain>::_fixed_size(),
+                    );
+                })*
+                decls
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let generate_contracts = if cfg!(feature = "contract-generator") {
+        let write_types = quote! {
+            let mut type_strs: Vec<&'static str> = vec![];
+            #(
+                type_strs.push(<#types as ckboots::__CodeStr__>::__get_code_str__());
+                type_strs.extend(<#types as ckboots::__CodeStr__>::__get_migration_code_str__());
+            )*
             ckboots::generators::contract::write_types("proj-name-contracts/contracts", type_strs);
         };
 
+        let write_schema = quote! {
+            ckboots::generators::contract::write_schema("proj-name-contracts", #ident::_molecule_decls());
+        };
+
+        // The registered name of a generic type tells its parameters
+        let type_names = container.types.iter().map(schema_type_name);
+        let contracts = &container.contracts;
+        let write_json_schema = quote! {
+            let _schema = ckboots::generators::contract::AppSchema {
+                types: vec![#({
+                    let mut _type = <#types as ckboots::__CodeStr__>::__get_type_schema__();
+                    _type.name = String::from(#type_names);
+                    _type
+                }),*],
+                contracts: vec![#(#contracts::_schema()),*],
+            };
+            ckboots::generators::contract::write_json_schema("proj-name-contracts", &_schema);
+        };
+
+        // The migration contracts only check that the output is the input
+        // upgraded to the current version
+        let type_paths = container.types.iter().map(type_in_types_crate);
+        let migrations = quote! {
+            let mut _migrations: Vec<(&'static str, &'static str)> = vec![];
+            #(if let Some(_id) = <#types as ckboots::OnChain>::_migration_id() {
+                if !_migrations.iter().any(|(id, _)| *id == _id) {
+                    _migrations.push((_id, #type_paths));
+                }
+            })*
+        };
+        let write_migrations = quote! {
+            for (_id, _type_path) in _migrations.iter() {
+                let _code = ckboots::generators::contract::get_contract_code(
+                    &[],
+                    &[(String::from("state"), _type_path.to_string())],
+                    None,
+                    String::from("{}"),
+                );
+                ckboots::generators::contract::write_contract("proj-name-contracts/contracts", _code, _id);
+            }
+        };
+
+        let fuzz_types = container.types.iter().map(type_in_types_crate);
+        let write_fuzz = quote! {
+            ckboots::generators::contract::write_fuzz("proj-name-contracts", &[#(#fuzz_types),*]);
+        };
+
         let contract = container.contracts.iter().map(|c| {
             quote! {
                 let _id = #c::_id();
@@ -56,6 +169,7 @@ pub fn get_app_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
             quote! {
                 let mut _ids: Vec<&'static str> = Vec::new();
                 #(#ids)*
+                _ids.extend(_migrations.iter().map(|(id, _)| *id));
                 ckboots::generators::contract::write_capsule("proj-name", &_ids);
             }
         };
@@ -66,14 +180,19 @@ pub fn get_app_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
                 }
             });
             let ids_idx = 0..container.types.len();
-            let type_paths = container.types.iter().map(|p| quote! {#p});
+            let type_paths = container.types.iter().map(type_in_types_crate);
             quote! {
-                let mut _type_ids_idx:Vec<(&'static str, usize)> = vec![
-                    #((#ids.unwrap(), #ids_idx)),*
-                ];
+                // Helper types without an id are shipped in the types crate
+                // but have no state cell to check
+                let mut _type_ids_idx: Vec<(&'static str, usize)> = vec![
+                    #((#ids, #ids_idx)),*
+                ]
+                .into_iter()
+                .filter_map(|(id, idx)| Some((id?, idx)))
+                .collect();
                 _type_ids_idx.sort_by_key(|k| k.0);
-                let _type_path: Vec<String> = vec![
-                    #(ckboots::quote!{#type_paths}.to_string()),*
+                let _type_path: Vec<&'static str> = vec![
+                    #(#type_paths),*
                 ];
                 let data = _type_ids_idx.into_iter().map(|(id, idx)| {
                     let p = _type_path.get(idx).unwrap().to_string();
@@ -86,10 +205,15 @@ pub fn get_app_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
             #[test]
             pub fn generate_contracts() {
                 use ckboots::__CodeStr__;
+                #migrations
                 #capsule
-                #types
+                #write_types
                 #entry
                 #(#contract)*
+                #write_migrations
+                #write_schema
+                #write_json_schema
+                #write_fuzz
             }
 
         }
@@ -100,12 +224,74 @@ pub fn get_app_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
     quote! {
 
         impl #ident {
-            pub fn _exec<T: ckboots::OnChain>(&self, id: &str, user_input: T) -> ContractResult
+            // Fails if a state is not held by the manager or cannot be decoded
+            pub fn _exec<T: ckboots::OnChain>(&self, id: &str, user_input: T) -> Result<ContractResult, ckboots::StateError>
+            {
+                self._exec_instances(id, user_input, vec![])
+            }
+
+            // Like `_exec`, the states of the keyed types in `keys` are the
+            // instances of their keys, see `#[onchain(keyed)]`
+            pub fn _exec_instances<T: ckboots::OnChain>(
+                &self,
+                id: &str,
+                user_input: T,
+                keys: Vec<(&'static str, Vec<u8>)>,
+            ) -> Result<ContractResult, ckboots::StateError>
             {
                 let user_input = user_input._to_bytes();
                 #(#contract_exec_branches)*
-                panic!("could not match any contract id for {:?}", id)
+                Err(ckboots::StateError::UnknownContract(id.to_string()))
+            }
+
+            // The state held by the manager encoded with the current version
+            // of its type, which is migrated if it is written by an older one
+            fn _get_state(&self, id: &'static str, key: &[u8]) -> Result<Vec<u8>, ckboots::StateError> {
+                #(if <#types as ckboots::OnChain>::_id() == Some(id) {
+                    let state = self._manager.get_instance::<#types>(key)?;
+                    return Ok(<#types as ckboots::OnChain>::_to_bytes(&state));
+                })*
+                self._manager
+                    .get_by_key(id, key)
+                    .map(|data| data.to_vec())
+                    .ok_or(ckboots::StateError::Unregistered(id))
+            }
+
+            // Upgrade the state cell in place with the generated migration
+            // contract of its type, see `#[onchain(migrate)]`
+            pub fn _migrate(&self, id: &'static str) -> Result<ContractResult, ckboots::StateError> {
+                self._migrate_instance(id, vec![])
+            }
+
+            // Like `_migrate`, the instance `key` of a keyed type
+            pub fn _migrate_instance(&self, id: &'static str, key: Vec<u8>) -> Result<ContractResult, ckboots::StateError> {
+                #(if <#types as ckboots::OnChain>::_id() == Some(id) {
+                    if let Some(contract_id) = <#types as ckboots::OnChain>::_migration_id() {
+                        let output = self._get_state(id, &key)?;
+                        let data = self._manager.get_by_key(id, &key).ok_or(ckboots::StateError::Unregistered(id))?;
+                        return Ok(ContractResult {
+                            deps: vec![],
+                            input_output_data: vec![(id, data.to_vec(), output)],
+                            contract_id,
+                            user_input: None,
+                            versions: vec![(id, <#types as ckboots::OnChain>::_version())],
+                            keys: vec![(id, key)],
+                        });
+                    }
+                })*
+                Err(ckboots::StateError::NoMigration(id))
+            }
+
+            // The ids, versions and encoded default values of the registered types,
+            // which the genesis tx puts on chain. Types without an id are
+            // only shipped to the contracts.
+            pub fn _default_states() -> Vec<(&'static str, u8, Vec<u8>)> {
+                #default_states
             }
+
+            #cell_to_json
+
+            #molecule_decls
         }
 
         #generate_contracts
@@ -115,45 +301,68 @@ pub fn get_app_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
 }
 
 struct Container {
-    pub types: Vec<Path>,
+    pub types: Vec<Type>,
     pub contracts: Vec<Path>,
 }
 
 impl Container {
+    // `#[app(contracts(...), types(...))]`. The types are parsed as types
+    // rather than paths so that generic ones like `Pair<u8, Item>` are allowed.
     pub fn from_attrs(attrs: Vec<Attribute>) -> Self {
         let mut contracts: Vec<Path> = vec![];
-        let mut types: Vec<Path> = vec![];
-
+        let mut types: Vec<Type> = vec![];
         attrs
             .iter()
-            .flat_map(|attr| {
-                if !attr.path.is_ident("app") {
-                    return Err(());
-                }
-                match attr.parse_meta() {
-                    Ok(List(meta)) => Ok(meta.nested.into_iter().collect::<Vec<_>>()),
-                    _ => Err(()),
-                }
-            })
-            .flatten()
-            .for_each(|meta| match meta {
-                NestedMeta::Meta(List(m)) => {
-                    m.nested.into_iter().for_each(|e| match e {
-                        NestedMeta::Meta(entry) => match entry {
-                            syn::Meta::Path(p) => {
-                                if m.path.is_ident("contracts") {
-                                    contracts.push(p);
-                                } else if m.path.is_ident("types") {
-                                    types.push(p);
-                                }
-                            }
-                            _ => panic!("required: #[app(contracts(...), types(...))]"),
-                        },
-                        NestedMeta::Lit(_) => todo!(),
+            .filter(|attr| attr.path.is_ident("app"))
+            .for_each(|attr| {
+                let lists = attr
+                    .parse_args_with(Punctuated::<AppList, Token![,]>::parse_terminated)
+                    .unwrap_or_else(|e| {
+                        panic!("required: #[app(contracts(...), types(...))], {}", e)
                     });
-                }
-                _ => todo!(),
+                lists.into_iter().for_each(|list| match list {
+                    AppList::Contracts(c) => contracts.extend(c),
+                    AppList::Types(t) => types.extend(t),
+                });
             });
         Container { types, contracts }
     }
 }
+
+enum AppList {
+    Contracts(Vec<Path>),
+    Types(Vec<Type>),
+}
+
+impl Parse for AppList {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        let content;
+        parenthesized!(content in input);
+        if name == "contracts" {
+            let types = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+            let paths = types
+                .into_iter()
+                .map(contract_path)
+                .collect::<syn::Result<_>>()?;
+            Ok(AppList::Contracts(paths))
+        } else if name == "types" {
+            let types = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+            Ok(AppList::Types(types.into_iter().collect()))
+        } else {
+            Err(syn::Error::new(
+                name.span(),
+                "expected `contracts` or `types`",
+            ))
+        }
+    }
+}
+
+// `$c:ty` of `create_app!` arrives as an invisible group around the path
+fn contract_path(ty: Type) -> syn::Result<Path> {
+    match ty {
+        Type::Group(g) => contract_path(*g.elem),
+        Type::Path(p) if p.qself.is_none() => Ok(p.path),
+        ty => Err(syn::Error::new_spanned(ty, "a contract should be a path")),
+    }
+}
@@ -1,6 +1,11 @@
 use proc_macro2::Ident;
 use quote::quote;
-use syn::{AttributeArgs, ItemFn, LitStr, ReturnType, Signature, Type, TypePath};
+use syn::{
+    AttributeArgs, GenericArgument, ItemFn, LitStr, PathArguments, ReturnType, Signature, Type,
+    TypePath,
+};
+
+use crate::schema::schema_type_name;
 
 pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2::TokenStream {
     let descriptor = ContractDescriptor::from_ast(attr, func);
@@ -34,8 +39,8 @@ pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2:
                 };
                 let ty = arg.get_type_path();
                 let decodes = quote! {
-                    let _bytes = bytes.get(#idx).unwrap();
-                    let (#ident, _) = ckboots::consume_and_decode::<#ty>(_bytes).unwrap();
+                    let _bytes = bytes.get(#idx).expect("missing the bytes of an argument");
+                    let #ident = ckboots::decode::<#ty>(_bytes)?;
                 };
                 prev_idents.push(idents);
                 prev_decodes.push(decodes);
@@ -43,11 +48,11 @@ pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2:
             },
         );
         quote! {
-                pub fn new(bytes: Vec<&[u8]>) -> Self {
+                pub fn new(bytes: Vec<&[u8]>) -> Result<Self, ckboots::DecodeError> {
                     #(#decodes)*
-                    Self {
+                    Ok(Self {
                         #(#idents)*
-                    }
+                    })
                 }
         }
     };
@@ -60,17 +65,19 @@ pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2:
                 let _input_id = <#p as ckboots::OnChain>::_id().unwrap();
                 let _input_data = <#p as ckboots::OnChain>::_to_bytes(&self.#ident);
                 _inputs.push((_input_id, _input_data));
+                _versions.push((_input_id, <#p as ckboots::OnChain>::_version()));
                 let #ident = &mut self.#ident;
             },
             SigArg::UnmutRef(p) => quote! {
                 let _dep_id = <#p as ckboots::OnChain>::_id().unwrap();
                 _dep_ids.push(_dep_id);
-                let #ident = &self.#ident.unwrap();
+                let #ident = &self.#ident;
             },
             SigArg::Value(p) => {
                 quote! {
                     let _user_input_data = <#p as ckboots::OnChain>::_to_bytes(&self.#ident);
                     _user_input = Some(_user_input_data);
+                    let #ident = self.#ident;
                 }
             }
         });
@@ -87,7 +94,8 @@ pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2:
             pub fn run(mut self) -> ckboots::ContractResult {
                 let mut _inputs: Vec<(&'static str, Vec<u8>)> = vec![];
                 let mut _dep_ids: Vec<&'static str> = vec![];
-                let _user_input: Option<Vec<u8>> = None;
+                let mut _versions: Vec<(&'static str, u8)> = vec![];
+                let mut _user_input: Option<Vec<u8>> = None;
 
                 #(#init_branches)*
 
@@ -106,6 +114,8 @@ pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2:
                     user_input: _user_input,
                     contract_id: #contract_id,
                     input_output_data: _input_output_data,
+                    versions: _versions,
+                    keys: vec![],
                 }
             }
         }
@@ -146,13 +156,47 @@ pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2:
         });
         let user_input_token = if let Some((ident, ty)) = user_input {
             quote! {
-                Some((#ident, #ty))
+                Some((#ident.to_string(), #ty.to_string()))
             }
         } else {
             quote! {
                 None
             }
         };
+        let schema_func = {
+            let arg_schema = |ident: &Ident, t: &TypePath| {
+                let name = ident.to_string();
+                let ty = schema_type_name(&Type::Path(t.clone()));
+                quote! {
+                    ckboots::generators::contract::ArgSchema {
+                        name: #name,
+                        id: <#t as ckboots::OnChain>::_id(),
+                        ty: #ty,
+                    }
+                }
+            };
+            let mut mutates = vec![];
+            let mut deps = vec![];
+            let mut user_input = quote! {None};
+            descriptor.args.iter().for_each(|(ident, arg)| match arg {
+                SigArg::MutRef(t) => mutates.push(arg_schema(ident, t)),
+                SigArg::UnmutRef(t) => deps.push(arg_schema(ident, t)),
+                SigArg::Value(t) => {
+                    let schema = arg_schema(ident, t);
+                    user_input = quote! {Some(#schema)};
+                }
+            });
+            quote! {
+                pub fn _schema() -> ckboots::generators::contract::ContractSchema {
+                    ckboots::generators::contract::ContractSchema {
+                        id: #contract_id,
+                        mutates: vec![#(#mutates),*],
+                        deps: vec![#(#deps),*],
+                        user_input: #user_input,
+                    }
+                }
+            }
+        };
         let cell_deps_idx = 0..cell_deps.len();
         let updates_idx = 0..updates.len();
         let func_block = func.block.as_ref();
@@ -203,6 +247,8 @@ pub fn build_contract_entry(attr: &AttributeArgs, func: &ItemFn) -> proc_macro2:
                     _code,
                 )
             }
+
+            #schema_func
         }
     } else {
         quote! {}
@@ -248,8 +294,8 @@ struct Attrs<'a> {
     pub id: &'a LitStr,
 }
 
-fn parse_signature(sig: &Signature) -> Vec<(&Ident, SigArg)> {
-    check_signature(&sig);
+fn parse_signature(sig: &Signature) -> Vec<(&Ident, SigArg<'_>)> {
+    check_signature(sig);
 
     let inputs = &sig.inputs;
     let mut result: Vec<(&Ident, SigArg)> = Vec::with_capacity(inputs.len());
@@ -286,7 +332,7 @@ fn check_signature(sig: &Signature) {
         panic!("async is not allowed in the contract functions")
     }
 
-    if sig.generics.params.len() > 0 {
+    if !sig.generics.params.is_empty() {
         unimplemented!("generic is not supported in the contract functions");
     }
 
@@ -299,7 +345,7 @@ fn check_signature(sig: &Signature) {
     }
 }
 
-fn parse_attrs(attrs: &AttributeArgs) -> Attrs {
+fn parse_attrs(attrs: &AttributeArgs) -> Attrs<'_> {
     let mut entry: Option<&Ident> = None;
     let mut id: Option<&LitStr> = None;
     attrs.iter().for_each(|meta| match meta {
@@ -343,7 +389,7 @@ impl<'a> SigArg<'a> {
     }
 }
 
-fn parse_sig_type(ty: &Type) -> SigArg {
+fn parse_sig_type(ty: &Type) -> SigArg<'_> {
     match ty {
         Type::Path(p) => SigArg::Value(p),
         Type::Reference(r) => {
@@ -363,7 +409,41 @@ fn parse_sig_type(ty: &Type) -> SigArg {
 }
 
 fn turn_type_path_into_string(tp: &TypePath) -> String {
-    let ts = quote! {#tp};
-    let s: String = ts.to_string();
-    s
+    type_in_types_crate(&Type::Path(tp.clone()))
+}
+
+// The type as it is named in the generated contracts, which glob import the
+// `types` crate: `crate::game::Pair<u8, item::Item>` becomes `Pair<u8, Item>`
+pub(crate) fn type_in_types_crate(ty: &Type) -> String {
+    let mut ty = ty.clone();
+    strip_module_paths(&mut ty);
+    quote! {#ty}.to_string()
+}
+
+fn strip_module_paths(ty: &mut Type) {
+    match ty {
+        // `$t:ty` of `create_app!` arrives as an invisible group
+        Type::Group(g) => {
+            strip_module_paths(&mut g.elem);
+            *ty = (*g.elem).clone();
+        }
+        Type::Paren(p) => strip_module_paths(&mut p.elem),
+        Type::Path(p) if p.qself.is_none() => {
+            let mut last = p.path.segments.pop().expect("empty type path").into_value();
+            if let PathArguments::AngleBracketed(args) = &mut last.arguments {
+                args.args.iter_mut().for_each(|arg| {
+                    if let GenericArgument::Type(t) = arg {
+                        strip_module_paths(t);
+                    }
+                });
+            }
+            p.path.leading_colon = None;
+            p.path.segments.clear();
+            p.path.segments.push(last);
+        }
+        Type::Array(a) => strip_module_paths(&mut a.elem),
+        Type::Tuple(t) => t.elems.iter_mut().for_each(strip_module_paths),
+        Type::Reference(r) => strip_module_paths(&mut r.elem),
+        _ => {}
+    }
 }
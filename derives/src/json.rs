@@ -0,0 +1,182 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, DeriveInput, Fields, GenericParam, Generics, Member};
+
+use crate::on_chain::{field_members, member_pat, member_var};
+
+// `OnChainJson`, `Serialize` and `Deserialize` of the `serde` feature. The
+// serde impls go through the JSON form, see `OnChainJson`.
+pub fn get_json_impl_block(input: &DeriveInput) -> TokenStream {
+    let ident = &input.ident;
+    let generics = add_json_bounds(input.generics.clone());
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let mut de_generics = generics.clone();
+    de_generics.params.insert(0, parse_quote!('de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+
+    let (to_json, from_json) = match &input.data {
+        syn::Data::Struct(data) => {
+            let members = field_members(&data.fields);
+            let pats = members.iter().map(|m| member_pat(m, "f"));
+            let to_json = fields_to_json(&data.fields);
+            let from_json = fields_from_json(&data.fields);
+            let vars = members.iter().map(|m| member_var(m, "f"));
+            (
+                quote! {
+                    let Self { #(#pats),* } = self;
+                    #to_json
+                },
+                quote! {
+                    #from_json
+                    Ok(Self { #(#members: #vars),* })
+                },
+            )
+        }
+        syn::Data::Enum(data) => {
+            let to_json_branch = data.variants.iter().map(|v| {
+                let variant = &v.ident;
+                let name = variant.to_string();
+                let members = field_members(&v.fields);
+                let pats = members.iter().map(|m| member_pat(m, "f"));
+                // Unit variants are their names, like serde does
+                let value = if let Fields::Unit = v.fields {
+                    quote! {ckboots::serde_json::Value::String(String::from(#name))}
+                } else {
+                    let to_json = fields_to_json(&v.fields);
+                    quote! {
+                        let mut object = ckboots::serde_json::Map::new();
+                        object.insert(String::from(#name), { #to_json });
+                        ckboots::serde_json::Value::Object(object)
+                    }
+                };
+                quote! {
+                    Self::#variant { #(#pats),* } => { #value }
+                }
+            });
+            let from_json_branch = data.variants.iter().map(|v| {
+                let variant = &v.ident;
+                let name = variant.to_string();
+                let members = field_members(&v.fields);
+                let vars = members.iter().map(|m| member_var(m, "f"));
+                let from_json = fields_from_json(&v.fields);
+                quote! {
+                    #name => {
+                        #from_json
+                        Ok(Self::#variant { #(#members: #vars),* })
+                    }
+                }
+            });
+            (
+                quote! {
+                    match self {
+                        #(#to_json_branch,)*
+                    }
+                },
+                quote! {
+                    let (name, value) = ckboots::json_variant(value)?;
+                    match name {
+                        #(#from_json_branch,)*
+                        name => Err(ckboots::JsonError::UnknownVariant(String::from(name))),
+                    }
+                },
+            )
+        }
+        syn::Data::Union(_) => todo!(),
+    };
+
+    quote! {
+        impl #impl_generics ckboots::OnChainJson for #ident #type_generics #where_clause {
+            fn _to_json(&self) -> ckboots::serde_json::Value {
+                #to_json
+            }
+
+            #[allow(unused_variables)]
+            fn _from_json(value: &ckboots::serde_json::Value) -> Result<Self, ckboots::JsonError> {
+                #from_json
+            }
+        }
+
+        impl #impl_generics ckboots::serde::Serialize for #ident #type_generics #where_clause {
+            fn serialize<S: ckboots::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let value = <Self as ckboots::OnChainJson>::_to_json(self);
+                ckboots::serde::Serialize::serialize(&value, serializer)
+            }
+        }
+
+        impl #de_impl_generics ckboots::serde::Deserialize<'de> for #ident #type_generics #where_clause {
+            fn deserialize<D: ckboots::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = <ckboots::serde_json::Value as ckboots::serde::Deserialize>::deserialize(deserializer)?;
+                <Self as ckboots::OnChainJson>::_from_json(&value)
+                    .map_err(<D::Error as ckboots::serde::de::Error>::custom)
+            }
+        }
+    }
+}
+
+fn add_json_bounds(mut generics: Generics) -> Generics {
+    generics.params.iter_mut().for_each(|param| {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(ckboots::OnChainJson));
+        }
+    });
+    generics
+}
+
+// Named fields are an object, a single unnamed field is its own value like a
+// serde newtype, and other unnamed fields are an array. The fields are bound
+// to the vars of their members prefixed with `f`, which cannot shadow the
+// locals.
+fn fields_to_json(fields: &Fields) -> TokenStream {
+    let members = field_members(fields);
+    let vars = members.iter().map(|m| member_var(m, "f"));
+    match fields {
+        Fields::Named(_) => {
+            let names = members.iter().map(|m| match m {
+                Member::Named(ident) => ident.to_string(),
+                Member::Unnamed(_) => unreachable!(),
+            });
+            quote! {
+                let mut object = ckboots::serde_json::Map::new();
+                #(object.insert(String::from(#names), ckboots::OnChainJson::_to_json(#vars));)*
+                ckboots::serde_json::Value::Object(object)
+            }
+        }
+        Fields::Unnamed(_) if members.len() == 1 => quote! {
+            #(ckboots::OnChainJson::_to_json(#vars))*
+        },
+        Fields::Unnamed(_) => quote! {
+            ckboots::serde_json::Value::Array(vec![#(ckboots::OnChainJson::_to_json(#vars)),*])
+        },
+        Fields::Unit => quote! {ckboots::serde_json::Value::Null},
+    }
+}
+
+// Bind the vars of the members, prefixed with `f`, from `value`
+fn fields_from_json(fields: &Fields) -> TokenStream {
+    let members = field_members(fields);
+    let vars = members.iter().map(|m| member_var(m, "f"));
+    let types = fields.iter().map(|f| &f.ty);
+    match fields {
+        Fields::Named(_) => {
+            let names = members.iter().map(|m| match m {
+                Member::Named(ident) => ident.to_string(),
+                Member::Unnamed(_) => unreachable!(),
+            });
+            quote! {
+                #(let #vars = <#types as ckboots::OnChainJson>::_from_json(ckboots::json_field(value, #names)?)?;)*
+            }
+        }
+        Fields::Unnamed(_) if members.len() == 1 => quote! {
+            #(let #vars = <#types as ckboots::OnChainJson>::_from_json(value)?;)*
+        },
+        Fields::Unnamed(_) => {
+            let len = members.len();
+            let indexes = 0..len;
+            quote! {
+                let items = ckboots::json_items(value, #len)?;
+                #(let #vars = <#types as ckboots::OnChainJson>::_from_json(&items[#indexes])?;)*
+            }
+        }
+        Fields::Unit => quote! {},
+    }
+}
@@ -1,11 +1,18 @@
 mod app;
 mod contract;
 mod error;
+mod json;
+mod molecule;
 mod on_chain;
+mod roundtrip;
+mod schema;
 
 use app::get_app_impl_block;
 use contract::build_contract_entry;
-use on_chain::get_on_chain_impl_block;
+use json::get_json_impl_block;
+use on_chain::{add_on_chain_bounds, get_on_chain_impl_block, has_migrate, is_molecule};
+use roundtrip::{get_arbitrary_impl_block, get_roundtrip_test};
+use schema::get_type_schema_fn;
 
 use proc_macro::TokenStream;
 use quote::quote;
@@ -18,30 +25,82 @@ pub fn derive_on_chain(input: TokenStream) -> TokenStream {
 
     let on_chain = get_on_chain_impl_block(input.clone());
 
+    // Before the attrs are cleaned by the generator
+    let roundtrip = if cfg!(feature = "roundtrip") {
+        let arbitrary = get_arbitrary_impl_block(&input);
+        let test = get_roundtrip_test(&input);
+        quote! {
+            #arbitrary
+            #test
+        }
+    } else {
+        quote! {}
+    };
+
     let generator = if cfg!(feature = "contract-generator") {
+        let migration = if has_migrate(&input.attrs) {
+            quote! {
+                fn __get_migration_code_str__() -> Option<&'static str> {
+                    Some(Self::__MIGRATION_CODE_STR__)
+                }
+            }
+        } else {
+            quote! {}
+        };
+        let molecule_encoding = is_molecule(&input.attrs);
+        let molecule = if molecule_encoding {
+            quote! {
+                fn __get_molecule_decls__(decls: &mut Vec<String>) {
+                    <Self as ckboots::Molecule>::_molecule_decls(decls)
+                }
+
+                fn __is_molecule__() -> bool {
+                    true
+                }
+            }
+        } else {
+            quote! {}
+        };
+        let schema = get_type_schema_fn(&input);
         clean_attr(&mut input);
         let struct_code = quote! {#input};
         let impl_code = on_chain.clone();
         let code = quote! {
-            use crate as ckboots;
             #struct_code
             #impl_code
         }
         .to_string();
+        // The schema and the Molecule decls need the bounded impls
+        let generics = add_on_chain_bounds(input.generics.clone(), molecule_encoding);
+        let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
         quote! {
-            impl ckboots::__CodeStr__ for #ident {
+            impl #impl_generics ckboots::__CodeStr__ for #ident #type_generics #where_clause {
                 fn __get_code_str__() -> &'static str {
                     #code
                 }
+
+                #migration
+
+                #molecule
+
+                #schema
             }
         }
     } else {
         quote! {}
     };
 
+    let json = if cfg!(feature = "serde") {
+        get_json_impl_block(&input)
+    } else {
+        quote! {}
+    };
+
     quote! {
         #on_chain
         #generator
+        #json
+        #roundtrip
     }
     .into()
 }
@@ -70,6 +129,39 @@ pub fn contract(attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
+// Put on `impl Migrate for T`, so that the migration is shipped to the
+// generated contracts along with `T`, which derives `#[onchain(migrate)]`
+#[proc_macro_attribute]
+pub fn migration(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as Item);
+    if let Item::Impl(block) = &item {
+        if block.trait_.is_none() {
+            panic!("#[migration] should be used on `impl Migrate for T`")
+        }
+        let generator = if cfg!(feature = "contract-generator") {
+            let code = quote! {#item}.to_string();
+            let self_ty = &block.self_ty;
+            let (impl_generics, _, where_clause) = block.generics.split_for_impl();
+            quote! {
+                impl #impl_generics #self_ty #where_clause {
+                    #[doc(hidden)]
+                    pub const __MIGRATION_CODE_STR__: &'static str = #code;
+                }
+            }
+        } else {
+            quote! {}
+        };
+        quote! {
+            #item
+
+            #generator
+        }
+        .into()
+    } else {
+        panic!("#[migration] should be used on `impl Migrate for T`")
+    }
+}
+
 #[proc_macro_derive(CkbError)]
 pub fn derive_error(_: TokenStream) -> TokenStream {
     todo!()
@@ -79,7 +171,10 @@ fn clean_attr(input: &mut DeriveInput) {
     input.attrs = vec![];
     match &mut input.data {
         syn::Data::Struct(data) => data.fields.iter_mut().for_each(|f| f.attrs = vec![]),
-        syn::Data::Enum(data) => data.variants.iter_mut().for_each(|f| f.attrs = vec![]),
+        syn::Data::Enum(data) => data.variants.iter_mut().for_each(|v| {
+            v.attrs = vec![];
+            v.fields.iter_mut().for_each(|f| f.attrs = vec![]);
+        }),
         syn::Data::Union(_) => todo!(),
     };
 }
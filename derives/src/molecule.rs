@@ -0,0 +1,285 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DataEnum, DataStruct, DeriveInput, Member, Type};
+
+use crate::on_chain::{field_members, member_pat, member_var, variant_tags};
+
+// The `OnChain` fns of `#[onchain(encoding = "molecule")]`, whose bytes are
+// the Molecule ones. A struct has its size and the others tell their length
+// with `delimited_len`, an expression of `bytes`, so there is no length prefix.
+pub fn molecule_encoding_fns(delimited_len: TokenStream) -> TokenStream {
+    quote! {
+        fn _capacity(&self) -> u64 {
+            ckboots::Molecule::_to_molecule(self).len() as u64
+        }
+
+        fn _to_bytes(&self) -> Vec<u8> {
+            ckboots::Molecule::_to_molecule(self)
+        }
+
+        fn _from_bytes(bytes: &[u8]) -> Result<Self, ckboots::DecodeError> {
+            <Self as ckboots::Molecule>::_from_molecule(bytes)
+        }
+
+        fn _fixed_size() -> Option<u64> {
+            <Self as ckboots::Molecule>::_molecule_size().map(|size| size as u64)
+        }
+
+        fn _delimited_len(bytes: &[u8]) -> Result<Option<u64>, ckboots::DecodeError> {
+            let len: usize = { #delimited_len };
+            Ok(Some(len as u64))
+        }
+    }
+}
+
+// The item id and the length of the item, which is a struct or a table
+pub fn molecule_enum_len(data: &DataEnum) -> TokenStream {
+    let tags = variant_tags(data)
+        .into_iter()
+        .map(|t| t as u32)
+        .collect::<Vec<_>>();
+    let sizes = data.variants.iter().map(|v| {
+        let types = v.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+        let members = field_members(&v.fields);
+        FieldList {
+            members: &members,
+            types: &types,
+        }
+        .size()
+    });
+    quote! {
+        let (id, item) = ckboots::molecule_union_item(bytes)?;
+        let size: Option<usize> = match id {
+            #(#tags => #sizes,)*
+            id => return Err(ckboots::DecodeError::UnknownUnionId(id)),
+        };
+        4 + ckboots::molecule_item_len(item, size)?
+    }
+}
+
+pub fn get_molecule_struct_impl(input: &DeriveInput, data: &DataStruct) -> TokenStream {
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let members = field_members(&data.fields);
+    let types = data.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let vars = members
+        .iter()
+        .map(|m| member_var(m, ""))
+        .collect::<Vec<_>>();
+    let pats = members.iter().map(|m| member_pat(m, ""));
+    let name = molecule_name(input);
+    let fields = FieldList {
+        members: &members,
+        types: &types,
+    };
+    let size = fields.size();
+    let to_molecule = fields.encode();
+    let from_molecule = fields.decode();
+    let decls = fields.decls(quote! {&Self::_molecule_name()});
+
+    quote! {
+        impl #impl_generics ckboots::Molecule for #ident #type_generics #where_clause {
+            fn _to_molecule(&self) -> Vec<u8> {
+                let Self { #(#pats),* } = self;
+                #to_molecule
+            }
+
+            fn _from_molecule(bytes: &[u8]) -> Result<Self, ckboots::DecodeError> {
+                #from_molecule
+                Ok(Self { #(#members: #vars),* })
+            }
+
+            fn _molecule_size() -> Option<usize> {
+                #size
+            }
+
+            fn _molecule_name() -> String {
+                #name
+            }
+
+            fn _molecule_decls(decls: &mut Vec<String>) {
+                #decls
+            }
+        }
+    }
+}
+
+// A union whose items are the variants, named like `ActionMove`. The item ids
+// are the tags.
+pub fn get_molecule_enum_impl(input: &DeriveInput, data: &DataEnum) -> TokenStream {
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let name = molecule_name(input);
+    let tags = variant_tags(data)
+        .into_iter()
+        .map(|t| t as u32)
+        .collect::<Vec<_>>();
+    let variants = data
+        .variants
+        .iter()
+        .map(|v| {
+            let members = field_members(&v.fields);
+            let types = v.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+            (&v.ident, members, types)
+        })
+        .collect::<Vec<_>>();
+
+    let to_molecule_branch = variants
+        .iter()
+        .zip(&tags)
+        .map(|((v, members, types), tag)| {
+            let pats = members.iter().map(|m| member_pat(m, ""));
+            let to_molecule = FieldList { members, types }.encode();
+            quote! {
+                Self::#v { #(#pats),* } => {
+                    let item = { #to_molecule };
+                    ckboots::molecule_union(#tag, item)
+                }
+            }
+        });
+    let from_molecule_branch = variants
+        .iter()
+        .zip(&tags)
+        .map(|((v, members, types), tag)| {
+            let vars = members.iter().map(|m| member_var(m, ""));
+            let from_molecule = FieldList { members, types }.decode();
+            quote! {
+                #tag => {
+                    let bytes = item;
+                    #from_molecule
+                    Ok(Self::#v { #(#members: #vars),* })
+                }
+            }
+        });
+    let variant_decls = variants.iter().map(|(v, members, types)| {
+        let variant_name = v.to_string();
+        FieldList { members, types }
+            .decls(quote! {&format!("{}{}", Self::_molecule_name(), #variant_name)})
+    });
+    let variant_names = variants.iter().map(|(v, _, _)| v.to_string());
+
+    quote! {
+        impl #impl_generics ckboots::Molecule for #ident #type_generics #where_clause {
+            fn _to_molecule(&self) -> Vec<u8> {
+                match self {
+                    #(#to_molecule_branch,)*
+                }
+            }
+
+            fn _from_molecule(bytes: &[u8]) -> Result<Self, ckboots::DecodeError> {
+                let (id, item) = ckboots::molecule_union_item(bytes)?;
+                match id {
+                    #(#from_molecule_branch,)*
+                    id => Err(ckboots::DecodeError::UnknownUnionId(id)),
+                }
+            }
+
+            fn _molecule_size() -> Option<usize> {
+                None
+            }
+
+            fn _molecule_name() -> String {
+                #name
+            }
+
+            fn _molecule_decls(decls: &mut Vec<String>) {
+                #({ #variant_decls })*
+                let mut decl = format!("union {} {{\n", Self::_molecule_name());
+                #(decl.push_str(&format!("    {}{}: {},\n", Self::_molecule_name(), #variant_names, #tags));)*
+                decl.push('}');
+                ckboots::molecule_declare(decls, decl);
+            }
+        }
+    }
+}
+
+// The name of the type followed by the ones of its type parameters, like
+// `PairByteUint32`
+fn molecule_name(input: &DeriveInput) -> TokenStream {
+    let ident = input.ident.to_string();
+    let params = input.generics.type_params().map(|p| &p.ident);
+    quote! {
+        let mut name = String::from(#ident);
+        #(name.push_str(&ckboots::molecule_compose(&<#params as ckboots::Molecule>::_molecule_name(), ""));)*
+        name
+    }
+}
+
+// The fields of a struct or a variant, which is a Molecule struct if they are
+// all fixed-size, or a table otherwise
+struct FieldList<'a> {
+    members: &'a [Member],
+    types: &'a [&'a Type],
+}
+
+impl<'a> FieldList<'a> {
+    // `Some` for a Molecule struct
+    fn size(&self) -> TokenStream {
+        let types = self.types;
+        quote! {
+            ckboots::molecule_struct_size(&[
+                #(<#types as ckboots::Molecule>::_molecule_size()),*
+            ])
+        }
+    }
+
+    // The fields are bound to the vars of their members
+    fn encode(&self) -> TokenStream {
+        let types = self.types;
+        let vars = self.members.iter().map(|m| member_var(m, ""));
+        let size = self.size();
+        quote! {
+            let fields: Vec<Vec<u8>> = vec![
+                #(<#types as ckboots::Molecule>::_to_molecule(#vars)),*
+            ];
+            let size: Option<usize> = #size;
+            if size.is_some() {
+                fields.concat()
+            } else {
+                ckboots::molecule_table(fields)
+            }
+        }
+    }
+
+    // Bind the vars of the members from `bytes`
+    fn decode(&self) -> TokenStream {
+        let types = self.types;
+        let count = types.len();
+        let vars = self.members.iter().map(|m| member_var(m, ""));
+        let size = self.size();
+        quote! {
+            let size: Option<usize> = #size;
+            let fields = if size.is_some() {
+                let sizes: Vec<usize> = vec![
+                    #(<#types as ckboots::Molecule>::_molecule_size().unwrap_or(0)),*
+                ];
+                ckboots::molecule_struct_fields(bytes, &sizes)?
+            } else {
+                ckboots::molecule_table_items(bytes, Some(#count))?
+            };
+            #[allow(unused_mut, unused_variables)]
+            let mut fields = fields.into_iter();
+            #(let #vars = <#types as ckboots::Molecule>::_from_molecule(
+                fields.next().ok_or(ckboots::DecodeError::Truncated)?,
+            )?;)*
+        }
+    }
+
+    fn decls(&self, name: TokenStream) -> TokenStream {
+        let types = self.types;
+        let names = self.members.iter().map(|m| match m {
+            Member::Named(ident) => ident.to_string(),
+            Member::Unnamed(index) => format!("f{}", index.index),
+        });
+        let size = self.size();
+        quote! {
+            #(<#types as ckboots::Molecule>::_molecule_decls(decls);)*
+            let fields: Vec<(String, String)> = vec![
+                #((String::from(#names), <#types as ckboots::Molecule>::_molecule_name())),*
+            ];
+            let size: Option<usize> = #size;
+            let decl = ckboots::molecule_fields_decl(#name, &fields, size.is_some());
+            ckboots::molecule_declare(decls, decl);
+        }
+    }
+}
@@ -1,18 +1,34 @@
+use std::collections::BTreeMap;
+
 use proc_macro2::Group;
+use proc_macro2::Ident;
 use proc_macro2::Span;
 use proc_macro2::TokenStream;
 use proc_macro2::TokenTree;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::parse;
 use syn::parse::Parse;
-use syn::LitStr;
+use syn::parse_quote;
 use syn::Meta::List;
 use syn::Meta::NameValue;
 use syn::Meta::Path;
-use syn::{DataEnum, DataStruct, DeriveInput, NestedMeta};
+use syn::{
+    Attribute, DataEnum, DataStruct, DeriveInput, Field, Fields, Generics, Index, Member,
+    NestedMeta,
+};
+use syn::{LitInt, LitStr};
+
+use crate::molecule::{
+    get_molecule_enum_impl, get_molecule_struct_impl, molecule_encoding_fns, molecule_enum_len,
+};
 
 pub fn get_on_chain_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
     let mut id: Option<LitStr> = None;
+    let mut version: Option<LitInt> = None;
+    let mut migrate = false;
+    let mut keyed = false;
+    let mut owned = false;
+    let mut molecule = false;
     input
         .attrs
         .iter()
@@ -34,20 +50,186 @@ pub fn get_on_chain_impl_block(input: DeriveInput) -> proc_macro2::TokenStream {
                     _ => todo!(),
                 }
             }
+            NestedMeta::Meta(NameValue(m)) if m.path.is_ident("version") => match m.lit {
+                syn::Lit::Int(lit_int) => {
+                    let v = lit_int
+                        .base10_parse::<u8>()
+                        .unwrap_or_else(|e| panic!("version should be a u8: {}", e));
+                    if v == 0 {
+                        panic!("version starts from 1");
+                    }
+                    version = Some(lit_int);
+                }
+                _ => panic!("version should be an integer, like #[onchain(version = 2)]"),
+            },
+            NestedMeta::Meta(Path(p)) if p.is_ident("migrate") => migrate = true,
+            NestedMeta::Meta(Path(p)) if p.is_ident("keyed") => keyed = true,
+            NestedMeta::Meta(Path(p)) if p.is_ident("owned") => owned = true,
+            // See `get_roundtrip_test`
+            NestedMeta::Meta(Path(p)) if p.is_ident("test_roundtrip") => {}
+            NestedMeta::Meta(NameValue(m)) if m.path.is_ident("encoding") => match m.lit {
+                syn::Lit::Str(lit_str) if lit_str.value() == "molecule" => molecule = true,
+                syn::Lit::Str(lit_str) if lit_str.value() == "default" => molecule = false,
+                _ => panic!("the encoding should be \"default\" or \"molecule\""),
+            },
             _ => todo!(),
         });
-
+    if migrate && version.is_none() {
+        panic!("#[onchain(migrate)] needs the version, like #[onchain(version = 2, migrate)]");
+    }
+    if keyed && id.is_none() {
+        panic!("#[onchain(keyed)] needs the id, like #[onchain(id = \"hero\", keyed)]");
+    }
+    // The instances are told apart by their keys, not by their owners
+    if owned && !keyed {
+        panic!(
+            "#[onchain(owned)] needs keyed instances, like #[onchain(id = \"item\", keyed, owned)]"
+        );
+    }
+    let container_fns = container_fns(id.as_ref(), version.as_ref(), migrate, keyed, owned);
+
+    let mut input = input;
+    input.generics = add_on_chain_bounds(input.generics, molecule);
+    if !molecule {
+        return match &input.data {
+            syn::Data::Struct(data) => get_struct_impl_block(&input, data, container_fns, None),
+            syn::Data::Enum(data) => get_enum_impl_block(&input, data, container_fns, None),
+            syn::Data::Union(_) => todo!(),
+        };
+    }
     match &input.data {
-        syn::Data::Struct(data) => get_struct_impl_block(&input, &data, id),
-        syn::Data::Enum(data) => get_enum_impl_block(&input, &data, id),
+        syn::Data::Struct(data) => {
+            // A table starts with its length
+            let encoding_fns = Some(molecule_encoding_fns(quote! {
+                ckboots::molecule_item_len(bytes, None)?
+            }));
+            let on_chain = get_struct_impl_block(&input, data, container_fns, encoding_fns);
+            let molecule = get_molecule_struct_impl(&input, data);
+            quote! {
+                #on_chain
+                #molecule
+            }
+        }
+        syn::Data::Enum(data) => {
+            let encoding_fns = Some(molecule_encoding_fns(molecule_enum_len(data)));
+            let on_chain = get_enum_impl_block(&input, data, container_fns, encoding_fns);
+            let molecule = get_molecule_enum_impl(&input, data);
+            quote! {
+                #on_chain
+                #molecule
+            }
+        }
         syn::Data::Union(_) => todo!(),
     }
 }
 
+// Whether the type is `#[onchain(migrate)]`
+pub fn has_migrate(attrs: &[Attribute]) -> bool {
+    onchain_metas(attrs)
+        .iter()
+        .any(|meta| matches!(meta, NestedMeta::Meta(Path(p)) if p.is_ident("migrate")))
+}
+
+pub fn has_test_roundtrip(attrs: &[Attribute]) -> bool {
+    onchain_metas(attrs)
+        .iter()
+        .any(|meta| matches!(meta, NestedMeta::Meta(Path(p)) if p.is_ident("test_roundtrip")))
+}
+
+pub fn is_molecule(attrs: &[Attribute]) -> bool {
+    onchain_metas(attrs).iter().any(|meta| {
+        matches!(meta, NestedMeta::Meta(NameValue(m))
+            if m.path.is_ident("encoding")
+                && matches!(&m.lit, syn::Lit::Str(lit_str) if lit_str.value() == "molecule"))
+    })
+}
+
+// The `OnChain` fns set by the attributes of the type itself
+fn container_fns(
+    id: Option<&LitStr>,
+    version: Option<&LitInt>,
+    migrate: bool,
+    keyed: bool,
+    owned: bool,
+) -> TokenStream {
+    let id_tokens = if let Some(id) = id {
+        quote! {Some(#id)}
+    } else {
+        quote!(None)
+    };
+    let version_fn = version.map(|v| {
+        quote! {
+            fn _version() -> u8 {
+                #v
+            }
+        }
+    });
+    let migrate_fns = if migrate {
+        let migration_id = match id {
+            Some(id) => {
+                let migration_id = LitStr::new(&format!("migrate_{}", id.value()), id.span());
+                quote! {Some(#migration_id)}
+            }
+            None => quote! {None},
+        };
+        quote! {
+            fn _from_versioned_bytes(version: u8, bytes: &[u8]) -> Result<Self, ckboots::DecodeError> {
+                if version == Self::_version() {
+                    return ckboots::decode::<Self>(bytes);
+                }
+                if version > Self::_version() {
+                    return Err(ckboots::DecodeError::UnknownVersion(version));
+                }
+                let previous = <<Self as ckboots::Migrate>::Previous as ckboots::OnChain>::_from_versioned_bytes(version, bytes)?;
+                Ok(<Self as ckboots::Migrate>::_migrate(previous))
+            }
+
+            fn _migration_id() -> Option<&'static str> {
+                #migration_id
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let keyed_fn = if keyed {
+        quote! {
+            fn _keyed() -> bool {
+                true
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let owned_fn = if owned {
+        quote! {
+            fn _owned() -> bool {
+                true
+            }
+        }
+    } else {
+        quote! {}
+    };
+    quote! {
+        fn _id() -> Option<&'static str> {
+            #id_tokens
+        }
+
+        #version_fn
+
+        #keyed_fn
+
+        #owned_fn
+
+        #migrate_fns
+    }
+}
+
+// `encoding_fns` replaces the default encoding, see `molecule_encoding_fns`
 fn get_struct_impl_block(
     input: &DeriveInput,
     data: &DataStruct,
-    id: Option<LitStr>,
+    container_fns: TokenStream,
+    encoding_fns: Option<TokenStream>,
 ) -> proc_macro2::TokenStream {
     let ident = &input.ident;
     let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
@@ -56,91 +238,69 @@ fn get_struct_impl_block(
         prev.push(ty);
         prev
     });
-    let field_idents = data.fields.iter().fold(vec![], |mut prev, i| {
-        let ident = i.ident.as_ref().expect("unnamed field is not supported");
-        prev.push(ident);
-        prev
-    });
-
-    let id_tokens = if let Some(id) = id {
-        quote! {Some(#id)}
-    } else {
-        quote!(None)
-    };
+    let field_members = field_members(&data.fields);
+    let field_vars = field_members
+        .iter()
+        .map(|m| member_var(m, ""))
+        .collect::<Vec<_>>();
 
-    let defaults = data.fields.iter().map(|f| {
-        let ident = f.ident.as_ref().expect("");
-        let ty = &f.ty;
-        let mut tokenstream: Option<TokenStream> = None;
-        f.attrs
-            .iter()
-            .flat_map(|attr| {
-                if !attr.path.is_ident("onchain") {
-                    return Err(());
-                }
-                match attr.parse_meta() {
-                    Ok(List(meta)) => Ok(meta.nested.into_iter().collect::<Vec<_>>()),
-                    _ => Err(()),
-                }
-            })
-            .flatten()
-            .for_each(|meta| match meta {
-                NestedMeta::Meta(NameValue(nv)) => {
-                    if nv.path.is_ident("default") {
-                        let ts = lit_to_tokenstream(nv.lit);
-                        tokenstream = Some(ts);
-                    }
-                }
-                _ => panic!("#[onchain(default = <value>)]"),
-            });
-        if let Some(ts) = tokenstream {
-            quote! {
-                #ident: #ts,
-            }
-        } else {
-            quote! {
-                #ident: <#ty as ckboots::OnChain>::_default(),
-            }
+    let defaults = data.fields.iter().zip(&field_members).map(|(f, member)| {
+        let value = field_default(f);
+        quote! {
+            #member: #value,
         }
     });
 
-    quote! {
-        impl #impl_generics ckboots::OnChain for #ident #type_generics #where_clause{
+    // Struct expressions with braces work for tuple and unit structs too,
+    // like `Self { 0: _0 }`
+    let encoding_fns = encoding_fns.unwrap_or_else(|| {
+        quote! {
             fn _capacity(&self) -> u64 {
-                #(self.#field_idents._capacity())+*
+                let size = 0 #(+ self.#field_members._capacity())*;
+                if Self::_fixed_size().is_some() {
+                    size
+                } else {
+                    size + 8
+                }
             }
 
             fn _to_bytes(&self) -> Vec<u8> {
                 let mut result = Vec::with_capacity(self._capacity() as usize);
-                #(result.extend(<#field_types as ckboots::OnChain>::_to_bytes(&self.#field_idents));)*
-                if let Some(_) = #ident::_fixed_size() {
+                #(result.extend(<#field_types as ckboots::OnChain>::_to_bytes(&self.#field_members));)*
+                if let Some(_) = Self::_fixed_size() {
                     result
                 } else {
-                    let mut prefix: Vec<u8> = result.len().to_le_bytes().to_vec();
+                    let mut prefix: Vec<u8> = (result.len() as u64).to_le_bytes().to_vec();
                     prefix.extend(result);
                     prefix
                 }
             }
 
-            fn _from_bytes(bytes: &[u8]) -> Option<Self> {
+            fn _from_bytes(bytes: &[u8]) -> Result<Self, ckboots::DecodeError> {
                 let left = bytes;
-                #(let (#field_idents, left) = ckboots::consume_and_decode::<#field_types>(left)?;)*
-                Some(Self {
-                    #(#field_idents,)*
+                #(let (#field_vars, left) = ckboots::consume_and_decode::<#field_types>(left)?;)*
+                if !left.is_empty() {
+                    return Err(ckboots::DecodeError::TrailingBytes(left.len()));
+                }
+                Ok(Self {
+                    #(#field_members: #field_vars,)*
                 })
             }
 
             fn _fixed_size() -> Option<u64> {
-                let size = #(<#field_types as ckboots::OnChain>::_fixed_size()?)+*;
+                let size = 0 #(+ <#field_types as ckboots::OnChain>::_fixed_size()?)*;
                 Some(size)
             }
+        }
+    });
+    quote! {
+        impl #impl_generics ckboots::OnChain for #ident #type_generics #where_clause{
+            #encoding_fns
 
-            fn _id() -> Option<&'static str> {
-                #id_tokens
-            }
+            #container_fns
 
             fn _eq(&self, other: &Self) -> bool {
-                #(if !self.#field_idents._eq(&other.#field_idents) {
+                #(if !self.#field_members._eq(&other.#field_members) {
                     return false;
                 })*
                 true
@@ -155,10 +315,10 @@ fn get_struct_impl_block(
 
         impl #impl_generics #ident #type_generics #where_clause {
             pub fn onchain_new(
-                #(#field_idents: #field_types),*
+                #(#field_vars: #field_types),*
             ) -> Self {
                 Self {
-                    #(#field_idents),*
+                    #(#field_members: #field_vars),*
                 }
             }
         }
@@ -168,149 +328,117 @@ fn get_struct_impl_block(
 fn get_enum_impl_block(
     input: &DeriveInput,
     data: &DataEnum,
-    id: Option<LitStr>,
+    container_fns: TokenStream,
+    encoding_fns: Option<TokenStream>,
 ) -> proc_macro2::TokenStream {
-    if data.variants.len() == 0 {
+    if data.variants.is_empty() {
         panic!("onchain enum should has at least 1 variant")
     }
     let ident = &input.ident;
     let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
 
-    let id_tokens = if let Some(id) = id {
-        quote! {Some(#id)}
-    } else {
-        quote!(None)
-    };
-
-    let variant_field_index = data.variants.iter().enumerate().map(|(i, v)| {
-        if v.fields.len() > 1 {
-            panic!("variant should not have more than 1 field")
-        }
-        let field = v.fields.iter().next();
-        (i as u8, field, &v.ident)
+    // Variants are matched with braces whatever their kind, like
+    // `Self::Attack { 0: _0, 1: _1 }` and `Self::Stay {}`
+    let tags = variant_tags(data);
+    let variants = data.variants.iter().zip(tags).map(|(v, tag)| {
+        let members = field_members(&v.fields);
+        let types = v.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+        (tag, &v.ident, members, types)
     });
 
-    let to_bytes_branch = variant_field_index.clone().map(|(i, f, v)| {
-        if let Some(_) = f {
-            quote! {
-                Self::#v(s) => {
-                    let mut result = <u8 as ckboots::OnChain>::_to_bytes(&#i);
-                    result.extend(s._to_bytes());
-                    result
-                }
-            }
-        } else {
-            quote! {
-                Self::#v => <u8 as ckboots::OnChain>::_to_bytes(&#i)
+    let to_bytes_branch = variants.clone().map(|(i, v, members, types)| {
+        let pats = members.iter().map(|m| member_pat(m, ""));
+        let vars2 = members.iter().map(|m| member_var(m, ""));
+        quote! {
+            Self::#v { #(#pats),* } => {
+                let mut result = <u8 as ckboots::OnChain>::_to_bytes(&#i);
+                #(result.extend(<#types as ckboots::OnChain>::_to_bytes(#vars2));)*
+                result
             }
         }
     });
 
-    let from_bytes_branch = variant_field_index.clone().map(|(i, f, v)| {
-        if let Some(field) = f {
-            let ty = &field.ty;
-            quote! {
-                #i => {
-                    let (item, _) = ckboots::consume_and_decode::<#ty>(left)?;
-                    Some(Self::#v(item))
-                }
-            }
-        } else {
-            quote! {
-                #i => Some(Self::#v)
+    let from_bytes_branch = variants.clone().map(|(i, v, members, types)| {
+        let vars = members.iter().map(|m| member_var(m, ""));
+        let vars2 = vars.clone();
+        quote! {
+            #i => {
+                #(let (#vars, left) = ckboots::consume_and_decode::<#types>(left)?;)*
+                (Self::#v { #(#members: #vars2),* }, left)
             }
         }
     });
 
-    let capacity_branch = variant_field_index.clone().map(|(_, f, v)| {
-        if f.is_none() {
-            quote! {
-                Self::#v => 0
-            }
-        } else {
-            quote! {
-                Self::#v(s) => s._capacity()
-            }
+    let capacity_branch = variants.clone().map(|(_, v, members, _)| {
+        let pats = members.iter().map(|m| member_pat(m, ""));
+        let vars = members.iter().map(|m| member_var(m, ""));
+        quote! {
+            Self::#v { #(#pats),* } => 0 #(+ #vars._capacity())*
         }
     });
 
-    let eq_branch = variant_field_index.clone().map(|(_, f, v)| {
-        if f.is_none() {
-            quote! {
-                (Self::#v, Self::#v) => true
-            }
-        } else {
-            quote! {
-                (Self::#v(i1), Self::#v(i2)) => i1._eq(i2)
+    let eq_branch = variants.clone().map(|(_, v, members, _)| {
+        let lhs = members
+            .iter()
+            .map(|m| member_var(m, "l"))
+            .collect::<Vec<_>>();
+        let rhs = members
+            .iter()
+            .map(|m| member_var(m, "r"))
+            .collect::<Vec<_>>();
+        quote! {
+            (Self::#v { #(#members: #lhs),* }, Self::#v { #(#members: #rhs),* }) => {
+                true #(&& #lhs._eq(#rhs))*
             }
         }
     });
 
-    let mut default_ident: Option<&syn::Ident> = None;
-    let mut default_ty: Option<&syn::Type> = None;
+    let mut default_variant: Option<&syn::Variant> = None;
     let mut default_value: Option<TokenStream> = None;
     data.variants.iter().for_each(|v| {
-        let ident = &v.ident;
-        let f = v.fields.iter().next();
-        let _ = &v
-            .attrs
-            .iter()
-            .flat_map(|attr| {
-                if !attr.path.is_ident("onchain") {
-                    return Err(());
-                }
-                match attr.parse_meta() {
-                    Ok(List(meta)) => Ok(meta.nested.into_iter().collect::<Vec<_>>()),
-                    _ => Err(()),
-                }
-            })
-            .flatten()
-            .for_each(|meta| match meta {
-                NestedMeta::Meta(NameValue(nv)) => {
-                    if nv.path.is_ident("default") {
-                        let ts = lit_to_tokenstream(nv.lit);
-                        if f.is_none() {
-                            panic!("maybe #[onchain(default)]")
-                        }
-                        if default_ident.is_none() {
-                            default_ident = Some(ident);
-                            default_value = Some(ts);
-                            default_ty = Some(&f.as_ref().unwrap().ty);
-                        } else {
-                            panic!("should only one #[onchain(default)]")
-                        }
+        onchain_metas(&v.attrs).into_iter().for_each(|meta| match meta {
+            NestedMeta::Meta(NameValue(nv)) => {
+                if nv.path.is_ident("default") {
+                    if v.fields.len() != 1 {
+                        panic!("maybe #[onchain(default)], `default = <value>` is for variants with 1 field")
+                    }
+                    if default_variant.is_some() {
+                        panic!("should only one #[onchain(default)]")
                     }
+                    default_variant = Some(v);
+                    default_value = Some(lit_to_tokenstream(nv.lit));
                 }
-                NestedMeta::Meta(Path(path)) => {
-                    if path.is_ident("default") {
-                        if default_ident.is_some() {
-                            panic!("should only one #[onchain(default)]")
-                        }
-                        default_ident = Some(ident);
-                        if let Some(_) = f {
-                            default_ty = Some(&f.as_ref().unwrap().ty);
-                        }
+            }
+            NestedMeta::Meta(Path(path)) => {
+                if path.is_ident("default") {
+                    if default_variant.is_some() {
+                        panic!("should only one #[onchain(default)]")
                     }
+                    default_variant = Some(v);
                 }
-                _ => todo!(),
-            });
+            }
+            _ => todo!(),
+        });
     });
 
     let default_func = {
-        if default_ident.is_none() {
-            panic!("should use #[onchain(default)] to specify the default variant")
-        }
-        let ident = default_ident.unwrap();
-        let branch = match (default_ty, default_value) {
-            (None, None) => quote! {
-                Self::#ident
-            },
-            (Some(ty), None) => quote! {
-                Self::#ident(<#ty as ckboots::OnChain>::_default())
-            },
-            (_, Some(ts)) => quote! {
-                Self::#ident(#ts)
-            },
+        let variant =
+            default_variant.expect("should use #[onchain(default)] to specify the default variant");
+        let ident = &variant.ident;
+        let branch = match default_value {
+            Some(ts) => {
+                let member = field_members(&variant.fields).remove(0);
+                quote! {
+                    Self::#ident { #member: #ts }
+                }
+            }
+            None => {
+                let members = field_members(&variant.fields);
+                let values = variant.fields.iter().map(field_default);
+                quote! {
+                    Self::#ident { #(#members: #values),* }
+                }
+            }
         };
         quote! {
             fn _default() -> Self {
@@ -319,42 +447,48 @@ fn get_enum_impl_block(
         }
     };
 
-    quote! {
-        impl #impl_generics ckboots::OnChain for #ident #type_generics #where_clause{
+    let encoding_fns = encoding_fns.unwrap_or_else(|| {
+        quote! {
             fn _capacity(&self) -> u64 {
-                let prefix = 1u64;
+                // The length prefix and the tag
+                let prefix = 8 + 1u64;
                 let cap = match self {
                     #(#capacity_branch,)*
-                    _ => 0,
                 };
                 cap + prefix
             }
 
-            fn _id() -> Option<&'static str> {
-                #id_tokens
-            }
-
             fn _to_bytes(&self) -> Vec<u8> {
                 let bytes = match self {
                     #(#to_bytes_branch,)*
-                    _ => unreachable!(),
                 };
                 let mut prefix = (bytes.len() as u64).to_le_bytes().to_vec();
                 prefix.extend(bytes);
                 prefix
             }
 
-            fn _from_bytes(bytes: &[u8]) -> Option<Self> {
+            fn _from_bytes(bytes: &[u8]) -> Result<Self, ckboots::DecodeError> {
                 let (idx, left) = ckboots::consume_and_decode::<u8>(bytes)?;
-                match idx {
+                let (item, left) = match idx {
                     #(#from_bytes_branch,)*
-                    _ => unreachable!(),
+                    tag => return Err(ckboots::DecodeError::UnknownTag(tag)),
+                };
+                if !left.is_empty() {
+                    return Err(ckboots::DecodeError::TrailingBytes(left.len()));
                 }
+                Ok(item)
             }
 
             fn _fixed_size() -> Option<u64> {
                 None
             }
+        }
+    });
+    quote! {
+        impl #impl_generics ckboots::OnChain for #ident #type_generics #where_clause{
+            #encoding_fns
+
+            #container_fns
 
             fn _eq(&self, other: &Self) -> bool {
                 match (self, other) {
@@ -368,6 +502,116 @@ fn get_enum_impl_block(
     }
 }
 
+// Every type parameter is encoded as a field, so it has to be `OnChain` too,
+// and `Molecule` in the Molecule encoding
+pub(crate) fn add_on_chain_bounds(mut generics: Generics, molecule: bool) -> Generics {
+    generics.type_params_mut().for_each(|param| {
+        param.bounds.push(parse_quote!(ckboots::OnChain));
+        if molecule {
+            param.bounds.push(parse_quote!(ckboots::Molecule));
+        }
+    });
+    generics
+}
+
+// The tag of a variant is given by `#[onchain(tag = N)]`, which every variant
+// has to carry, so that the encoded cells survive reordering the variants.
+pub(crate) fn variant_tags(data: &DataEnum) -> Vec<u8> {
+    let mut used: BTreeMap<u8, &Ident> = BTreeMap::new();
+    data.variants
+        .iter()
+        .map(|v| {
+            let tag = onchain_metas(&v.attrs)
+                .into_iter()
+                .find_map(|meta| match meta {
+                    NestedMeta::Meta(NameValue(nv)) if nv.path.is_ident("tag") => match nv.lit {
+                        syn::Lit::Int(i) => Some(i.base10_parse::<u8>().unwrap_or_else(|_| {
+                            panic!("the tag of {} should be in 0..=255", v.ident)
+                        })),
+                        _ => panic!("#[onchain(tag = <u8>)]"),
+                    },
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("{} needs #[onchain(tag = <u8>)]", v.ident));
+            if let Some(other) = used.insert(tag, &v.ident) {
+                panic!("{} and {} have the same tag {}", other, v.ident, tag);
+            }
+            tag
+        })
+        .collect()
+}
+
+// `a` for a named field and `0` for an unnamed one
+pub(crate) fn field_members(fields: &Fields) -> Vec<Member> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match &f.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        })
+        .collect()
+}
+
+// The local variable bound to a field, `_0` for an unnamed one. `prefix`
+// tells apart the variables of two values of the same type.
+pub(crate) fn member_var(member: &Member, prefix: &str) -> Ident {
+    match member {
+        Member::Named(ident) if prefix.is_empty() => ident.clone(),
+        Member::Named(ident) => format_ident!("{}_{}", prefix, ident),
+        Member::Unnamed(index) => format_ident!("{}_{}", prefix, index.index),
+    }
+}
+
+// Binds a field to `member_var(member, prefix)` in a pattern. Shorthand is
+// required where the variable is the field name.
+pub(crate) fn member_pat(member: &Member, prefix: &str) -> TokenStream {
+    let var = member_var(member, prefix);
+    match member {
+        Member::Named(_) if prefix.is_empty() => quote! {#var},
+        _ => quote! {#member: #var},
+    }
+}
+
+// The value of `#[onchain(default = <value>)]` on the field, or the default
+// of its type
+fn field_default(field: &Field) -> TokenStream {
+    let ty = &field.ty;
+    let mut tokenstream: Option<TokenStream> = None;
+    onchain_metas(&field.attrs)
+        .into_iter()
+        .for_each(|meta| match meta {
+            NestedMeta::Meta(NameValue(nv)) => {
+                if nv.path.is_ident("default") {
+                    let ts = lit_to_tokenstream(nv.lit);
+                    tokenstream = Some(ts);
+                }
+            }
+            _ => panic!("#[onchain(default = <value>)]"),
+        });
+    tokenstream.unwrap_or_else(|| {
+        quote! {
+            <#ty as ckboots::OnChain>::_default()
+        }
+    })
+}
+
+fn onchain_metas(attrs: &[Attribute]) -> Vec<NestedMeta> {
+    attrs
+        .iter()
+        .flat_map(|attr| {
+            if !attr.path.is_ident("onchain") {
+                return Err(());
+            }
+            match attr.parse_meta() {
+                Ok(List(meta)) => Ok(meta.nested.into_iter().collect::<Vec<_>>()),
+                _ => Err(()),
+            }
+        })
+        .flatten()
+        .collect()
+}
+
 fn respan(stream: TokenStream, span: Span) -> TokenStream {
     stream
         .into_iter()
@@ -0,0 +1,140 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::{parse_quote, DeriveInput, Field, Fields, GenericParam, Generics, Type};
+
+use crate::on_chain::{field_members, has_test_roundtrip, member_var};
+
+// `OnChainArbitrary` of the `roundtrip` feature, which generates every field.
+// It is only implemented for `#[onchain(test_roundtrip)]`, so the fields have
+// to implement it too.
+pub fn get_arbitrary_impl_block(input: &DeriveInput) -> TokenStream {
+    if !has_test_roundtrip(&input.attrs) {
+        return quote! {};
+    }
+    let ident = &input.ident;
+    let generics = add_arbitrary_bounds(input.generics.clone(), field_types(input));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let strategy = match &input.data {
+        syn::Data::Struct(data) => {
+            let (strategy, pat) = fields_strategy(&data.fields);
+            let members = field_members(&data.fields);
+            let vars = members.iter().map(|m| member_var(m, "f"));
+            quote! {
+                #strategy
+                    .prop_map(|#pat| ckboots::Sample(Self { #(#members: #vars.0),* }))
+                    .boxed()
+            }
+        }
+        syn::Data::Enum(data) => {
+            let variants = data.variants.iter().map(|v| {
+                let variant = &v.ident;
+                let (strategy, pat) = fields_strategy(&v.fields);
+                let members = field_members(&v.fields);
+                let vars = members.iter().map(|m| member_var(m, "f"));
+                quote! {
+                    #strategy
+                        .prop_map(|#pat| ckboots::Sample(Self::#variant { #(#members: #vars.0),* }))
+                        .boxed()
+                }
+            });
+            quote! {
+                ckboots::proptest::strategy::Union::new(vec![#(#variants),*]).boxed()
+            }
+        }
+        syn::Data::Union(_) => todo!(),
+    };
+
+    quote! {
+        impl #impl_generics ckboots::OnChainArbitrary for #ident #type_generics #where_clause {
+            fn _arbitrary() -> ckboots::proptest::strategy::BoxedStrategy<ckboots::Sample<Self>> {
+                use ckboots::proptest::strategy::Strategy;
+                #strategy
+            }
+        }
+    }
+}
+
+// A proptest module checking `check_roundtrip` against the generated values
+// of a type with `#[onchain(test_roundtrip)]`. A generic type is only tested
+// as a field of the others.
+pub fn get_roundtrip_test(input: &DeriveInput) -> TokenStream {
+    if !has_test_roundtrip(&input.attrs) || input.generics.type_params().next().is_some() {
+        return quote! {};
+    }
+    let ident = &input.ident;
+    let module = format_ident!("__onchain_roundtrip_{}", ident);
+    quote! {
+        #[cfg(test)]
+        #[allow(non_snake_case)]
+        mod #module {
+            use super::*;
+
+            ckboots::proptest::proptest! {
+                #[test]
+                fn roundtrip(sample in <#ident as ckboots::OnChainArbitrary>::_arbitrary()) {
+                    ckboots::check_roundtrip(&sample.0)?;
+                }
+            }
+        }
+    }
+}
+
+fn add_arbitrary_bounds(mut generics: Generics, field_types: Vec<Type>) -> Generics {
+    generics.params.iter_mut().for_each(|param| {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(ckboots::OnChainArbitrary));
+        }
+    });
+    let where_clause = generics.make_where_clause();
+    field_types.into_iter().for_each(|ty| {
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: ckboots::OnChainArbitrary));
+    });
+    generics
+}
+
+// The types of the fields, except the ones holding the type itself, whose
+// bounds would never be proven
+fn field_types(input: &DeriveInput) -> Vec<Type> {
+    let fields: Vec<&Field> = match &input.data {
+        syn::Data::Struct(data) => data.fields.iter().collect(),
+        syn::Data::Enum(data) => data.variants.iter().flat_map(|v| &v.fields).collect(),
+        syn::Data::Union(_) => vec![],
+    };
+    let ident = input.ident.to_string();
+    let mut types: Vec<Type> = vec![];
+    fields.into_iter().for_each(|field| {
+        if !mentions(field.ty.to_token_stream(), &ident) && !types.contains(&field.ty) {
+            types.push(field.ty.clone());
+        }
+    });
+    types
+}
+
+fn mentions(tokens: TokenStream, ident: &str) -> bool {
+    tokens.into_iter().any(|t| match t {
+        TokenTree::Ident(i) => i == ident || i == "Self",
+        TokenTree::Group(g) => mentions(g.stream(), ident),
+        _ => false,
+    })
+}
+
+// The strategy of the fields nested in pairs, like `(a, (b, Just(())))`, so
+// that there is no limit on the number of fields, and the pattern binding
+// their samples to the vars of the members prefixed with `f`
+fn fields_strategy(fields: &Fields) -> (TokenStream, TokenStream) {
+    let members = field_members(fields);
+    let types = fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    members.iter().zip(types).rev().fold(
+        (quote! {ckboots::proptest::strategy::Just(())}, quote! {()}),
+        |(strategy, pat), (member, ty)| {
+            let var = member_var(member, "f");
+            (
+                quote! {(<#ty as ckboots::OnChainArbitrary>::_arbitrary(), #strategy)},
+                quote! {(#var, #pat)},
+            )
+        },
+    )
+}
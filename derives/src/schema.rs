@@ -0,0 +1,86 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Fields, Member, Type};
+
+use crate::contract::type_in_types_crate;
+use crate::on_chain::{field_members, is_molecule, variant_tags};
+
+// `__get_type_schema__` of `__CodeStr__`, see `AppSchema`
+pub fn get_type_schema_fn(input: &syn::DeriveInput) -> TokenStream {
+    let name = input.ident.to_string();
+    let encoding = if is_molecule(&input.attrs) {
+        "molecule"
+    } else {
+        "default"
+    };
+    let layout = match &input.data {
+        syn::Data::Struct(data) => {
+            let fields = field_schemas(&data.fields);
+            quote! {
+                ckboots::generators::contract::LayoutSchema::Struct {
+                    fields: #fields,
+                }
+            }
+        }
+        syn::Data::Enum(data) => {
+            let tags = variant_tags(data);
+            let variants = data.variants.iter().zip(tags).map(|(v, tag)| {
+                let name = v.ident.to_string();
+                let fields = field_schemas(&v.fields);
+                quote! {
+                    ckboots::generators::contract::VariantSchema {
+                        name: #name,
+                        tag: #tag,
+                        fields: #fields,
+                    }
+                }
+            });
+            quote! {
+                ckboots::generators::contract::LayoutSchema::Enum {
+                    variants: vec![#(#variants),*],
+                }
+            }
+        }
+        syn::Data::Union(_) => todo!(),
+    };
+    quote! {
+        fn __get_type_schema__() -> ckboots::generators::contract::TypeSchema {
+            ckboots::generators::contract::TypeSchema {
+                name: String::from(#name),
+                id: <Self as ckboots::OnChain>::_id(),
+                version: <Self as ckboots::OnChain>::_version(),
+                keyed: <Self as ckboots::OnChain>::_keyed(),
+                owned: <Self as ckboots::OnChain>::_owned(),
+                encoding: #encoding,
+                fixed_size: <Self as ckboots::OnChain>::_fixed_size(),
+                default: <Self as ckboots::OnChain>::_to_bytes(&<Self as ckboots::OnChain>::_default()),
+                layout: #layout,
+            }
+        }
+    }
+}
+
+fn field_schemas(fields: &Fields) -> TokenStream {
+    let names = field_members(fields).into_iter().map(|m| match m {
+        Member::Named(ident) => ident.to_string(),
+        Member::Unnamed(index) => index.index.to_string(),
+    });
+    let types = fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let type_names = types.iter().map(|ty| schema_type_name(ty));
+    quote! {
+        vec![#(ckboots::generators::contract::FieldSchema {
+            name: #names,
+            ty: #type_names,
+            fixed_size: <#types as ckboots::OnChain>::_fixed_size(),
+        }),*]
+    }
+}
+
+// The type as the generated contracts name it, without the spaces of the
+// token stream: `Vec < (u8 , Item) >` becomes `Vec<(u8, Item)>`
+pub(crate) fn schema_type_name(ty: &Type) -> String {
+    type_in_types_crate(ty)
+        .replace(' ', "")
+        .replace(',', ", ")
+        .replace(';', "; ")
+}
@@ -2,29 +2,41 @@ use ckboots::create_app;
 use ckboots_derives::{contract, OnChain};
 
 #[derive(OnChain)]
-#[onchain(id = "frog")]
+#[onchain(id = "frog", test_roundtrip)]
 pub struct Frog {
     #[onchain(default = 100)]
     pub physical: u8,
     pub traval_cnt: u8,
 }
 
+#[derive(OnChain)]
+#[onchain(id = "pond")]
+pub struct Pond {
+    pub lilies: u8,
+}
+
 #[contract(Travel, id = "travel")]
 fn travel(frog: &mut Frog) {
     frog.physical -= 1;
     frog.traval_cnt += 1;
 }
 
+// The wrapper idx of a state cell is the position of its id in the sorted ids,
+// not of its type here
 create_app!(TravelFrog {
-    types: [Frog],
+    types: [Pond, Frog],
     contracts: [Travel],
 });
 
 #[cfg(test)]
 mod tests {
-    use super::{Frog, Travel};
-    use ckboots::OnChain;
+    use super::{Frog, Pond, Travel, TravelFrog};
+    use ckboots::testing::SimChain;
+    use ckboots::{
+        App, BuildError, ClientError, Config, Deployment, OnChain, OnChainWrapper, StateError,
+    };
     use ckboots_derives::OnChain;
+    use std::collections::HashMap;
 
     #[derive(OnChain)]
     pub struct House1 {
@@ -55,13 +67,159 @@ mod tests {
             traval_cnt: 0,
         };
         let bytes = frog._to_bytes();
-        let entry = Travel::new(vec![&bytes]);
+        let entry = Travel::new(vec![&bytes]).unwrap();
         let result = entry.run();
-        let (id, _, output) = result.input_output_data.iter().next().unwrap();
+        let (id, _, output) = result.input_output_data.first().unwrap();
         assert_eq!(*id, "frog");
         let (new_frog, _) = ckboots::consume_and_decode::<Frog>(output).unwrap();
         assert_eq!(new_frog.physical, 0);
         assert_eq!(new_frog.traval_cnt, 1);
         assert_eq!(Travel::_get_args_ids(), vec!["frog"]);
+        assert!(Travel::new(vec![&bytes[1..]]).is_err());
+    }
+
+    #[test]
+    fn test_default_states() {
+        let states = TravelFrog::_default_states();
+        assert_eq!(states.len(), 2);
+        let (id, version, data) = states.get(1).unwrap();
+        assert_eq!((*id, *version), ("frog", 1));
+        let (frog, _) = ckboots::consume_and_decode::<Frog>(data).unwrap();
+        assert_eq!(frog.physical, 100);
+        assert_eq!(frog.traval_cnt, 0);
+    }
+
+    #[test]
+    fn test_schema() {
+        use ckboots::__CodeStr__;
+        use ckboots::generators::contract::LayoutSchema;
+
+        let frog = Frog::__get_type_schema__();
+        assert_eq!((frog.id, frog.fixed_size), (Some("frog"), Some(2)));
+        assert_eq!(frog.default, vec![100, 0]);
+        match frog.layout {
+            LayoutSchema::Struct { fields } => {
+                assert_eq!(fields[0].name, "physical");
+                assert_eq!(fields[0].ty, "u8");
+            }
+            LayoutSchema::Enum { .. } => panic!("should be a struct"),
+        }
+        let travel = Travel::_schema();
+        assert_eq!(travel.mutates[0].id, Some("frog"));
+        assert!(travel.deps.is_empty());
+        assert!(travel.user_input.is_none());
+    }
+
+    #[test]
+    fn test_molecule_decls() {
+        use ckboots::generators::contract::declare_default_encoding;
+
+        let decls = TravelFrog::_molecule_decls();
+        assert_eq!(decls.len(), 2);
+        assert!(decls[1].ends_with("array Frog [byte; 2];"));
+        let mut decls = vec![];
+        declare_default_encoding(&mut decls, "Pair<u8, Item>", Some(3));
+        declare_default_encoding(&mut decls, "Vec<Frog>", None);
+        assert!(decls[0].ends_with("array PairU8Item [byte; 3];"));
+        assert!(decls[1].starts_with("// Vec<Frog> is in the default encoding"));
+    }
+
+    #[test]
+    fn test_cell_to_json() {
+        let frog = Frog {
+            physical: 7,
+            traval_cnt: 1,
+        };
+        let wrapper = OnChainWrapper {
+            idx: 0,
+            version: 1,
+            data: frog._to_bytes(),
+        };
+        let value = TravelFrog::_cell_to_json(&wrapper._to_bytes()).unwrap();
+        assert_eq!(value.to_string(), r#"{"physical":7,"traval_cnt":1}"#);
+        let wrapper = OnChainWrapper {
+            idx: 1,
+            version: 1,
+            data: Pond { lilies: 3 }._to_bytes(),
+        };
+        let value = TravelFrog::_cell_to_json(&wrapper._to_bytes()).unwrap();
+        assert_eq!(value.to_string(), r#"{"lilies":3}"#);
+        let wrapper = OnChainWrapper { idx: 2, ..wrapper };
+        let err = TravelFrog::_cell_to_json(&wrapper._to_bytes()).err();
+        assert!(matches!(err, Some(ckboots::JsonError::UnknownIdx(2))));
+    }
+
+    #[test]
+    fn test_travel_on_sim_chain() {
+        let config = r#"
+[chain]
+rpc = "http://127.0.0.1:8114"
+secret_key = "0x0101010101010101010101010101010101010101010101010101010101010101"
+
+[boots]
+app_name = "travel frog"
+initial_capacity = 1000
+"#
+        .parse::<Config>()
+        .unwrap();
+        let chain = SimChain::new();
+        let mut contracts = HashMap::new();
+        contracts.insert(String::from(Travel::_id()), chain.deploy(Travel::_id()));
+        let deployment = Deployment {
+            entry: chain.deploy("_entry"),
+            contracts,
+            lock_dep: None,
+        };
+        let mut app = App::new(config, Box::new(chain.clone()), deployment, HashMap::new());
+
+        let funding = chain.fund(app.lock(), 2000 * 100_000_000);
+        let (tx, genesis) = app
+            .build_genesis_tx(TravelFrog::_default_states(), vec![funding])
+            .unwrap();
+        app.send_tx(tx).unwrap();
+        assert_eq!(chain.block_number(), 1);
+
+        let ids = vec![Frog::_id().unwrap(), Pond::_id().unwrap()];
+        let manager = app
+            .load_cells(genesis.input_hash.clone(), ids.clone())
+            .unwrap();
+        let mut frogs = TravelFrog { _manager: manager };
+        assert_eq!(
+            frogs._exec("swim", 0u8).err(),
+            Some(StateError::UnknownContract(String::from("swim")))
+        );
+        assert_eq!(
+            frogs._migrate("frog").err(),
+            Some(StateError::NoMigration("frog"))
+        );
+        let (tx, cells) = app
+            .build_tx(frogs._exec(Travel::_id(), 0u8).unwrap())
+            .unwrap();
+        frogs._manager.stage_cells(&cells).unwrap();
+        app.send_tx(tx.clone()).unwrap();
+        app.update_cells(cells.clone());
+        frogs._manager.commit();
+        assert_eq!(chain.block_number(), 2);
+        let frog = frogs._manager.get::<Frog>().unwrap();
+        assert_eq!(frog.physical, 99);
+
+        frogs._manager.stage_cells(&cells).unwrap();
+        let err = app.send_tx(tx);
+        assert!(matches!(
+            err,
+            Err(BuildError::Client(ClientError::Rejected(_)))
+        ));
+        frogs._manager.rollback();
+        assert!(!frogs._manager.has_pending());
+
+        let manager = app.load_cells(genesis.input_hash, ids).unwrap();
+        let (frog, _) =
+            ckboots::consume_and_decode::<Frog>(manager.get_by_id("frog").unwrap()).unwrap();
+        assert_eq!(frog.physical, 99);
+        assert_eq!(frog.traval_cnt, 1);
+        let cell = app.get_cell("frog").unwrap();
+        let live = chain.get_live_cell(&cell.outpoint).unwrap();
+        let (wrapper, _) = ckboots::consume_and_decode::<OnChainWrapper>(&live.data).unwrap();
+        assert_eq!(wrapper.idx, 0);
     }
 }
@@ -0,0 +1,32 @@
+#![no_main]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ckboots::Bytes;
+use ckboots_fuzz::{check_decode, check_one_of};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    check_one_of(
+        data,
+        &[
+            check_decode::<u8>,
+            check_decode::<u64>,
+            check_decode::<i128>,
+            check_decode::<bool>,
+            check_decode::<String>,
+            check_decode::<Bytes>,
+            check_decode::<Option<u32>>,
+            check_decode::<Option<String>>,
+            check_decode::<Vec<u16>>,
+            check_decode::<Vec<Bytes>>,
+            check_decode::<Vec<[u8; 0]>>,
+            check_decode::<[u32; 3]>,
+            check_decode::<[String; 2]>,
+            check_decode::<BTreeMap<u8, String>>,
+            check_decode::<BTreeSet<String>>,
+            check_decode::<(u8, String, bool)>,
+            check_decode::<Vec<Option<(u16, Vec<u8>)>>>,
+        ],
+    )
+});
@@ -0,0 +1,121 @@
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use ckboots::{decode_versioned, Bytes, Migrate, OnChain};
+use ckboots_derives::{migration, OnChain};
+use ckboots_fuzz::{check_decode, check_one_of};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(OnChain)]
+pub struct Empty {}
+
+#[derive(OnChain)]
+pub struct Pos(u8, i16);
+
+#[derive(OnChain)]
+pub struct Pair<A, B> {
+    pub first: A,
+    pub second: B,
+}
+
+#[derive(OnChain)]
+pub enum Action {
+    #[onchain(tag = 0, default)]
+    Stay,
+    #[onchain(tag = 3)]
+    Move(Pos),
+    #[onchain(tag = 4)]
+    Attack {
+        target: Option<u32>,
+        power: u128,
+    },
+    #[onchain(tag = 5)]
+    Say(String),
+}
+
+#[derive(OnChain)]
+pub struct HeroV1 {
+    pub hp: u8,
+}
+
+#[derive(OnChain)]
+#[onchain(id = "hero", version = 2, migrate)]
+pub struct Hero {
+    pub hp: u16,
+    pub name: String,
+    pub bag: BTreeMap<u8, Bytes>,
+    pub actions: Vec<Action>,
+    pub last: Pair<Action, [bool; 2]>,
+    pub marks: Vec<Empty>,
+}
+
+#[migration]
+impl Migrate for Hero {
+    type Previous = HeroV1;
+
+    fn _migrate(previous: HeroV1) -> Self {
+        Hero {
+            hp: previous.hp as u16,
+            ..Hero::_default()
+        }
+    }
+}
+
+#[derive(OnChain)]
+#[onchain(encoding = "molecule")]
+pub struct Point {
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(OnChain)]
+#[onchain(encoding = "molecule")]
+pub enum Step {
+    #[onchain(tag = 0, default)]
+    Stay,
+    #[onchain(tag = 1)]
+    Move(Point),
+    #[onchain(tag = 2)]
+    Jump {
+        to: Vec<Point>,
+        note: Option<String>,
+    },
+}
+
+#[derive(OnChain)]
+#[onchain(id = "frog", encoding = "molecule")]
+pub struct Frog {
+    pub pos: Point,
+    pub name: String,
+    pub steps: Vec<Step>,
+    pub items: [Vec<u32>; 2],
+}
+
+// Both versions of `Hero` go through `_from_versioned_bytes`, like the
+// contracts decode the data of an older state cell
+fn check_versioned(data: &[u8]) {
+    if let Some((version, data)) = data.split_first() {
+        if let Ok(hero) = decode_versioned::<Hero>(*version, data) {
+            assert!(hero._capacity() == hero._to_bytes().len() as u64);
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    check_one_of(
+        data,
+        &[
+            check_decode::<Empty>,
+            check_decode::<Pos>,
+            check_decode::<Pair<u8, String>>,
+            check_decode::<Action>,
+            check_decode::<Hero>,
+            check_versioned,
+            check_decode::<Point>,
+            check_decode::<Step>,
+            check_decode::<Frog>,
+            check_decode::<Vec<Frog>>,
+        ],
+    )
+});
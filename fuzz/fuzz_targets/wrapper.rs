@@ -0,0 +1,15 @@
+#![no_main]
+
+use ckboots::{consume_and_decode, OnChain, OnChainWrapper};
+use ckboots_fuzz::check_decode;
+use libfuzzer_sys::fuzz_target;
+
+// The data of every state cell, which the contracts decode first
+fuzz_target!(|data: &[u8]| {
+    check_decode::<OnChainWrapper>(data);
+    if let Ok((wrapper, left)) = consume_and_decode::<OnChainWrapper>(data) {
+        assert_eq!(wrapper._capacity(), (data.len() - left.len()) as u64);
+        let len = OnChainWrapper::encoded_len(wrapper.data.len() as u64);
+        assert_eq!(len, wrapper._capacity());
+    }
+});
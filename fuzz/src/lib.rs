@@ -0,0 +1,18 @@
+use ckboots::{decode, OnChain};
+
+// Cell data is attacker-controlled, so decoding it must not panic. Whatever
+// decodes has to be the encoding of the value, otherwise a state could be
+// written as different cell data.
+pub fn check_decode<T: OnChain>(data: &[u8]) {
+    if let Ok(value) = decode::<T>(data) {
+        assert_eq!(value._to_bytes(), data, "the encoding is not canonical");
+        assert_eq!(value._capacity(), data.len() as u64);
+    }
+}
+
+// The first byte picks the type `data` is decoded as
+pub fn check_one_of(data: &[u8], checks: &[fn(&[u8])]) {
+    if let Some((selector, data)) = data.split_first() {
+        checks[*selector as usize % checks.len()](data);
+    }
+}
@@ -5,21 +5,88 @@ use core::result::Result;
 // Import heap related library from `alloc`
 // https://doc.rust-lang.org/alloc/index.html
 use alloc::{vec, vec::Vec};
+use ckb_std::error::SysError;
 use crate::error::Error;
-use types::OnChain;
+use types::*;
 
 pub fn main() -> Result<(), Error> {
     let script = types::load_exec_script()?;
     if script.len() == 0 {
-        
-let bytes = types::load_output_data(0);
-let frog_output = <types::Frog as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
-let _default = <types::Frog as types::OnChain>::_default();
-if !frog_output.eq(&frog_output) {
-    return Err(crate::error::Error::NotEqual);
+        // Spending state cells always runs a contract
+        if types::has_group_inputs()? {
+            return Err(Error::Exec);
+        }
+        if !types::check_instance_locks()? {
+            return Err(Error::Lock);
+        }
+        // New state cells, which are the genesis or new keyed instances,
+        // hold the default value, once per id
+        if !types::check_new_instances()? {
+            return Err(Error::Key);
+        }
+        let keyed = !types::load_instance_key()?.is_empty();
+        let mut created: Vec<u8> = vec![];
+        let mut idx = 0;
+        loop {
+            let bytes = match types::load_group_output_data(idx) {
+                Ok(bytes) => bytes,
+                Err(SysError::IndexOutOfBound) => break,
+                Err(err) => return Err(err.into()),
+            };
+            let wrapper = types::decode::<types::OnChainWrapper>(&bytes)?;
+            if created.contains(&wrapper.idx) {
+                return Err(Error::Key);
+            }
+            created.push(wrapper.idx);
+            match wrapper.idx {
+                
+0 => {
+    if keyed && !<Frog as types::OnChain>::_keyed() {
+        return Err(Error::Key);
+    }
+    if wrapper.version != <Frog as types::OnChain>::_version() {
+        return Err(Error::Version);
+    }
+    let output = types::decode::<Frog>(&wrapper.data)?;
+    let _default = <Frog as types::OnChain>::_default();
+    if !types::OnChain::_eq(&output, &_default) {
+        return Err(Error::NotEqual);
+    }
+}
+
+1 => {
+    if keyed && !<Pond as types::OnChain>::_keyed() {
+        return Err(Error::Key);
+    }
+    if wrapper.version != <Pond as types::OnChain>::_version() {
+        return Err(Error::Version);
+    }
+    let output = types::decode::<Pond>(&wrapper.data)?;
+    let _default = <Pond as types::OnChain>::_default();
+    if !types::OnChain::_eq(&output, &_default) {
+        return Err(Error::NotEqual);
+    }
 }
 
+                types::REGISTRY_IDX if !keyed => {
+                    types::decode::<Vec<[u8; 32]>>(&wrapper.data)?;
+                }
+                _ => return Err(Error::TypeError),
+            }
+            idx += 1;
+        }
     } else {
+        if !types::check_instance_keys()? {
+            return Err(Error::Key);
+        }
+        if !types::check_instance_locks()? {
+            return Err(Error::Lock);
+        }
+        // Only the contracts of the app change its state cells, and the
+        // registry listing them never changes
+        if types::has_group_registry()? || !types::is_registered(&script)? {
+            return Err(Error::Exec);
+        }
         types::exec_script(&script)?;
     }
     Ok(())
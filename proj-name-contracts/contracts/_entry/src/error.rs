@@ -7,8 +7,13 @@ pub enum Error {
     IndexOutOfBound = 1,
     ItemMissing,
     LengthNotEnough,
+    TypeError,
     Encoding,
     NotEqual,
+    Version,
+    Key,
+    Lock,
+    Exec,
 }
 
 impl From<SysError> for Error {
@@ -24,4 +29,13 @@ impl From<SysError> for Error {
     }
 }
 
+impl From<types::DecodeError> for Error {
+    fn from(err: types::DecodeError) -> Self {
+        match err {
+            types::DecodeError::UnknownVersion(_) => Self::Version,
+            _ => Self::Encoding,
+        }
+    }
+}
+
     
\ No newline at end of file
@@ -6,7 +6,7 @@ use core::result::Result;
 // https://doc.rust-lang.org/alloc/index.html
 use alloc::{vec, vec::Vec};
 use crate::error::Error;
-use types::OnChain;
+use types::*;
 
 pub fn main() -> Result<(), Error> {
     
@@ -15,22 +15,26 @@ pub fn main() -> Result<(), Error> {
 
 
 let bytes = types::load_input_data(0)?;
-let wrapper = <types::OnChainWrapper as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let wrapper = types::decode::<types::OnChainWrapper>(&bytes)?;
 let input_id = wrapper.idx;
 let bytes = wrapper.data;
-let mut frog = <types::Frog as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let mut frog = types::decode_versioned::<Frog>(wrapper.version, &bytes)?;
 
 
 { frog . physical -= 1 ; frog . traval_cnt += 1 ; }
 
 
 let bytes = types::load_output_data(0)?;
-let wrapper = <types::OnChainWrapper as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let wrapper = types::decode::<types::OnChainWrapper>(&bytes)?;
 let output_id = wrapper.idx;
 if input_id != output_id {
     return Err(crate::error::Error::TypeError);
 }
-let frog_output = <types::Frog as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+if wrapper.version != <Frog as types::OnChain>::_version() {
+    return Err(crate::error::Error::Version);
+}
+let bytes = wrapper.data;
+let frog_output = types::decode::<Frog>(&bytes)?;
 if !frog._eq(&frog_output) {
     return Err(crate::error::Error::NotEqual);
 }
@@ -10,6 +10,10 @@ pub enum Error {
     TypeError,
     Encoding,
     NotEqual,
+    Version,
+    Key,
+    Lock,
+    Exec,
 }
 
 impl From<SysError> for Error {
@@ -25,4 +29,13 @@ impl From<SysError> for Error {
     }
 }
 
+impl From<types::DecodeError> for Error {
+    fn from(err: types::DecodeError) -> Self {
+        match err {
+            types::DecodeError::UnknownVersion(_) => Self::Version,
+            _ => Self::Encoding,
+        }
+    }
+}
+
     
\ No newline at end of file
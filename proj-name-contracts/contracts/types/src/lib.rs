@@ -11,13 +11,60 @@ use core::clone::Clone;
 use core::iter::Extend;
 use core::iter::Iterator;
 
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 
 #[macro_use]
 extern crate alloc;
 
+// The generated impls refer to `ckboots::OnChain` and friends
+use crate as ckboots;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // The bytes end before the value does
+    Truncated,
+    // The length prefix claims more bytes than there are
+    BadLengthPrefix,
+    // No variant of the enum is encoded with the tag
+    UnknownTag(u8),
+    // No variant of the Molecule union is encoded with the item id
+    UnknownUnionId(u32),
+    // The number of bytes left after the value
+    TrailingBytes(usize),
+    // A `String` holds bytes which are not UTF-8
+    InvalidUtf8,
+    // A `bool` is encoded as neither 0 nor 1
+    InvalidBool(u8),
+    // The keys of a map or set are not strictly ascending, so the encoding
+    // is not canonical
+    UnsortedKeys,
+    // The bytes are written by a version of the type which cannot be
+    // migrated to the current one
+    UnknownVersion(u8),
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "bytes are truncated"),
+            DecodeError::BadLengthPrefix => write!(f, "length prefix is out of bounds"),
+            DecodeError::UnknownTag(tag) => write!(f, "unknown enum tag {}", tag),
+            DecodeError::UnknownUnionId(id) => write!(f, "unknown union item id {}", id),
+            DecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes", n),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::InvalidBool(b) => write!(f, "{} is not a bool", b),
+            DecodeError::UnsortedKeys => write!(f, "keys are unsorted or duplicated"),
+            DecodeError::UnknownVersion(v) => write!(f, "cannot migrate from version {}", v),
+        }
+    }
+}
+
 pub trait OnChain: Sized {
+    // The exact length of `_to_bytes()`, length prefix included
     fn _capacity(&self) -> u64;
 
     fn _id() -> Option<&'static str> {
@@ -26,13 +73,60 @@ pub trait OnChain: Sized {
 
     fn _to_bytes(&self) -> Vec<u8>;
 
-    fn _from_bytes(bytes: &[u8]) -> Option<Self>;
+    // `bytes` is the content without the length prefix, which must be
+    // consumed entirely
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError>;
 
     fn _fixed_size() -> Option<u64>;
 
+    // The length of the value at the head of `bytes` if its encoding tells
+    // it, like a Molecule table does, so that `_to_bytes` needs no length
+    // prefix. Only asked of the types without a fixed size.
+    fn _delimited_len(_bytes: &[u8]) -> Result<Option<u64>, DecodeError> {
+        Ok(None)
+    }
+
     fn _eq(&self, other: &Self) -> bool;
 
     fn _default() -> Self;
+
+    // Whether the type has a state cell per instance key rather than one,
+    // see `#[onchain(keyed)]`
+    fn _keyed() -> bool {
+        false
+    }
+
+    // Whether the instances are locked by their owners rather than the
+    // operator, see `#[onchain(owned)]`
+    fn _owned() -> bool {
+        false
+    }
+
+    // The layout version, which is stored in the `OnChainWrapper` header
+    fn _version() -> u8 {
+        1
+    }
+
+    // Decode `bytes` written by the `version` of the type. Types with a
+    // `Migrate` impl upgrade the older versions, see `#[onchain(migrate)]`.
+    fn _from_versioned_bytes(version: u8, bytes: &[u8]) -> Result<Self, DecodeError> {
+        if version != Self::_version() {
+            return Err(DecodeError::UnknownVersion(version));
+        }
+        decode::<Self>(bytes)
+    }
+
+    // The id of the generated contract upgrading the state cell in place
+    fn _migration_id() -> Option<&'static str> {
+        None
+    }
+}
+
+// How the previous version of a type is upgraded to it
+pub trait Migrate: OnChain {
+    type Previous: OnChain;
+
+    fn _migrate(previous: Self::Previous) -> Self;
 }
 
 macro_rules! impl_on_chain_for_builtin {
@@ -46,12 +140,12 @@ macro_rules! impl_on_chain_for_builtin {
                 self.to_le_bytes().into()
             }
 
-            fn _from_bytes(bytes: &[u8]) -> Option<Self> {
-                let mut slice = [0u8; $c];
-                for i in 0..$c {
-                    slice[i] = bytes.get(i)?.clone();
+            fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                if bytes.len() > $c {
+                    return Err(DecodeError::TrailingBytes(bytes.len() - $c));
                 }
-                Some(<$t>::from_le_bytes(slice))
+                let slice: [u8; $c] = bytes.try_into().map_err(|_| DecodeError::Truncated)?;
+                Ok(<$t>::from_le_bytes(slice))
             }
 
             fn _fixed_size() -> Option<u64> {
@@ -80,6 +174,290 @@ impl_on_chain_for_builtin!(i32, 4);
 impl_on_chain_for_builtin!(i64, 8);
 impl_on_chain_for_builtin!(i128, 16);
 
+impl OnChain for bool {
+    fn _capacity(&self) -> u64 {
+        1
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match <u8 as OnChain>::_from_bytes(bytes)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(DecodeError::InvalidBool(b)),
+        }
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        Some(1)
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn _default() -> Self {
+        false
+    }
+}
+
+// Tagged like an enum, 0 for `None` and 1 for `Some`
+impl<T: OnChain> OnChain for Option<T> {
+    fn _capacity(&self) -> u64 {
+        let prefix = 8 + 1;
+        match self {
+            Some(item) => item._capacity() + prefix,
+            None => prefix,
+        }
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let content = match self {
+            Some(item) => {
+                let mut content = vec![1u8];
+                content.extend(item._to_bytes());
+                content
+            }
+            None => vec![0u8],
+        };
+        with_prefix(content)
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (tag, left) = consume_and_decode::<u8>(bytes)?;
+        let (item, left) = match tag {
+            0 => (None, left),
+            1 => {
+                let (item, left) = consume_and_decode::<T>(left)?;
+                (Some(item), left)
+            }
+            tag => return Err(DecodeError::UnknownTag(tag)),
+        };
+        if !left.is_empty() {
+            return Err(DecodeError::TrailingBytes(left.len()));
+        }
+        Ok(item)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a._eq(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    fn _default() -> Self {
+        None
+    }
+}
+
+// The items one after another, without a length prefix if the items are
+// fixed-size
+impl<T: OnChain, const N: usize> OnChain for [T; N] {
+    fn _capacity(&self) -> u64 {
+        let size = self.iter().fold(0, |prev, item| prev + item._capacity());
+        if Self::_fixed_size().is_some() {
+            size
+        } else {
+            size + 8
+        }
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        self.iter().for_each(|item| result.extend(item._to_bytes()));
+        if Self::_fixed_size().is_some() {
+            result
+        } else {
+            with_prefix(result)
+        }
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut items = Vec::with_capacity(N);
+        let mut left = bytes;
+        for _ in 0..N {
+            let (item, rest) = consume_and_decode::<T>(left)?;
+            items.push(item);
+            left = rest;
+        }
+        if !left.is_empty() {
+            return Err(DecodeError::TrailingBytes(left.len()));
+        }
+        items.try_into().map_err(|_| DecodeError::Truncated)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        Some(T::_fixed_size()? * N as u64)
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self.iter().zip(other.iter()).all(|(a, b)| a._eq(b))
+    }
+
+    fn _default() -> Self {
+        core::array::from_fn(|_| T::_default())
+    }
+}
+
+// The entries in ascending key order, which is the only accepted order so
+// that equal maps are always encoded into the same bytes
+impl<K: OnChain + Ord, V: OnChain> OnChain for BTreeMap<K, V> {
+    fn _capacity(&self) -> u64 {
+        let prefix = 8;
+        self.iter()
+            .fold(0, |prev, (k, v)| prev + k._capacity() + v._capacity())
+            + prefix
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        self.iter().for_each(|(k, v)| {
+            result.extend(k._to_bytes());
+            result.extend(v._to_bytes());
+        });
+        with_prefix(result)
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut result = BTreeMap::new();
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let (k, left) = consume_and_decode::<K>(bytes)?;
+            let (v, left) = consume_and_decode::<V>(left)?;
+            // `is_some_and` is too new for the toolchain of the contracts
+            if let Some(last) = result.keys().next_back() {
+                if k <= *last {
+                    return Err(DecodeError::UnsortedKeys);
+                }
+            }
+            result.insert(k, v);
+            bytes = left;
+        }
+        Ok(result)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|((k1, v1), (k2, v2))| k1._eq(k2) && v1._eq(v2))
+    }
+
+    fn _default() -> Self {
+        BTreeMap::new()
+    }
+}
+
+// The items in ascending order, like the keys of `BTreeMap`
+impl<T: OnChain + Ord> OnChain for BTreeSet<T> {
+    fn _capacity(&self) -> u64 {
+        let prefix = 8;
+        self.iter().fold(0, |prev, item| prev + item._capacity()) + prefix
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        self.iter().for_each(|item| result.extend(item._to_bytes()));
+        with_prefix(result)
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut result = BTreeSet::new();
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let (item, left) = consume_and_decode::<T>(bytes)?;
+            if let Some(last) = result.iter().next_back() {
+                if item <= *last {
+                    return Err(DecodeError::UnsortedKeys);
+                }
+            }
+            result.insert(item);
+            bytes = left;
+        }
+        Ok(result)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a._eq(b))
+    }
+
+    fn _default() -> Self {
+        BTreeSet::new()
+    }
+}
+
+// Encoded like a struct whose fields are the elements
+macro_rules! impl_on_chain_for_tuple {
+    ($($t:ident $v:ident $i:tt),+) => {
+        impl<$($t: OnChain),+> OnChain for ($($t,)+) {
+            fn _capacity(&self) -> u64 {
+                let size = 0 $(+ self.$i._capacity())+;
+                if Self::_fixed_size().is_some() {
+                    size
+                } else {
+                    size + 8
+                }
+            }
+
+            fn _to_bytes(&self) -> Vec<u8> {
+                let mut result = vec![];
+                $(result.extend(self.$i._to_bytes());)+
+                if Self::_fixed_size().is_some() {
+                    result
+                } else {
+                    with_prefix(result)
+                }
+            }
+
+            fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                let left = bytes;
+                $(let ($v, left) = consume_and_decode::<$t>(left)?;)+
+                if !left.is_empty() {
+                    return Err(DecodeError::TrailingBytes(left.len()));
+                }
+                Ok(($($v,)+))
+            }
+
+            fn _fixed_size() -> Option<u64> {
+                Some(0 $(+ $t::_fixed_size()?)+)
+            }
+
+            fn _eq(&self, other: &Self) -> bool {
+                $(self.$i._eq(&other.$i))&&+
+            }
+
+            fn _default() -> Self {
+                ($($t::_default(),)+)
+            }
+        }
+    };
+}
+
+impl_on_chain_for_tuple!(A a 0);
+impl_on_chain_for_tuple!(A a 0, B b 1);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2, D d 3);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2, D d 3, E e 4);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2, D d 3, E e 4, F f 5);
+
 impl<T: OnChain> OnChain for Vec<T> {
     fn _capacity(&self) -> u64 {
         let prefix = 8;
@@ -87,26 +465,25 @@ impl<T: OnChain> OnChain for Vec<T> {
     }
 
     fn _to_bytes(&self) -> Vec<u8> {
-        let mut total_capacity: u64 = 0;
         let mut bytes = vec![];
-        self.iter().for_each(|element| {
-            total_capacity += element._capacity();
-            bytes.extend(element._to_bytes());
-        });
-        let mut res = total_capacity.to_le_bytes().to_vec();
-        res.extend(bytes);
-        res
+        self.iter()
+            .for_each(|element| bytes.extend(element._to_bytes()));
+        with_prefix(bytes)
     }
 
-    fn _from_bytes(bytes: &[u8]) -> Option<Self> {
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut result: Vec<T> = vec![];
         let mut bytes = bytes;
-        while bytes.len() > 0 {
+        while !bytes.is_empty() {
             let (item, left) = consume_and_decode::<T>(bytes)?;
+            // Zero-sized items would be decoded from the same bytes forever
+            if left.len() == bytes.len() {
+                return Err(DecodeError::TrailingBytes(left.len()));
+            }
             result.push(item);
             bytes = left;
         }
-        Some(result)
+        Ok(result)
     }
 
     fn _fixed_size() -> Option<u64> {
@@ -126,7 +503,7 @@ impl<T: OnChain> OnChain for Vec<T> {
             }
         }
 
-        return true;
+        true
     }
 
     fn _default() -> Self {
@@ -136,36 +513,36 @@ impl<T: OnChain> OnChain for Vec<T> {
 
 // It is almost the same as
 // #[derive(OnChain)]
+// #[onchain(encoding = "molecule")]
 pub struct OnChainWrapper {
     pub idx: u8,
+    // `_version()` of the type when `data` is written
+    pub version: u8,
     pub data: Vec<u8>,
 }
 
+// The wrapper idx of the registry, the cell created by the genesis besides the
+// state cells. Its data is the `Vec<[u8; 32]>` of the type hashes of the
+// contracts that `_entry` may exec, so an app has at most 255 types.
+pub const REGISTRY_IDX: u8 = u8::MAX;
+
+// The data of every cell of an app, which is the Molecule table
+// `OnChainWrapper`, see `write_schema`
 impl OnChain for OnChainWrapper {
     fn _capacity(&self) -> u64 {
-        self.idx._capacity() + self.data._capacity()
+        Self::encoded_len(self.data.len() as u64)
     }
     fn _to_bytes(&self) -> Vec<u8> {
-        let mut result = Vec::with_capacity(self._capacity() as usize);
-        result.extend(<u8 as OnChain>::_to_bytes(&self.idx));
-        result.extend(<Vec<u8> as OnChain>::_to_bytes(&self.data));
-        if let Some(_) = OnChainWrapper::_fixed_size() {
-            result
-        } else {
-            let mut prefix: Vec<u8> = result.len().to_le_bytes().to_vec();
-            prefix.extend(result);
-            prefix
-        }
+        self._to_molecule()
     }
-    fn _from_bytes(bytes: &[u8]) -> Option<Self> {
-        let left = bytes;
-        let (idx, left) = consume_and_decode::<u8>(left)?;
-        let (data, _) = consume_and_decode::<Vec<u8>>(left)?;
-        Some(Self { idx, data })
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::_from_molecule(bytes)
     }
     fn _fixed_size() -> Option<u64> {
-        let size = <u8 as OnChain>::_fixed_size()? + <Vec<u8> as OnChain>::_fixed_size()?;
-        Some(size)
+        None
+    }
+    fn _delimited_len(bytes: &[u8]) -> Result<Option<u64>, DecodeError> {
+        Ok(Some(molecule_u32(bytes, 0)? as u64))
     }
     fn _id() -> Option<&'static str> {
         None
@@ -174,6 +551,9 @@ impl OnChain for OnChainWrapper {
         if !self.idx._eq(&other.idx) {
             return false;
         }
+        if !self.version._eq(&other.version) {
+            return false;
+        }
         if !self.data._eq(&other.data) {
             return false;
         }
@@ -182,55 +562,618 @@ impl OnChain for OnChainWrapper {
     fn _default() -> Self {
         Self {
             idx: <u8 as OnChain>::_default(),
+            version: <u8 as OnChain>::_default(),
             data: <Vec<u8> as OnChain>::_default(),
         }
     }
 }
-// impl OnChain for String {
-//     fn _capacity(&self) -> u64 {
-//         self.as_bytes().len() as u64 + 8
-//     }
+impl OnChainWrapper {
+    // The length of the encoded wrapper of a state of `state_len` bytes,
+    // which is the cell data of the state cell: the total size and the 3
+    // offsets of the table, idx, version, and the item count of `data`
+    pub fn encoded_len(state_len: u64) -> u64 {
+        let header = 4 + 3 * 4;
+        header + 1 + 1 + 4 + state_len
+    }
+}
+
+impl OnChain for String {
+    fn _capacity(&self) -> u64 {
+        self.len() as u64 + 8
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut prefix = (self.len() as u64).to_le_bytes().to_vec();
+        prefix.extend(self.as_bytes());
+        prefix
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn _default() -> Self {
+        String::new()
+    }
+}
+
+// A byte string. It is encoded the same as `Vec<u8>`, but it is copied as a
+// whole instead of byte by byte.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bytes(pub Vec<u8>);
+
+impl core::ops::Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Self {
+        Bytes(bytes.to_vec())
+    }
+}
+
+impl OnChain for Bytes {
+    fn _capacity(&self) -> u64 {
+        self.0.len() as u64 + 8
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut prefix = (self.0.len() as u64).to_le_bytes().to_vec();
+        prefix.extend(&self.0);
+        prefix
+    }
 
-//     fn _to_bytes(&self) -> Vec<u8> {
-//         let bytes = self.as_bytes().to_vec();
-//         let mut prefix = bytes.len().to_le_bytes().to_vec();
-//         prefix.extend(bytes);
-//         prefix
-//     }
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Bytes(bytes.to_vec()))
+    }
 
-//     fn _from_bytes(bytes: &[u8]) -> Option<Self> {
-//         String::from_utf8(bytes.to_vec()).ok()
-//     }
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self == other
+    }
 
-//     fn _fixed_size() -> Option<u64> {
-//         None
-//     }
+    fn _default() -> Self {
+        Bytes(vec![])
+    }
+}
 
-//     fn _eq(&self, other: &Self) -> bool {
-//         self == other
-//     }
-// }
+fn with_prefix(content: Vec<u8>) -> Vec<u8> {
+    let mut prefix = (content.len() as u64).to_le_bytes().to_vec();
+    prefix.extend(content);
+    prefix
+}
 
-pub fn consume_and_decode<T: OnChain>(bytes: &[u8]) -> Option<(T, &[u8])> {
+// Decode the value at the head of `bytes`, returning it and the bytes after it.
+pub fn consume_and_decode<T: OnChain>(bytes: &[u8]) -> Result<(T, &[u8]), DecodeError> {
     if let Some(capacity) = T::_fixed_size() {
         let end = capacity as usize;
-        let item = T::_from_bytes(&bytes[0..end])?;
-        Some((item, &bytes[end..]))
+        if bytes.len() < end {
+            return Err(DecodeError::Truncated);
+        }
+        let item = T::_from_bytes(&bytes[..end])?;
+        Ok((item, &bytes[end..]))
+    } else if let Some(size) = T::_delimited_len(bytes)? {
+        if size > bytes.len() as u64 {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+        let (content, left) = bytes.split_at(size as usize);
+        let item = T::_from_bytes(content)?;
+        Ok((item, left))
     } else {
-        let size: [u8; 8] = bytes[0..8].try_into().unwrap();
-        let end = usize::from_le_bytes(size);
-        let item = T::_from_bytes(&bytes[8..end + 8])?;
-        Some((item, &bytes[end + 8..]))
+        if bytes.len() < 8 {
+            return Err(DecodeError::Truncated);
+        }
+        let (size, left) = bytes.split_at(8);
+        let size = u64::from_le_bytes(size.try_into().map_err(|_| DecodeError::Truncated)?);
+        if size > left.len() as u64 {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+        let (content, left) = left.split_at(size as usize);
+        let item = T::_from_bytes(content)?;
+        Ok((item, left))
+    }
+}
+
+// Decode `bytes` holding exactly one value, like the data of a cell.
+pub fn decode<T: OnChain>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let (item, left) = consume_and_decode::<T>(bytes)?;
+    if !left.is_empty() {
+        return Err(DecodeError::TrailingBytes(left.len()));
+    }
+    Ok(item)
+}
+
+// Decode the `data` of an `OnChainWrapper` written with `version`, migrating
+// it to the current version of `T`
+pub fn decode_versioned<T: OnChain>(version: u8, bytes: &[u8]) -> Result<T, DecodeError> {
+    T::_from_versioned_bytes(version, bytes)
+}
+
+// The Molecule encoding of a type, which other CKB tools can read, see
+// `#[onchain(encoding = "molecule")]`. Tuples and maps have none.
+pub trait Molecule: Sized {
+    fn _to_molecule(&self) -> Vec<u8>;
+
+    // `bytes` must be consumed entirely
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError>;
+
+    // Some if the type is a Molecule byte, array or struct
+    fn _molecule_size() -> Option<usize>;
+
+    // The name in the `.mol` schema, like `Uint32`
+    fn _molecule_name() -> String;
+
+    // Add the declarations of the type and the ones it refers to, in the
+    // order the schema needs
+    fn _molecule_decls(_decls: &mut Vec<String>) {}
+}
+
+// Add `decl` if it is not yet declared
+pub fn molecule_declare(decls: &mut Vec<String>, decl: String) {
+    if !decls.contains(&decl) {
+        decls.push(decl);
+    }
+}
+
+// `byte` is the only name of the schema starting in lower case, so capitalize
+// names before composing them, like `ByteOpt`
+pub fn molecule_compose(name: &str, suffix: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => format!("{}{}{}", c.to_ascii_uppercase(), chars.as_str(), suffix),
+        None => String::from(suffix),
+    }
+}
+
+fn molecule_u32(bytes: &[u8], at: usize) -> Result<usize, DecodeError> {
+    let slice = bytes.get(at..at + 4).ok_or(DecodeError::Truncated)?;
+    let mut n = [0u8; 4];
+    n.copy_from_slice(slice);
+    Ok(u32::from_le_bytes(n) as usize)
+}
+
+// A table or a dynvec: the total size, the offsets of the items and the items
+pub fn molecule_table(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let header = 4 + 4 * items.len();
+    let total = header + items.iter().map(|i| i.len()).sum::<usize>();
+    let mut result = Vec::with_capacity(total);
+    result.extend((total as u32).to_le_bytes());
+    let mut offset = header;
+    items.iter().for_each(|item| {
+        result.extend((offset as u32).to_le_bytes());
+        offset += item.len();
+    });
+    items.into_iter().for_each(|item| result.extend(item));
+    result
+}
+
+// The items of a table or a dynvec. `count` is the expected number of fields
+// of a table, `None` for a dynvec.
+pub fn molecule_table_items(bytes: &[u8], count: Option<usize>) -> Result<Vec<&[u8]>, DecodeError> {
+    let total = molecule_u32(bytes, 0)?;
+    if total != bytes.len() {
+        return Err(DecodeError::BadLengthPrefix);
+    }
+    let header = if total == 4 {
+        4
+    } else {
+        molecule_u32(bytes, 4)?
+    };
+    if header % 4 != 0 || header < 4 || header > total {
+        return Err(DecodeError::BadLengthPrefix);
+    }
+    // Nothing but the total size without items
+    if header == 4 && total != 4 {
+        return Err(DecodeError::TrailingBytes(total - 4));
+    }
+    let n = header / 4 - 1;
+    if let Some(count) = count {
+        if n != count {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+    }
+    let mut offsets = Vec::with_capacity(n + 1);
+    for i in 0..n {
+        offsets.push(molecule_u32(bytes, 4 + 4 * i)?);
+    }
+    offsets.push(total);
+    // The first offset is the header, the others must not go back or past
+    // the end
+    let mut items = Vec::with_capacity(n);
+    for i in 0..n {
+        let (start, end) = (offsets[i], offsets[i + 1]);
+        if start > end || end > total {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+        items.push(&bytes[start..end]);
+    }
+    Ok(items)
+}
+
+// A fixvec: the item count and the fixed-size items
+pub fn molecule_fixvec(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut result = (items.len() as u32).to_le_bytes().to_vec();
+    items.into_iter().for_each(|item| result.extend(item));
+    result
+}
+
+pub fn molecule_fixvec_items(bytes: &[u8], size: usize) -> Result<Vec<&[u8]>, DecodeError> {
+    let n = molecule_u32(bytes, 0)?;
+    // Like `Vec<T>`, zero-sized items cannot be told apart from no items
+    if size == 0 && n > 0 {
+        return Err(DecodeError::BadLengthPrefix);
+    }
+    let expected = n.checked_mul(size).and_then(|s| s.checked_add(4));
+    match expected {
+        Some(expected) if expected == bytes.len() => {}
+        Some(expected) if expected < bytes.len() => {
+            return Err(DecodeError::TrailingBytes(bytes.len() - expected))
+        }
+        _ => return Err(DecodeError::Truncated),
+    }
+    Ok((0..n)
+        .map(|i| &bytes[4 + i * size..4 + (i + 1) * size])
+        .collect())
+}
+
+// The fields of a struct or the items of an array, one after another
+pub fn molecule_struct_fields<'a>(
+    bytes: &'a [u8],
+    sizes: &[usize],
+) -> Result<Vec<&'a [u8]>, DecodeError> {
+    let total = sizes.iter().sum::<usize>();
+    if bytes.len() < total {
+        return Err(DecodeError::Truncated);
+    }
+    if bytes.len() > total {
+        return Err(DecodeError::TrailingBytes(bytes.len() - total));
+    }
+    let mut start = 0;
+    Ok(sizes
+        .iter()
+        .map(|size| {
+            let field = &bytes[start..start + size];
+            start += size;
+            field
+        })
+        .collect())
+}
+
+// The size of a struct with the fields of `sizes`, `None` if it has to be a
+// table. A struct has at least one field, all of them fixed-size.
+pub fn molecule_struct_size(sizes: &[Option<usize>]) -> Option<usize> {
+    if sizes.is_empty() {
+        return None;
+    }
+    sizes
+        .iter()
+        .try_fold(0, |total, size| Some(total + (*size)?))
+}
+
+// A union: the item id and the item
+pub fn molecule_union(id: u32, item: Vec<u8>) -> Vec<u8> {
+    let mut result = id.to_le_bytes().to_vec();
+    result.extend(item);
+    result
+}
+
+// The length of the struct of `size` or the table at the head of `bytes`
+pub fn molecule_item_len(bytes: &[u8], size: Option<usize>) -> Result<usize, DecodeError> {
+    match size {
+        Some(size) => Ok(size),
+        None => molecule_u32(bytes, 0),
+    }
+}
+
+pub fn molecule_union_item(bytes: &[u8]) -> Result<(u32, &[u8]), DecodeError> {
+    let id = molecule_u32(bytes, 0)?;
+    Ok((id as u32, &bytes[4..]))
+}
+
+// The fields of a derived struct, or a variant of a derived enum. It is a
+// Molecule struct if every field is fixed-size, or a table otherwise.
+pub fn molecule_fields_decl(name: &str, fields: &[(String, String)], fixed: bool) -> String {
+    let kind = if fixed { "struct" } else { "table" };
+    let mut decl = format!("{} {} {{\n", kind, name);
+    fields.iter().for_each(|(field, ty)| {
+        decl.push_str(&format!("    {}: {},\n", field, ty));
+    });
+    decl.push('}');
+    decl
+}
+
+macro_rules! impl_molecule_for_builtin {
+    ($t:ty, $name:literal) => {
+        impl Molecule for $t {
+            fn _to_molecule(&self) -> Vec<u8> {
+                <$t as OnChain>::_to_bytes(self)
+            }
+
+            fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+                <$t as OnChain>::_from_bytes(bytes)
+            }
+
+            fn _molecule_size() -> Option<usize> {
+                Some(core::mem::size_of::<$t>())
+            }
+
+            fn _molecule_name() -> String {
+                String::from($name)
+            }
+
+            fn _molecule_decls(decls: &mut Vec<String>) {
+                if $name != "byte" {
+                    let size = core::mem::size_of::<$t>();
+                    molecule_declare(decls, format!("array {} [byte; {}];", $name, size));
+                }
+            }
+        }
+    };
+}
+
+impl_molecule_for_builtin!(u8, "byte");
+impl_molecule_for_builtin!(u16, "Uint16");
+impl_molecule_for_builtin!(u32, "Uint32");
+impl_molecule_for_builtin!(u64, "Uint64");
+impl_molecule_for_builtin!(u128, "Uint128");
+impl_molecule_for_builtin!(i8, "byte");
+impl_molecule_for_builtin!(i16, "Int16");
+impl_molecule_for_builtin!(i32, "Int32");
+impl_molecule_for_builtin!(i64, "Int64");
+impl_molecule_for_builtin!(i128, "Int128");
+impl_molecule_for_builtin!(bool, "byte");
+
+impl Molecule for String {
+    fn _to_molecule(&self) -> Vec<u8> {
+        molecule_fixvec(self.bytes().map(|b| vec![b]).collect())
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = <Bytes as Molecule>::_from_molecule(bytes)?;
+        String::from_utf8(bytes.0).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        String::from("Bytes")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        <Bytes as Molecule>::_molecule_decls(decls)
+    }
+}
+
+impl Molecule for Bytes {
+    fn _to_molecule(&self) -> Vec<u8> {
+        let mut result = (self.len() as u32).to_le_bytes().to_vec();
+        result.extend(self.iter());
+        result
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let items = molecule_fixvec_items(bytes, 1)?;
+        Ok(Bytes(items.concat()))
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        String::from("Bytes")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        molecule_declare(decls, String::from("vector Bytes <byte>;"));
+    }
+}
+
+// A fixvec if the items are fixed-size, or a dynvec otherwise. `Vec<u8>` is
+// named `Bytes`.
+impl<T: Molecule> Molecule for Vec<T> {
+    fn _to_molecule(&self) -> Vec<u8> {
+        let items = self.iter().map(|t| t._to_molecule()).collect();
+        if T::_molecule_size().is_some() {
+            molecule_fixvec(items)
+        } else {
+            molecule_table(items)
+        }
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let items = match T::_molecule_size() {
+            Some(size) => molecule_fixvec_items(bytes, size)?,
+            None => molecule_table_items(bytes, None)?,
+        };
+        items.into_iter().map(T::_from_molecule).collect()
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        let item = T::_molecule_name();
+        if item == "byte" {
+            String::from("Bytes")
+        } else {
+            molecule_compose(&item, "Vec")
+        }
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        T::_molecule_decls(decls);
+        let decl = format!(
+            "vector {} <{}>;",
+            Self::_molecule_name(),
+            T::_molecule_name()
+        );
+        molecule_declare(decls, decl);
+    }
+}
+
+impl Molecule for OnChainWrapper {
+    fn _to_molecule(&self) -> Vec<u8> {
+        molecule_table(vec![
+            self.idx._to_molecule(),
+            self.version._to_molecule(),
+            self.data._to_molecule(),
+        ])
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let fields = molecule_table_items(bytes, Some(3))?;
+        Ok(Self {
+            idx: u8::_from_molecule(fields[0])?,
+            version: u8::_from_molecule(fields[1])?,
+            data: Vec::<u8>::_from_molecule(fields[2])?,
+        })
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        String::from("OnChainWrapper")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        <Vec<u8> as Molecule>::_molecule_decls(decls);
+        let fields = [("idx", "byte"), ("version", "byte"), ("data", "Bytes")]
+            .iter()
+            .map(|(field, ty)| (String::from(*field), String::from(*ty)))
+            .collect::<Vec<_>>();
+        molecule_declare(
+            decls,
+            molecule_fields_decl("OnChainWrapper", &fields, false),
+        );
+    }
+}
+
+impl<T: Molecule> Molecule for Option<T> {
+    fn _to_molecule(&self) -> Vec<u8> {
+        match self {
+            Some(t) => t._to_molecule(),
+            None => vec![],
+        }
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(T::_from_molecule(bytes)?))
+        }
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        molecule_compose(&T::_molecule_name(), "Opt")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        T::_molecule_decls(decls);
+        let decl = format!(
+            "option {} ({});",
+            Self::_molecule_name(),
+            T::_molecule_name()
+        );
+        molecule_declare(decls, decl);
+    }
+}
+
+// An array if the items are fixed-size, like `Byte32`, or a dynvec otherwise
+impl<T: Molecule, const N: usize> Molecule for [T; N] {
+    fn _to_molecule(&self) -> Vec<u8> {
+        let items: Vec<Vec<u8>> = self.iter().map(|t| t._to_molecule()).collect();
+        if T::_molecule_size().is_some() {
+            items.concat()
+        } else {
+            molecule_table(items)
+        }
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let items = match T::_molecule_size() {
+            Some(size) => molecule_struct_fields(bytes, &[size; N])?,
+            None => molecule_table_items(bytes, Some(N))?,
+        };
+        let result = items
+            .into_iter()
+            .map(T::_from_molecule)
+            .collect::<Result<Vec<T>, _>>()?;
+        result.try_into().map_err(|_| DecodeError::Truncated)
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        Some(T::_molecule_size()? * N)
+    }
+
+    fn _molecule_name() -> String {
+        molecule_compose(&T::_molecule_name(), &format!("{}", N))
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        T::_molecule_decls(decls);
+        let decl = if T::_molecule_size().is_some() {
+            format!(
+                "array {} [{}; {}];",
+                Self::_molecule_name(),
+                T::_molecule_name(),
+                N
+            )
+        } else {
+            format!(
+                "vector {} <{}>;",
+                Self::_molecule_name(),
+                T::_molecule_name()
+            )
+        };
+        molecule_declare(decls, decl);
     }
 }
 
 use ckb_std::ckb_constants::Source;
 use ckb_std::high_level::load_cell_data;
+use ckb_std::high_level::load_cell;
+use ckb_std::high_level::load_cell_type;
+use ckb_std::high_level::load_input;
+use ckb_std::high_level::load_script;
 use ckb_std::high_level::exec_cell;
 use ckb_std::high_level::load_witness_args;
 use ckb_std::syscalls::SysError;
+use ckb_standalone_types::packed::{CellOutput, WitnessArgs};
 use ckb_standalone_types::prelude::Entity;
 use ckb_standalone_types::core::ScriptHashType;
+use blake2b_rs::Blake2bBuilder;
 
 pub fn load_cell_deps_data(idx: usize) -> Result<Vec<u8>, SysError> {
     load_cell_data(idx, Source::CellDep)
@@ -244,60 +1187,280 @@ pub fn load_output_data(idx: usize) -> Result<Vec<u8>, SysError> {
     load_cell_data(idx, Source::Output)
 }
 
+pub fn load_group_output_data(idx: usize) -> Result<Vec<u8>, SysError> {
+    load_cell_data(idx, Source::GroupOutput)
+}
+
+// Every state cell of the app in the inputs or the outputs is paired with the
+// same instance at the same index on the other side, so that a contract can
+// neither swap keyed instances nor burn or mint state cells
+pub fn check_instance_keys() -> Result<bool, SysError> {
+    check_instances(true, |input, output| {
+        input.type_().as_slice() == output.type_().as_slice()
+    })
+}
+
+// The outputs keep the locks of the inputs, so that the operator cannot take
+// the cells of an owner
+pub fn check_instance_locks() -> Result<bool, SysError> {
+    check_instances(false, |input, output| {
+        input.lock().as_slice() == output.lock().as_slice()
+    })
+}
+
+// Whether `same` holds for every state cell of the app in the inputs, or in
+// the outputs too if `both_sides`, and the cell at its index on the other side
+fn check_instances(
+    both_sides: bool,
+    same: impl Fn(&CellOutput, &CellOutput) -> bool,
+) -> Result<bool, SysError> {
+    let code_hash = load_script()?.code_hash();
+    let is_state = |cell: &Option<CellOutput>| {
+        cell.as_ref()
+            .and_then(|c| c.type_().to_opt())
+            .map_or(false, |s| s.code_hash().as_slice() == code_hash.as_slice())
+    };
+    let mut idx = 0;
+    loop {
+        let input = load_cell_at(idx, Source::Input)?;
+        let output = load_cell_at(idx, Source::Output)?;
+        if input.is_none() && output.is_none() {
+            return Ok(true);
+        }
+        if is_state(&input) || (both_sides && is_state(&output)) {
+            match (input, output) {
+                (Some(input), Some(output)) if same(&input, &output) => {}
+                _ => return Ok(false),
+            }
+        }
+        idx += 1;
+    }
+}
+
+// Whether the registry is among the cells of the group, which only the genesis
+// creates and nothing spends
+pub fn has_group_registry() -> Result<bool, SysError> {
+    for source in [Source::GroupInput, Source::GroupOutput] {
+        let mut idx = 0;
+        loop {
+            let data = match load_cell_data(idx, source) {
+                Ok(data) => data,
+                Err(SysError::IndexOutOfBound) => break,
+                Err(err) => return Err(err),
+            };
+            if decode::<OnChainWrapper>(&data).map_or(false, |w| w.idx == REGISTRY_IDX) {
+                return Ok(true);
+            }
+            idx += 1;
+        }
+    }
+    Ok(false)
+}
+
+// Whether `code_hash` is one of the contracts in the registry of the app. The
+// registry is the cell dep whose type script is the one of the app without an
+// instance key, and whose wrapper idx is `REGISTRY_IDX`. Only the genesis can
+// create such a cell, see `check_new_instances`.
+pub fn is_registered(code_hash: &[u8]) -> Result<bool, SysError> {
+    let script = load_script()?;
+    let args = script.args().raw_data();
+    let app_args = match args.get(..32) {
+        Some(app_args) => app_args,
+        None => return Ok(false),
+    };
+    let mut idx = 0;
+    loop {
+        let dep = match load_cell_type(idx, Source::CellDep) {
+            Ok(dep) => dep,
+            Err(SysError::IndexOutOfBound) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let is_app = dep.map_or(false, |s| {
+            s.code_hash().as_slice() == script.code_hash().as_slice()
+                && s.hash_type().as_slice() == script.hash_type().as_slice()
+                && s.args().raw_data().as_ref() == app_args
+        });
+        if is_app {
+            let data = load_cell_data(idx, Source::CellDep)?;
+            if let Ok(wrapper) = decode::<OnChainWrapper>(&data) {
+                if wrapper.idx == REGISTRY_IDX {
+                    let hashes = decode::<Vec<[u8; 32]>>(&wrapper.data).unwrap_or_default();
+                    return Ok(hashes.iter().any(|hash| hash.as_slice() == code_hash));
+                }
+            }
+        }
+        idx += 1;
+    }
+}
+
+// Whether the tx spends state cells of the group
+pub fn has_group_inputs() -> Result<bool, SysError> {
+    Ok(load_cell_at(0, Source::GroupInput)?.is_some())
+}
+
+fn load_cell_at(idx: usize, source: Source) -> Result<Option<CellOutput>, SysError> {
+    match load_cell(idx, source) {
+        Ok(cell) => Ok(Some(cell)),
+        Err(SysError::IndexOutOfBound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+// The instance key after the 32 bytes of the app args, empty unless the
+// state cells of the group are a keyed instance
+pub fn load_instance_key() -> Result<Vec<u8>, SysError> {
+    let args = load_script()?.args().raw_data();
+    Ok(args.get(32..).map_or(vec![], |key| key.to_vec()))
+}
+
+// New state cells cannot take the ids or keys of existing ones. The app args
+// are the hash of the first input of the genesis and the app name in its user
+// input, and that input is spent for good. A key is the hash of the first input
+// of the tx creating the instance and the output index, like the type id, and
+// a tx creates one cell of a key.
+pub fn check_new_instances() -> Result<bool, SysError> {
+    let script = load_script()?;
+    let args = script.args().raw_data();
+    if args.len() < 32 {
+        return Ok(false);
+    }
+    let (app_args, key) = args.split_at(32);
+    let first_input = load_input(0, Source::Input)?;
+    if key.is_empty() {
+        let input_hash = blake2b_256(&[first_input.as_slice()]);
+        let app_name = load_user_input()?;
+        return Ok(blake2b_256(&[&input_hash, &app_name]) == app_args);
+    }
+    if load_cell_at(1, Source::GroupOutput)?.is_some() {
+        return Ok(false);
+    }
+    let mut idx = 0;
+    loop {
+        match load_cell_type(idx, Source::Output)? {
+            Some(s) if s.as_slice() == script.as_slice() => break,
+            _ => idx += 1,
+        }
+    }
+    let index = (idx as u64).to_le_bytes();
+    Ok(blake2b_256(&[first_input.as_slice(), &index]) == key)
+}
+
+// The blake2b of CKB
+fn blake2b_256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut blake2b = Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build();
+    parts.iter().for_each(|part| blake2b.update(part));
+    let mut hash = [0u8; 32];
+    blake2b.finalize(&mut hash);
+    hash
+}
+
 pub fn load_user_input() -> Result<Vec<u8>, SysError> {
     let witness_arg = load_witness_args(0, Source::Input)?;
-    if let Some(b) = witness_arg.input_type().to_opt() {
-        Ok(b.as_slice().to_vec())
-    } else {
-        Ok(vec![])
-    }
+    Ok(witness_user_input(&witness_arg))
 }
 
 pub fn load_exec_script() -> Result<Vec<u8>, SysError> {
     let witness_arg = load_witness_args(0, Source::Input)?;
-    if let Some(b) = witness_arg.output_type().to_opt() {
-        Ok(b.as_slice().to_vec())
-    } else {
-        Ok(vec![])
-    }
+    Ok(witness_exec_script(&witness_arg))
 }
 
 pub fn exec_script(code_hash: &[u8]) -> Result<u64, SysError> {
     let argv = vec![];
     exec_cell(code_hash, ScriptHashType::Type, 0, 0, &argv)
 }
-use crate as ckboots ; pub struct Frog
-{ pub physical : u8, pub traval_cnt : u8, } impl ckboots :: OnChain for Frog
+// Where `build_tx` puts the user input and the type hash of the contract to
+// exec, which are the `input_type` and `output_type` of the first witness.
+// Included by `utils` and shipped to the types crate too, see `get_utils`, so
+// that both sides read the witness the same way. The molecule header of the
+// bytes is not a part of them.
+
+pub fn witness_user_input(witness: &WitnessArgs) -> Vec<u8> {
+    match witness.input_type().to_opt() {
+        Some(b) => b.raw_data().to_vec(),
+        None => Vec::new(),
+    }
+}
+
+pub fn witness_exec_script(witness: &WitnessArgs) -> Vec<u8> {
+    match witness.output_type().to_opt() {
+        Some(b) => b.raw_data().to_vec(),
+        None => Vec::new(),
+    }
+}
+pub struct Pond { pub lilies : u8, } impl ckboots :: OnChain for Pond
 {
     fn _capacity(& self) -> u64
-    { self.physical._capacity() + self.traval_cnt._capacity() } fn
-    _to_bytes(& self) -> Vec < u8 >
     {
-        let mut result = Vec :: with_capacity(self._capacity() as usize) ;
+        let size = 0 + self.lilies._capacity(); if Self ::
+        _fixed_size().is_some() { size } else { size + 8 }
+    } fn _to_bytes(& self) -> Vec < u8 >
+    {
+        let mut result = Vec :: with_capacity(self._capacity() as usize);
+        result.extend(< u8 as ckboots :: OnChain > ::
+        _to_bytes(& self.lilies)); if let Some(_) = Self :: _fixed_size()
+        { result } else
+        {
+            let mut prefix : Vec < u8 > =
+            (result.len() as u64).to_le_bytes().to_vec();
+            prefix.extend(result); prefix
+        }
+    } fn _from_bytes(bytes : & [u8]) -> Result < Self, ckboots :: DecodeError
+    >
+    {
+        let left = bytes; let (lilies, left) = ckboots :: consume_and_decode
+        :: < u8 > (left) ? ; if ! left.is_empty()
+        { return Err(ckboots :: DecodeError :: TrailingBytes(left.len())); }
+        Ok(Self { lilies : lilies, })
+    } fn _fixed_size() -> Option < u64 >
+    {
+        let size = 0 + < u8 as ckboots :: OnChain > :: _fixed_size() ? ;
+        Some(size)
+    } fn _id() -> Option < & 'static str > { Some("pond") } fn
+    _eq(& self, other : & Self) -> bool
+    { if ! self.lilies._eq(& other.lilies) { return false; } true } fn
+    _default() -> Self
+    { Self { lilies : < u8 as ckboots :: OnChain > :: _default(), } }
+} impl Pond
+{ pub fn onchain_new(lilies : u8) -> Self { Self { lilies : lilies } } }pub struct Frog { pub physical : u8, pub traval_cnt : u8, } impl ckboots ::
+OnChain for Frog
+{
+    fn _capacity(& self) -> u64
+    {
+        let size = 0 + self.physical._capacity() +
+        self.traval_cnt._capacity(); if Self :: _fixed_size().is_some()
+        { size } else { size + 8 }
+    } fn _to_bytes(& self) -> Vec < u8 >
+    {
+        let mut result = Vec :: with_capacity(self._capacity() as usize);
         result.extend(< u8 as ckboots :: OnChain > ::
-        _to_bytes(& self.physical)) ;
+        _to_bytes(& self.physical));
         result.extend(< u8 as ckboots :: OnChain > ::
-        _to_bytes(& self.traval_cnt)) ; if let Some(_) = Frog :: _fixed_size()
+        _to_bytes(& self.traval_cnt)); if let Some(_) = Self :: _fixed_size()
         { result } else
         {
-            let mut prefix : Vec < u8 > = result.len().to_le_bytes().to_vec()
-            ; prefix.extend(result) ; prefix
+            let mut prefix : Vec < u8 > =
+            (result.len() as u64).to_le_bytes().to_vec();
+            prefix.extend(result); prefix
         }
-    } fn _from_bytes(bytes : & [u8]) -> Option < Self >
+    } fn _from_bytes(bytes : & [u8]) -> Result < Self, ckboots :: DecodeError
+    >
     {
-        let left = bytes ; let(physical, left) = ckboots :: consume_and_decode
-        :: < u8 > (left) ? ; let(traval_cnt, left) = ckboots ::
-        consume_and_decode :: < u8 > (left) ? ;
-        Some(Self { physical, traval_cnt, })
+        let left = bytes; let (physical, left) = ckboots :: consume_and_decode
+        :: < u8 > (left) ? ; let (traval_cnt, left) = ckboots ::
+        consume_and_decode :: < u8 > (left) ? ; if ! left.is_empty()
+        { return Err(ckboots :: DecodeError :: TrailingBytes(left.len())); }
+        Ok(Self { physical : physical, traval_cnt : traval_cnt, })
     } fn _fixed_size() -> Option < u64 >
     {
-        let size = < u8 as ckboots :: OnChain > :: _fixed_size() ? + < u8 as
-        ckboots :: OnChain > :: _fixed_size() ? ; Some(size)
+        let size = 0 + < u8 as ckboots :: OnChain > :: _fixed_size() ? + < u8
+        as ckboots :: OnChain > :: _fixed_size() ? ; Some(size)
     } fn _id() -> Option < & 'static str > { Some("frog") } fn
     _eq(& self, other : & Self) -> bool
     {
-        if! self.physical._eq(& other.physical) { return false ; } if!
-        self.traval_cnt._eq(& other.traval_cnt) { return false ; } true
+        if ! self.physical._eq(& other.physical) { return false; } if !
+        self.traval_cnt._eq(& other.traval_cnt) { return false; } true
     } fn _default() -> Self
     {
         Self
@@ -309,5 +1472,5 @@ use crate as ckboots ; pub struct Frog
 } impl Frog
 {
     pub fn onchain_new(physical : u8, traval_cnt : u8) -> Self
-    { Self { physical, traval_cnt } }
+    { Self { physical : physical, traval_cnt : traval_cnt } }
 }
\ No newline at end of file
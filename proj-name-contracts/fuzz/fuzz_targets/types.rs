@@ -0,0 +1,29 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use types::*;
+
+// Decode `data` like the contracts do with the data of a state cell, and as
+// the current version of the state, whose encoding has to be `data` then
+fn check<T: OnChain>(data: &[u8]) {
+    if let Ok(wrapper) = decode::<OnChainWrapper>(data) {
+        if let Ok(state) = decode_versioned::<T>(wrapper.version, &wrapper.data) {
+            assert_eq!(state._capacity(), state._to_bytes().len() as u64);
+        }
+    }
+    if let Ok(state) = decode::<T>(data) {
+        assert_eq!(state._to_bytes(), data, "the encoding is not canonical");
+    }
+}
+
+// The first byte picks the registered type
+const CHECKS: &[fn(&[u8])] = &[
+    check::<Pond>,
+    check::<Frog>,
+];
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((selector, data)) = data.split_first() {
+        CHECKS[*selector as usize % CHECKS.len()](data);
+    }
+});
@@ -1,24 +1,1022 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
-use ckb_types::packed::{OutPoint, TransactionView};
+use ckb_jsonrpc_types::{OutPoint as JsonOutPoint, Script as JsonScript};
+use ckb_sdk::traits::SecpCkbRawKeySigner;
+use ckb_sdk::unlock::{ScriptSigner, SecpSighashScriptSigner};
+use ckb_sdk::{ScriptGroup, ScriptGroupType};
+use ckb_types::bytes::Bytes;
+use ckb_types::core::{Capacity, DepType, TransactionBuilder, TransactionView};
+use ckb_types::packed::{Byte32, CellDep, CellInput, CellOutput, OutPoint, Script, WitnessArgs};
+use ckb_types::prelude::{Builder, Entity, Pack, Unpack};
+use ckb_types::H256;
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
 
+use crate::cell_manager::CellManager;
+use crate::client::{Client, ClientError, RpcClient};
+use crate::config::{Config, ConfigError, CONFIG_FILE};
 use crate::contract::ContractResult;
+use crate::dry_run::{DryRunError, MockDeployment};
+use crate::on_chain::{decode, DecodeError, OnChain, OnChainWrapper, REGISTRY_IDX};
+use crate::utils::{
+    get_input_hash, get_instance_key, get_instance_type_script, get_new_instance_key,
+    get_onchain_type_script, get_sighash_lock,
+};
 
-pub struct App {}
+// The on-chain id of a state and its instance key, which is empty unless the
+// type is `#[onchain(keyed)]`
+pub type StateKey = (&'static str, Vec<u8>);
+
+// The id of the registry among the cells of the app, see `REGISTRY_IDX`
+pub const REGISTRY_ID: &str = "_registry";
+
+// The tx creating an instance, its key and the cell it creates
+pub type InstanceTx = (TransactionView, Vec<u8>, HashMap<StateKey, Cell>);
+
+// Shannons per 1000 bytes, the minimal fee rate accepted by the tx pool
+pub(crate) const FEE_RATE: u64 = 1000;
+
+pub struct App {
+    config: Config,
+    client: Box<dyn Client>,
+    // The lock of the operator, derived from `chain.secret_key`
+    lock: Script,
+    deployment: Deployment,
+    // Live state cells, keyed by the on-chain id of the type they hold and
+    // the instance key
+    cells: HashMap<StateKey, Cell>,
+}
 
 impl App {
-    pub fn bootstrap() -> Self {
-        todo!()
+    pub fn new(
+        config: Config,
+        client: Box<dyn Client>,
+        deployment: Deployment,
+        cells: HashMap<StateKey, Cell>,
+    ) -> Self {
+        let lock = get_sighash_lock(&config.chain.secret_key);
+        App {
+            config,
+            client,
+            lock,
+            deployment,
+            cells,
+        }
+    }
+
+    // Build the app from `ckboots.toml` in the working directory. Only debug
+    // mode is supported, where the contracts deployed by capsule are found in
+    // `dev.contracts_dir`. Otherwise build the `Deployment` and use `App::new`.
+    pub fn bootstrap() -> Result<Self, BootstrapError> {
+        Self::bootstrap_with(Config::load(CONFIG_FILE)?)
+    }
+
+    // `bootstrap` with a config loaded from elsewhere
+    pub fn bootstrap_with(config: Config) -> Result<Self, BootstrapError> {
+        if !config.dev.debug {
+            return Err(BootstrapError::Config(ConfigError::Invalid {
+                key: "dev.debug",
+                reason: String::from("the deployment is only known in debug mode"),
+            }));
+        }
+        let dir = config
+            .dev
+            .contracts_dir
+            .as_ref()
+            .ok_or(ConfigError::Missing("dev.contracts_dir"))?;
+        let mut deployment = Deployment::load(dir)?;
+        let mut client = RpcClient::new(&config.chain.rpc);
+        deployment.lock_dep = Some(client.get_sighash_dep()?);
+        Ok(App::new(
+            config,
+            Box::new(client),
+            deployment,
+            HashMap::new(),
+        ))
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn lock(&self) -> &Script {
+        &self.lock
+    }
+
+    pub fn get_cell(&self, id: &'static str) -> Option<&Cell> {
+        self.get_instance_cell(id, &[])
+    }
+
+    // The cell of the instance `key` of a keyed type
+    pub fn get_instance_cell(&self, id: &'static str, key: &[u8]) -> Option<&Cell> {
+        self.cells.get(&(id, key.to_vec()))
+    }
+
+    // Replace the live cells with the ones returned by `build_tx` once the
+    // transaction is committed, like `CellManager::commit` does with the
+    // staged states.
+    pub fn update_cells(&mut self, cells: HashMap<StateKey, Cell>) {
+        self.cells.extend(cells);
+    }
+
+    // Inputs, outputs and state cell deps are sorted by id, which is the
+    // order the generated contracts load them in. The state cell deps come
+    // first so that `load_cell_deps_data(idx)` finds them at `idx`, and the
+    // registry follows the code of `_entry`. The contract has to be in the
+    // registry, see `build_genesis_tx`.
+    //
+    // The capacity cells of the operator are added after the state cells
+    // along with a change cell. They pay the fee, and top up a state cell
+    // which grows beyond its capacity.
+    //
+    // The first witness of the inputs of every lock holds a placeholder of
+    // its signature. The cells of an owner need `sign_tx_as` with their key
+    // besides `sign_tx`.
+    pub fn build_tx(
+        &mut self,
+        res: ContractResult,
+    ) -> Result<(TransactionView, HashMap<StateKey, Cell>), BuildError> {
+        let (tx, cells) = assemble_tx(&self.deployment, &self.cells, res)?;
+        let input_capacity = tx.input_pts_iter().fold(0u64, |prev, out_point| {
+            let cell = self.cells.values().find(|c| c.outpoint == out_point);
+            let c: u64 = cell.map_or(0, |c| c.output.capacity().unpack());
+            prev + c
+        });
+        let output_capacity = tx.outputs_capacity().expect("capacity overflow").as_u64();
+        let tx = self.top_up(tx, output_capacity.saturating_sub(input_capacity))?;
+        let tx = self.with_lock_placeholders(tx);
+        Ok((tx.clone(), rehash_cells(&tx, cells)))
+    }
+
+    // Build the tx creating a new instance of a keyed type, see
+    // `#[onchain(keyed)]`. `state` is the id, version and encoded value of
+    // the instance, like the ones of `_default_states()`, and the `_entry`
+    // only accepts the default value. The key of the instance comes from the
+    // first input like the type id, see `get_new_instance_key`, so that it
+    // cannot be taken twice. The cell is paid by the capacity cells of the
+    // operator.
+    pub fn build_instance_tx(
+        &mut self,
+        state: (&'static str, u8, Vec<u8>),
+    ) -> Result<InstanceTx, BuildError> {
+        let lock = self.lock.clone();
+        self.build_owned_instance_tx(state, lock)
+    }
+
+    // Like `build_instance_tx`, the cell is locked by `owner`, see
+    // `#[onchain(owned)]`. The operator cannot spend it anymore, and the
+    // contracts changing it need the signature of the owner.
+    pub fn build_owned_instance_tx(
+        &mut self,
+        state: (&'static str, u8, Vec<u8>),
+        owner: Script,
+    ) -> Result<InstanceTx, BuildError> {
+        let (id, version, data) = state;
+        let default = live_cell(&self.cells, id, &[])?;
+        let wrapper =
+            decode::<OnChainWrapper>(&default.data).map_err(|e| BuildError::Malformed(id, e))?;
+        let type_script = default
+            .output
+            .type_()
+            .to_opt()
+            .ok_or(BuildError::NotStateCell(id))?;
+        let data = OnChainWrapper {
+            idx: wrapper.idx,
+            version,
+            data,
+        }
+        ._to_bytes();
+        // The key is known once the inputs are, and it is as long as the
+        // placeholder so the fee stays the same
+        let instance_output = |key: &[u8]| {
+            let output = CellOutput::new_builder()
+                .lock(owner.clone())
+                .type_(Some(get_instance_type_script(&type_script, key)).pack())
+                .build();
+            let capacity = occupied_capacity(&output, data.len());
+            output.as_builder().capacity(capacity.pack()).build()
+        };
+        let output = instance_output(&[0u8; 32]);
+        let capacity: u64 = output.capacity().unpack();
+        let tx = TransactionBuilder::default()
+            .cell_dep(code_dep(self.deployment.entry.0.clone()))
+            .cell_deps(self.deployment.lock_dep.clone())
+            .output(output)
+            .output_data(Bytes::from(data.clone()).pack())
+            .witness(WitnessArgs::default().as_bytes().pack())
+            .build();
+        let tx = self.top_up(tx, capacity)?;
+        let first_input = tx.inputs().get(0).expect("top_up adds inputs");
+        let key = get_new_instance_key(&first_input, 0);
+        let output = instance_output(&key);
+        let mut outputs = tx.outputs().into_iter().collect::<Vec<_>>();
+        outputs[0] = output.clone();
+        let tx = tx.as_advanced_builder().set_outputs(outputs).build();
+        let tx = self.with_lock_placeholders(tx);
+        let cells = HashMap::from([(
+            (id, key.clone()),
+            Cell::new(OutPoint::default(), output, data),
+        )]);
+        Ok((tx.clone(), key, rehash_cells(&tx, cells)))
+    }
+
+    // Add the capacity cells of the operator to the inputs until they pay for
+    // `needed` shannons, the fee and a change cell
+    fn top_up(&mut self, tx: TransactionView, needed: u64) -> Result<TransactionView, BuildError> {
+        let change = CellOutput::new_builder().lock(self.lock.clone()).build();
+        let min_change = occupied_capacity(&change, 0);
+        let mut available = 0u64;
+        let mut builder = tx.as_advanced_builder();
+        for cell in self.client.get_capacity_cells(&self.lock)? {
+            let c: u64 = cell.output.capacity().unpack();
+            available += c;
+            builder = builder.input(CellInput::new(cell.outpoint, 0));
+            let size = self.signed_size(
+                builder
+                    .clone()
+                    .output(change.clone())
+                    .output_data(Bytes::new().pack())
+                    .build(),
+            );
+            let fee = (size * FEE_RATE).div_ceil(1000);
+            let change_capacity = available
+                .checked_sub(needed + fee)
+                .filter(|c| *c >= min_change);
+            if let Some(change_capacity) = change_capacity {
+                let change = change.as_builder().capacity(change_capacity.pack()).build();
+                return Ok(builder
+                    .output(change)
+                    .output_data(Bytes::new().pack())
+                    .build());
+            }
+        }
+        Err(BuildError::NotEnoughCapacity { needed, available })
+    }
+
+    // Run the compiled `_entry` and contract against a mock of the tx that
+    // `build_tx` would build, so that a contract disagreeing with the
+    // off-chain code fails before any fee is paid. The binaries are read from
+    // `<dev.contracts_dir>/build/release`. Returns the consumed cycles.
+    pub fn dry_run(&self, res: &ContractResult) -> Result<u64, DryRunError> {
+        let (mock, tx, cells) = self.mock_tx(res)?;
+        mock.verify(tx, &cells)
+    }
+
+    // The tx that `dry_run` runs, whose code cells are made up for the
+    // compiled binaries
+    pub fn dry_run_tx(&self, res: &ContractResult) -> Result<TransactionView, DryRunError> {
+        Ok(self.mock_tx(res)?.1)
+    }
+
+    fn mock_tx(
+        &self,
+        res: &ContractResult,
+    ) -> Result<(MockDeployment, TransactionView, HashMap<StateKey, Cell>), DryRunError> {
+        let dir = self
+            .config
+            .dev
+            .contracts_dir
+            .as_ref()
+            .ok_or(DryRunError::NoContractsDir)?
+            .join("build")
+            .join("release");
+        let read = |name: &str| {
+            let path = dir.join(name);
+            std::fs::read(&path).map_err(|e| DryRunError::Binary(path, e))
+        };
+        let mock = MockDeployment::new(
+            read("_entry")?,
+            vec![(res.contract_id, read(res.contract_id)?)],
+        );
+        // The made up registry lists the contracts of the real one
+        let registered = match self.cells.get(&(REGISTRY_ID, vec![])) {
+            Some(cell) => registered_hashes(cell)?,
+            None => vec![],
+        };
+        let names = self
+            .deployment
+            .contracts
+            .iter()
+            .filter(|(_, (_, hash))| registered.contains(hash))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        let cells = mock.state_cells(&self.cells, &names);
+        let (tx, _) = assemble_tx(&mock.deployment, &cells, res.clone())?;
+        Ok((mock, tx, cells))
+    }
+
+    // Build the tx creating one state cell per registered type, see
+    // `_default_states()` of the app for `states`. The wrapper idx of a type is
+    // its position in the sorted ids. `boots.initial_capacity` is shared by the
+    // state cells evenly. The registry of the deployed contracts follows the
+    // state cells, see `REGISTRY_IDX`. `funding` are live cells of the
+    // operator, which pay for the state cells, the registry and the fee, and
+    // the rest goes back to the operator.
+    pub fn build_genesis_tx(
+        &self,
+        states: Vec<(&'static str, u8, Vec<u8>)>,
+        funding: Vec<Cell>,
+    ) -> Result<(TransactionView, Genesis), BuildError> {
+        if states.is_empty() || states.len() > REGISTRY_IDX as usize {
+            return Err(BuildError::StateCount(states.len()));
+        }
+        let first = funding.first().ok_or(BuildError::NotEnoughCapacity {
+            needed: self.config.boots.initial_capacity,
+            available: 0,
+        })?;
+        let input_hash = get_input_hash(&CellInput::new(first.outpoint.clone(), 0));
+        let type_script = self.get_type_script(input_hash.clone());
+
+        let mut states = states;
+        states.sort_by_key(|s| s.0);
+        let capacity = self.config.boots.initial_capacity / states.len() as u64;
+        let mut outputs = Vec::with_capacity(states.len() + 1);
+        let mut outputs_data = Vec::with_capacity(states.len() + 1);
+        for (idx, (id, version, data)) in states.iter().enumerate() {
+            let data = OnChainWrapper {
+                idx: idx as u8,
+                version: *version,
+                data: data.clone(),
+            }
+            ._to_bytes();
+            let output = CellOutput::new_builder()
+                .capacity(capacity.pack())
+                .lock(self.lock.clone())
+                .type_(Some(type_script.clone()).pack())
+                .build();
+            let occupied = occupied_capacity(&output, data.len());
+            if occupied > capacity {
+                return Err(BuildError::InitialCapacity(id, occupied));
+            }
+            outputs.push(output);
+            outputs_data.push(data);
+        }
+        let registry_data = registry_data(self.deployment.contracts.values().map(|(_, h)| h));
+        let registry = CellOutput::new_builder()
+            .lock(self.lock.clone())
+            .type_(Some(type_script.clone()).pack())
+            .build();
+        let registry_capacity = occupied_capacity(&registry, registry_data.len());
+        outputs.push(
+            registry
+                .as_builder()
+                .capacity(registry_capacity.pack())
+                .build(),
+        );
+        outputs_data.push(registry_data);
+
+        let input_capacity = funding.iter().fold(0u64, |prev, cell| {
+            let c: u64 = cell.output.capacity().unpack();
+            prev + c
+        });
+        let change = CellOutput::new_builder().lock(self.lock.clone()).build();
+        // The `_entry` checks the type script args with the app name
+        let app_name = Bytes::from(self.config.boots.app_name.clone().into_bytes());
+        let witness = WitnessArgs::new_builder()
+            .input_type(Some(app_name).pack())
+            .lock(Some(Bytes::from(vec![0u8; 65])).pack())
+            .build();
+        let builder = TransactionBuilder::default()
+            .cell_deps(self.deployment.lock_dep.clone())
+            .inputs(
+                funding
+                    .iter()
+                    .map(|cell| CellInput::new(cell.outpoint.clone(), 0)),
+            )
+            .outputs(outputs.clone())
+            .output(change.clone())
+            .outputs_data(outputs_data.iter().map(|d| Bytes::from(d.clone()).pack()))
+            .output_data(Bytes::new().pack())
+            .witness(witness.as_bytes().pack());
+        let size = builder.clone().build().data().serialized_size_in_block() as u64;
+        let fee = (size * FEE_RATE).div_ceil(1000);
+        let spent = self.config.boots.initial_capacity + registry_capacity + fee;
+        let needed = spent + occupied_capacity(&change, 0);
+        if input_capacity < needed {
+            return Err(BuildError::NotEnoughCapacity {
+                needed,
+                available: input_capacity,
+            });
+        }
+        let change_capacity = input_capacity - spent;
+        let change = change.as_builder().capacity(change_capacity.pack()).build();
+        let tx = builder
+            .set_outputs(outputs.into_iter().chain(Some(change)).collect())
+            .build();
+
+        let tx_hash = tx.hash();
+        let cells = states
+            .into_iter()
+            .map(|(id, _, _)| id)
+            .chain(Some(REGISTRY_ID))
+            .zip(tx.outputs_with_data_iter())
+            .enumerate()
+            .map(|(idx, (id, (output, data)))| {
+                let outpoint = OutPoint::new(tx_hash.clone(), idx as u32);
+                ((id, vec![]), Cell::new(outpoint, output, data.to_vec()))
+            })
+            .collect();
+        let genesis = Genesis {
+            input_hash,
+            type_script,
+            cells,
+        };
+        Ok((tx, genesis))
+    }
+
+    // Find the state cells of the registered types by the type script, whose
+    // args come from the `input_hash` of the genesis, keyed instances
+    // included. The cells found are the live cells of the app from now on.
+    pub fn load_cells(
+        &mut self,
+        input_hash: Byte32,
+        ids: Vec<&'static str>,
+    ) -> Result<CellManager, LoadError> {
+        let type_script = self.get_type_script(input_hash);
+        let mut ids = ids;
+        ids.sort();
+        let mut cells: HashMap<StateKey, Cell> = HashMap::new();
+        for cell in self.client.get_cells(&type_script)? {
+            let wrapper = decode::<OnChainWrapper>(&cell.data)
+                .map_err(|e| LoadError::Malformed(cell.outpoint.clone(), e))?;
+            let id = match wrapper.idx {
+                REGISTRY_IDX => REGISTRY_ID,
+                idx => *ids.get(idx as usize).ok_or(LoadError::UnknownIdx(idx))?,
+            };
+            let key = cell
+                .output
+                .type_()
+                .to_opt()
+                .and_then(|script| get_instance_key(&type_script, &script))
+                .unwrap_or_default();
+            if cells.insert((id, key), cell).is_some() {
+                return Err(LoadError::Duplicated(id));
+            }
+        }
+        if let Some(id) = ids.iter().find(|id| !cells.contains_key(&(**id, vec![]))) {
+            return Err(LoadError::Missing(id));
+        }
+
+        let manager = CellManager::from_state_cells(&cells)?;
+        self.cells = cells;
+        Ok(manager)
+    }
+
+    fn get_type_script(&self, input_hash: Byte32) -> Script {
+        let (_, entry_hash) = &self.deployment.entry;
+        get_onchain_type_script(entry_hash.clone(), input_hash, &self.config.boots.app_name)
+    }
+
+    // Sign every input locked by the operator.
+    pub fn sign_tx(&self, tx: TransactionView) -> Result<TransactionView, BuildError> {
+        self.sign_tx_as(tx, &self.config.chain.secret_key)
+    }
+
+    // Sign every input locked by the sighash lock of `secret_key`, like the
+    // owner of a cell does, see `build_owned_instance_tx`
+    pub fn sign_tx_as(
+        &self,
+        tx: TransactionView,
+        secret_key: &SecretKey,
+    ) -> Result<TransactionView, BuildError> {
+        let lock = get_sighash_lock(secret_key);
+        let group = match self.lock_groups(&tx).into_iter().find(|g| g.script == lock) {
+            Some(group) => group,
+            None => return Ok(tx),
+        };
+        let signer = SecpSighashScriptSigner::new(Box::new(
+            SecpCkbRawKeySigner::new_with_secret_keys(vec![*secret_key]),
+        ));
+        signer
+            .sign_tx(&tx, &group)
+            .map_err(|e| BuildError::Sign(e.to_string()))
+    }
+
+    // The inputs of `tx` grouped by their lock. The state cells are looked up
+    // in the live cells, and the other inputs are the capacity cells of the
+    // operator.
+    fn lock_groups(&self, tx: &TransactionView) -> Vec<ScriptGroup> {
+        let mut groups: Vec<ScriptGroup> = vec![];
+        for (idx, out_point) in tx.input_pts_iter().enumerate() {
+            let lock = self
+                .cells
+                .values()
+                .find(|c| c.outpoint == out_point)
+                .map_or_else(|| self.lock.clone(), |c| c.output.lock());
+            match groups.iter_mut().find(|g| g.script == lock) {
+                Some(group) => group.input_indices.push(idx),
+                None => groups.push(ScriptGroup {
+                    script: lock,
+                    group_type: ScriptGroupType::Lock,
+                    input_indices: vec![idx],
+                    output_indices: vec![],
+                }),
+            }
+        }
+        groups
+    }
+
+    // Leave room for the signature of every lock in the first witness of its
+    // inputs. The witnesses cover all the inputs so that the signatures do
+    // not depend on the order they are made in.
+    fn with_lock_placeholders(&self, tx: TransactionView) -> TransactionView {
+        let mut witnesses = tx.witnesses().into_iter().collect::<Vec<_>>();
+        if witnesses.len() < tx.inputs().len() {
+            witnesses.resize(tx.inputs().len(), Default::default());
+        }
+        for group in self.lock_groups(&tx) {
+            let idx = group.input_indices[0];
+            let witness = WitnessArgs::from_slice(&witnesses[idx].raw_data())
+                .unwrap_or_default()
+                .as_builder()
+                .lock(Some(Bytes::from(vec![0u8; 65])).pack())
+                .build();
+            witnesses[idx] = witness.as_bytes().pack();
+        }
+        tx.as_advanced_builder().set_witnesses(witnesses).build()
+    }
+
+    // The size of `tx` once every lock signs it
+    fn signed_size(&self, tx: TransactionView) -> u64 {
+        self.with_lock_placeholders(tx)
+            .data()
+            .serialized_size_in_block() as u64
+    }
+
+    // Sign and send the tx. Call `update_cells` once it is committed.
+    pub fn send_tx(&mut self, tx: TransactionView) -> Result<Byte32, BuildError> {
+        let tx = self.sign_tx(tx)?;
+        Ok(self.client.send_transaction(tx)?)
     }
+}
+
+// Where the generated contracts live on chain.
+#[derive(Debug, Clone, Default)]
+pub struct Deployment {
+    // (out point, type hash) of the `_entry` contract, which is the type
+    // script of every state cell
+    pub entry: (OutPoint, Byte32),
+    // Contract id -> (out point, type hash) of the compiled contract. The type
+    // hash is what `_entry` passes to `exec_cell`.
+    pub contracts: HashMap<String, (OutPoint, Byte32)>,
+    // The dep of the operator's lock script
+    pub lock_dep: Option<CellDep>,
+}
+
+impl Deployment {
+    // Read the latest migration that `capsule deploy` wrote under
+    // `<contracts_dir>/migrations/dev`.
+    pub fn load<P: AsRef<Path>>(contracts_dir: P) -> Result<Self, FileError> {
+        let dir = contracts_dir.as_ref().join("migrations").join("dev");
+        let latest = std::fs::read_dir(&dir)
+            .map_err(|e| FileError::Io(dir.clone(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .max()
+            .ok_or_else(|| FileError::NoMigration(dir.clone()))?;
+        let content =
+            std::fs::read_to_string(&latest).map_err(|e| FileError::Io(latest.clone(), e))?;
+        let migration: Migration =
+            serde_json::from_str(&content).map_err(|e| FileError::Malformed(latest.clone(), e))?;
 
-    pub fn build_tx(&self, res: ContractResult) -> (TransactionView, HashMap<&'static str, Cell>) {
-        "".as_bytes();
-        todo!()
+        let mut deployment = Deployment::default();
+        let mut has_entry = false;
+        migration.cell_recipes.into_iter().for_each(|recipe| {
+            let out_point: OutPoint = JsonOutPoint {
+                tx_hash: recipe.tx_hash,
+                index: recipe.index.into(),
+            }
+            .into();
+            match recipe.type_id {
+                Some(type_id) if recipe.name == "_entry" => {
+                    deployment.entry = (out_point, type_id.pack());
+                    has_entry = true;
+                }
+                Some(type_id) => {
+                    deployment
+                        .contracts
+                        .insert(recipe.name, (out_point, type_id.pack()));
+                }
+                None => {}
+            }
+        });
+        if !has_entry {
+            return Err(FileError::NoEntry(latest));
+        }
+        Ok(deployment)
     }
 }
 
+// The migration file of capsule, only the fields we need
+#[derive(Deserialize)]
+struct Migration {
+    cell_recipes: Vec<CellRecipe>,
+}
+
+#[derive(Deserialize)]
+struct CellRecipe {
+    name: String,
+    tx_hash: H256,
+    index: u32,
+    // The type script hash, which is only set if `enable_type_id = true`
+    type_id: Option<H256>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Cell {
-    outpoint: OutPoint,
-    data: Vec<u8>,
+    pub outpoint: OutPoint,
+    pub output: CellOutput,
+    // The raw cell data, that is an encoded `OnChainWrapper`
+    pub data: Vec<u8>,
+}
+
+impl Cell {
+    pub fn new(outpoint: OutPoint, output: CellOutput, data: Vec<u8>) -> Self {
+        Cell {
+            outpoint,
+            output,
+            data,
+        }
+    }
+
+    pub fn cell_dep(&self) -> CellDep {
+        code_dep(self.outpoint.clone())
+    }
+}
+
+// The state cells created by the genesis tx
+#[derive(Debug, Clone)]
+pub struct Genesis {
+    // The hash of the first input, see `get_input_hash`
+    pub input_hash: Byte32,
+    pub type_script: Script,
+    pub cells: HashMap<StateKey, Cell>,
+}
+
+impl Genesis {
+    pub fn cell_manager(&self) -> Result<CellManager, LoadError> {
+        CellManager::from_state_cells(&self.cells)
+    }
+
+    pub fn record(&self) -> GenesisRecord {
+        GenesisRecord {
+            input_hash: self.input_hash.unpack(),
+            type_script: self.type_script.clone().into(),
+            out_points: self
+                .cells
+                .iter()
+                .map(|((id, _), cell)| (id.to_string(), cell.outpoint.clone().into()))
+                .collect(),
+        }
+    }
+}
+
+// What should be kept after the genesis to find the state cells again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisRecord {
+    pub input_hash: H256,
+    pub type_script: JsonScript,
+    pub out_points: BTreeMap<String, JsonOutPoint>,
+}
+
+impl GenesisRecord {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let content = serde_json::to_string_pretty(self).expect("record is serializable");
+        std::fs::write(path, content)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FileError> {
+        let path = path.as_ref();
+        let content =
+            std::fs::read_to_string(path).map_err(|e| FileError::Io(path.to_path_buf(), e))?;
+        serde_json::from_str(&content).map_err(|e| FileError::Malformed(path.to_path_buf(), e))
+    }
+}
+
+#[derive(Debug)]
+pub enum BootstrapError {
+    Config(ConfigError),
+    // The capsule migration in `dev.contracts_dir` cannot be read
+    Deployment(FileError),
+    // The node cannot tell the dep of the lock of the operator
+    Client(ClientError),
+}
+
+impl fmt::Display for BootstrapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootstrapError::Config(e) => write!(f, "{}", e),
+            BootstrapError::Deployment(e) => write!(f, "{}", e),
+            BootstrapError::Client(e) => write!(f, "failed to find the lock dep: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BootstrapError {}
+
+impl From<ConfigError> for BootstrapError {
+    fn from(e: ConfigError) -> Self {
+        BootstrapError::Config(e)
+    }
+}
+
+impl From<FileError> for BootstrapError {
+    fn from(e: FileError) -> Self {
+        BootstrapError::Deployment(e)
+    }
+}
+
+impl From<ClientError> for BootstrapError {
+    fn from(e: ClientError) -> Self {
+        BootstrapError::Client(e)
+    }
+}
+
+// The capsule migration read by `Deployment::load`, or the genesis record
+#[derive(Debug)]
+pub enum FileError {
+    Io(PathBuf, std::io::Error),
+    // The file is not the JSON expected
+    Malformed(PathBuf, serde_json::Error),
+    // No migration is found in the directory
+    NoMigration(PathBuf),
+    // The migration does not deploy `_entry` with type id
+    NoEntry(PathBuf),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            FileError::Malformed(path, e) => write!(f, "malformed {}: {}", path.display(), e),
+            FileError::NoMigration(dir) => {
+                write!(f, "no migration is found in {}", dir.display())
+            }
+            FileError::NoEntry(path) => {
+                write!(
+                    f,
+                    "_entry is not deployed with type id in {}",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Client(ClientError),
+    // The cell data is not an `OnChainWrapper`
+    Malformed(OutPoint, DecodeError),
+    // The wrapper idx does not belong to any registered type
+    UnknownIdx(u8),
+    // More than one live cell holds the type
+    Duplicated(&'static str),
+    // No live cell holds the type
+    Missing(&'static str),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Client(e) => write!(f, "{}", e),
+            LoadError::Malformed(out_point, e) => {
+                write!(
+                    f,
+                    "cell {} does not hold an OnChainWrapper: {}",
+                    out_point, e
+                )
+            }
+            LoadError::UnknownIdx(idx) => write!(f, "no type is registered at {}", idx),
+            LoadError::Duplicated(id) => write!(f, "more than one cell holds {}", id),
+            LoadError::Missing(id) => write!(f, "no cell holds {}", id),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Debug)]
+pub enum BuildError {
+    Client(ClientError),
+    // The capacity cells of the operator cannot pay for the grown state
+    // cells, or the cells created by the genesis, and the fee
+    NotEnoughCapacity { needed: u64, available: u64 },
+    // The genesis creates 1 to 255 state cells
+    StateCount(usize),
+    // The share of `boots.initial_capacity` of a state cell is less than the
+    // shannons it occupies
+    InitialCapacity(&'static str, u64),
+    // The contract is not in the `Deployment`
+    NotDeployed(&'static str),
+    // The contract is not in the registry of the app, so `_entry` refuses to
+    // exec it
+    Unregistered(&'static str),
+    // No live cell holds the id and key, perhaps the type is not registered
+    MissingCell(&'static str, Vec<u8>),
+    // The data of the live cell is not an `OnChainWrapper`
+    Malformed(&'static str, DecodeError),
+    // The live cell has no type script
+    NotStateCell(&'static str),
+    // The signer refuses the tx, e.g. its witnesses are malformed
+    Sign(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Client(e) => write!(f, "{}", e),
+            BuildError::NotEnoughCapacity { needed, available } => write!(
+                f,
+                "state cells need {} more shannons, but the operator only has {}",
+                needed, available
+            ),
+            BuildError::StateCount(n) => write!(f, "cannot create {} state cells", n),
+            BuildError::InitialCapacity(id, needed) => write!(
+                f,
+                "boots.initial_capacity is not enough, {} needs {} shannons",
+                id, needed
+            ),
+            BuildError::NotDeployed(id) => write!(f, "contract {} is not deployed", id),
+            BuildError::Unregistered(id) => write!(f, "contract {} is not registered", id),
+            BuildError::MissingCell(id, key) => write!(
+                f,
+                "cannot find the cell of {} {:?}, perhaps it is not registered",
+                id, key
+            ),
+            BuildError::Malformed(id, e) => {
+                write!(f, "the data of {} is not an OnChainWrapper: {}", id, e)
+            }
+            BuildError::NotStateCell(id) => write!(f, "the cell of {} has no type script", id),
+            BuildError::Sign(e) => write!(f, "failed to sign the tx: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<ClientError> for BuildError {
+    fn from(e: ClientError) -> Self {
+        BuildError::Client(e)
+    }
+}
+
+impl From<ClientError> for LoadError {
+    fn from(e: ClientError) -> Self {
+        LoadError::Client(e)
+    }
+}
+
+// The cells of `tx` point at the tx before the top up, see `App::top_up`
+fn rehash_cells(tx: &TransactionView, cells: HashMap<StateKey, Cell>) -> HashMap<StateKey, Cell> {
+    let tx_hash = tx.hash();
+    cells
+        .into_iter()
+        .map(|(state_key, cell)| {
+            let outpoint = OutPoint::new(tx_hash.clone(), cell.outpoint.index().unpack());
+            (state_key, Cell::new(outpoint, cell.output, cell.data))
+        })
+        .collect()
+}
+
+fn occupied_capacity(output: &CellOutput, data_len: usize) -> u64 {
+    output
+        .occupied_capacity(Capacity::bytes(data_len).expect("data is too large"))
+        .expect("capacity overflow")
+        .as_u64()
+}
+
+fn code_dep(out_point: OutPoint) -> CellDep {
+    CellDep::new_builder()
+        .out_point(out_point)
+        .dep_type(DepType::Code.into())
+        .build()
+}
+
+fn assemble_tx(
+    deployment: &Deployment,
+    cells: &HashMap<StateKey, Cell>,
+    res: ContractResult,
+) -> Result<(TransactionView, HashMap<StateKey, Cell>), BuildError> {
+    let contract_hash = deployment
+        .contracts
+        .get(res.contract_id)
+        .map(|(_, hash)| hash.clone())
+        .ok_or(BuildError::NotDeployed(res.contract_id))?;
+    let registry = live_cell(cells, REGISTRY_ID, &[])?;
+    if !registered_hashes(registry)?.contains(&contract_hash) {
+        return Err(BuildError::Unregistered(res.contract_id));
+    }
+
+    let mut deps = res.deps.clone();
+    deps.sort();
+    let mut cell_deps = deps
+        .into_iter()
+        .map(|id| live_cell(cells, id, res.key(id)).map(|cell| cell.cell_dep()))
+        .collect::<Result<Vec<_>, _>>()?;
+    cell_deps.push(code_dep(deployment.entry.0.clone()));
+    cell_deps.push(registry.cell_dep());
+    cell_deps.extend(deployment.lock_dep.clone());
+    cell_deps.extend(
+        deployment
+            .contracts
+            .get(res.contract_id)
+            .map(|(out_point, _)| code_dep(out_point.clone())),
+    );
+
+    let mut updates = res.input_output_data.clone();
+    updates.sort_by_key(|u| u.0);
+    let mut inputs = Vec::with_capacity(updates.len());
+    let mut outputs = Vec::with_capacity(updates.len());
+    let mut outputs_data = Vec::with_capacity(updates.len());
+    for (id, _, output) in updates {
+        let cell = live_cell(cells, id, res.key(id))?;
+        let wrapper =
+            decode::<OnChainWrapper>(&cell.data).map_err(|e| BuildError::Malformed(id, e))?;
+        // The contract may upgrade the state, see `Migrate`
+        let version = res
+            .versions
+            .iter()
+            .find(|(v_id, _)| *v_id == id)
+            .map_or(wrapper.version, |(_, version)| *version);
+        let data = OnChainWrapper {
+            idx: wrapper.idx,
+            version,
+            data: output,
+        }
+        ._to_bytes();
+        // A grown state needs more capacity, see `App::build_tx`
+        let capacity: u64 = cell.output.capacity().unpack();
+        let occupied = occupied_capacity(&cell.output, data.len());
+        let output = if occupied > capacity {
+            cell.output
+                .clone()
+                .as_builder()
+                .capacity(occupied.pack())
+                .build()
+        } else {
+            cell.output.clone()
+        };
+        inputs.push(CellInput::new(cell.outpoint.clone(), 0));
+        outputs.push(((id, res.key(id).to_vec()), output));
+        outputs_data.push(data);
+    }
+
+    let witness = WitnessArgs::new_builder()
+        .input_type(res.user_input.map(Bytes::from).pack())
+        .output_type(Some(contract_hash.as_bytes()).pack())
+        .build();
+
+    let tx = TransactionBuilder::default()
+        .cell_deps(cell_deps)
+        .inputs(inputs)
+        .outputs(outputs.iter().map(|(_, o)| o.clone()))
+        .outputs_data(outputs_data.iter().map(|d| Bytes::from(d.clone()).pack()))
+        .witness(witness.as_bytes().pack())
+        .build();
+
+    let tx_hash = tx.hash();
+    let cells = outputs
+        .into_iter()
+        .zip(outputs_data)
+        .enumerate()
+        .map(|(idx, ((state_key, output), data))| {
+            let outpoint = OutPoint::new(tx_hash.clone(), idx as u32);
+            (state_key, Cell::new(outpoint, output, data))
+        })
+        .collect();
+    Ok((tx, cells))
+}
+
+// The cell data of the registry listing the type hashes, see `REGISTRY_IDX`
+pub(crate) fn registry_data<'a>(hashes: impl Iterator<Item = &'a Byte32>) -> Vec<u8> {
+    let mut hashes = hashes.map(|h| h.unpack().0).collect::<Vec<[u8; 32]>>();
+    hashes.sort();
+    OnChainWrapper {
+        idx: REGISTRY_IDX,
+        version: 0,
+        data: hashes._to_bytes(),
+    }
+    ._to_bytes()
+}
+
+fn registered_hashes(registry: &Cell) -> Result<Vec<Byte32>, BuildError> {
+    let malformed = |e| BuildError::Malformed(REGISTRY_ID, e);
+    let wrapper = decode::<OnChainWrapper>(&registry.data).map_err(malformed)?;
+    let hashes = decode::<Vec<[u8; 32]>>(&wrapper.data).map_err(malformed)?;
+    Ok(hashes.into_iter().map(|h| h.pack()).collect())
+}
+
+fn live_cell<'a>(
+    cells: &'a HashMap<StateKey, Cell>,
+    id: &'static str,
+    key: &[u8],
+) -> Result<&'a Cell, BuildError> {
+    cells
+        .get(&(id, key.to_vec()))
+        .ok_or_else(|| BuildError::MissingCell(id, key.to_vec()))
 }
@@ -1,42 +1,290 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
+use ckb_types::packed::OutPoint;
+
+use crate::app::{Cell, LoadError, StateKey, REGISTRY_ID};
+use crate::on_chain::{decode, DecodeError, OnChain, OnChainWrapper};
+
+// The states of the app keyed by on-chain id and instance key, which is
+// empty unless the type is `#[onchain(keyed)]`. Writes are staged until the tx
+// writing them is confirmed, then `commit` applies them all at once, or
+// `rollback` drops them if the tx is rejected.
+#[derive(Clone)]
 pub struct CellManager {
-    // it is sorted
-    ids: Vec<&'static str>,
-    types: Vec<Vec<u8>>,
-    pending: HashMap<usize, Vec<u8>>,
+    states: BTreeMap<StateKey, State>,
+    pending: BTreeMap<StateKey, State>,
+    // Named copies of `states`, see `snapshot`
+    snapshots: HashMap<String, BTreeMap<StateKey, State>>,
+}
+
+#[derive(Clone)]
+struct State {
+    // The encoded type
+    data: Vec<u8>,
+    // The version in the `OnChainWrapper` of the cell, unknown if the data
+    // is built by hand, which is taken as the current version
+    version: Option<u8>,
+    // The cell holding the type, unknown if the data is built by hand
+    out_point: Option<OutPoint>,
 }
 
 impl CellManager {
     pub fn get_by_id(&self, id: &'static str) -> Option<&[u8]> {
-        let idx = self.ids.iter().position(|e| *e == id)?;
-        Some(self.types.get(idx)?)
+        self.get_by_key(id, &[])
+    }
+
+    // The encoded type of the instance `key` of `id`
+    pub fn get_by_key(&self, id: &'static str, key: &[u8]) -> Option<&[u8]> {
+        Some(&self.states.get(&(id, key.to_vec()))?.data)
+    }
+
+    // The instance keys of `id` in ascending order
+    pub fn get_keys(&self, id: &'static str) -> Vec<&[u8]> {
+        self.states
+            .keys()
+            .filter(|(k_id, _)| *k_id == id)
+            .map(|(_, key)| key.as_slice())
+            .collect()
+    }
+
+    pub fn get_out_point(&self, id: &'static str) -> Option<&OutPoint> {
+        self.states.get(&(id, vec![]))?.out_point.as_ref()
+    }
+
+    pub fn get_version(&self, id: &'static str) -> Option<u8> {
+        self.states.get(&(id, vec![]))?.version
+    }
+
+    // Decode the committed state of `T`, migrating it if it is written by an
+    // older version of `T`
+    pub fn get<T: OnChain>(&self) -> Result<T, StateError> {
+        self.get_instance(&[])
     }
 
-    pub fn set_pending(&mut self, id: usize, data: Vec<u8>) {
-        self.pending.insert(id, data);
+    // Like `get`, the instance `key` of a keyed type
+    pub fn get_instance<T: OnChain>(&self, key: &[u8]) -> Result<T, StateError> {
+        let id = instance_id::<T>(key)?;
+        let state = self
+            .states
+            .get(&(id, key.to_vec()))
+            .ok_or(StateError::Unregistered(id))?;
+        decode_state(state)
     }
 
+    // Stage the state of `T` changed by `f`. It starts from the staged state
+    // if any, so that the updates of a tx add up. The state is still held by
+    // the same cell until `stage_cells` stages the cells of the tx.
+    pub fn update<T: OnChain, R>(&mut self, f: impl FnOnce(&mut T) -> R) -> Result<R, StateError> {
+        self.update_instance(&[], f)
+    }
+
+    // Like `update`, the instance `key` of a keyed type
+    pub fn update_instance<T: OnChain, R>(
+        &mut self,
+        key: &[u8],
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, StateError> {
+        let id = instance_id::<T>(key)?;
+        let state_key = (id, key.to_vec());
+        let state = self
+            .pending
+            .get(&state_key)
+            .or_else(|| self.states.get(&state_key))
+            .ok_or(StateError::Unregistered(id))?;
+        let out_point = state.out_point.clone();
+        let mut t = decode_state::<T>(state)?;
+        let result = f(&mut t);
+        let state = State {
+            data: t._to_bytes(),
+            version: Some(T::_version()),
+            out_point,
+        };
+        self.pending.insert(state_key, state);
+        Ok(result)
+    }
+
+    // The staged data of `id`, which `get_by_id` returns once it is committed
+    pub fn get_pending(&self, id: &'static str) -> Option<&[u8]> {
+        Some(&self.pending.get(&(id, vec![]))?.data)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Stage the encoded type of `id`, replacing the one staged before
+    pub fn set_pending(&mut self, id: &'static str, data: Vec<u8>) {
+        let state = State {
+            data,
+            version: None,
+            out_point: None,
+        };
+        self.pending.insert((id, vec![]), state);
+    }
+
+    // Stage the state cells returned by `build_tx`
+    pub fn stage_cells(&mut self, cells: &HashMap<StateKey, Cell>) -> Result<(), StateError> {
+        let states = cell_states(cells).map_err(|(_, e)| StateError::Decode(e))?;
+        self.pending.extend(states);
+        Ok(())
+    }
+
+    // Apply the staged writes once the tx is confirmed
     pub fn commit(&mut self) {
-        self.pending.drain().for_each(|(k, v)| {
-            self.types.insert(k, v);
-        });
+        let pending = std::mem::take(&mut self.pending);
+        self.states.extend(pending);
+    }
+
+    // Drop the staged writes of a rejected tx
+    pub fn rollback(&mut self) {
+        self.pending.clear();
+    }
+
+    // Keep a copy of the committed states under `name`, replacing the one
+    // taken before
+    pub fn snapshot(&mut self, name: &str) {
+        self.snapshots
+            .insert(String::from(name), self.states.clone());
+    }
+
+    // Go back to the snapshot `name`, dropping the staged writes. Returns
+    // false if there is no such snapshot.
+    pub fn restore(&mut self, name: &str) -> bool {
+        match self.snapshots.get(name) {
+            Some(states) => {
+                self.states = states.clone();
+                self.pending.clear();
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn new(data: Vec<(&'static str, Vec<u8>)>) -> Self {
-        let mut data = data;
-        data.sort_by_key(|d| d.0);
-        let mut types = Vec::new();
-        let mut ids = Vec::new();
-        data.into_iter().for_each(|(id, d)| {
-            types.push(d);
-            ids.push(id);
-        });
+        let states = data
+            .into_iter()
+            .map(|(id, data)| {
+                let state = State {
+                    data,
+                    version: None,
+                    out_point: None,
+                };
+                ((id, vec![]), state)
+            })
+            .collect();
+        Self::with_states(states)
+    }
+
+    // `version` and `data` are the ones of the `OnChainWrapper` in the cell,
+    // that is the encoded type.
+    pub fn from_cells(cells: Vec<(&'static str, u8, Vec<u8>, OutPoint)>) -> Self {
+        let states = cells
+            .into_iter()
+            .map(|(id, version, data, out_point)| {
+                let state = State {
+                    data,
+                    version: Some(version),
+                    out_point: Some(out_point),
+                };
+                ((id, vec![]), state)
+            })
+            .collect();
+        Self::with_states(states)
+    }
+
+    // The state cells of `App`, keyed instances included
+    pub(crate) fn from_state_cells(cells: &HashMap<StateKey, Cell>) -> Result<Self, LoadError> {
+        let states =
+            cell_states(cells).map_err(|(out_point, e)| LoadError::Malformed(out_point, e))?;
+        Ok(Self::with_states(states))
+    }
 
+    fn with_states(states: BTreeMap<StateKey, State>) -> Self {
         CellManager {
-            ids,
-            types,
-            pending: HashMap::new(),
+            states,
+            pending: BTreeMap::new(),
+            snapshots: HashMap::new(),
         }
     }
 }
+
+// Only keyed types have instances other than the one of the empty key
+fn instance_id<T: OnChain>(key: &[u8]) -> Result<&'static str, StateError> {
+    let id = T::_id().ok_or(StateError::NoId)?;
+    if !key.is_empty() && !T::_keyed() {
+        return Err(StateError::NotKeyed(id));
+    }
+    Ok(id)
+}
+
+// Unknown versions are taken as the current one, see `State`
+fn decode_state<T: OnChain>(state: &State) -> Result<T, StateError> {
+    let version = state.version.unwrap_or_else(T::_version);
+    Ok(T::_from_versioned_bytes(version, &state.data)?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    // The type has no `#[onchain(id = "..")]`, so it has no state cell
+    NoId,
+    // The manager holds no state of the id, perhaps it is not registered
+    Unregistered(&'static str),
+    // An instance key is given for a type which is not `#[onchain(keyed)]`
+    NotKeyed(&'static str),
+    // No contract of the app has the id
+    UnknownContract(String),
+    // The type is not `#[onchain(migrate)]`
+    NoMigration(&'static str),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::NoId => write!(f, "the type has no on-chain id"),
+            StateError::Unregistered(id) => {
+                write!(f, "cannot find the {}, perhaps it is not registered", id)
+            }
+            StateError::NotKeyed(id) => write!(f, "{} has no instances, it is not keyed", id),
+            StateError::UnknownContract(id) => write!(f, "no contract has the id {}", id),
+            StateError::NoMigration(id) => {
+                write!(
+                    f,
+                    "{} has no migration, perhaps it is not #[onchain(migrate)]",
+                    id
+                )
+            }
+            StateError::Decode(e) => write!(f, "cannot decode the state: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<DecodeError> for StateError {
+    fn from(e: DecodeError) -> Self {
+        StateError::Decode(e)
+    }
+}
+
+// The encoded types in the `OnChainWrapper` of the state cells, or the cell
+// which holds something else. The registry is not a state.
+fn cell_states(
+    cells: &HashMap<StateKey, Cell>,
+) -> Result<BTreeMap<StateKey, State>, (OutPoint, DecodeError)> {
+    cells
+        .iter()
+        .filter(|(state_key, _)| state_key.0 != REGISTRY_ID)
+        .map(|(state_key, cell)| {
+            let wrapper =
+                decode::<OnChainWrapper>(&cell.data).map_err(|e| (cell.outpoint.clone(), e))?;
+            let state = State {
+                data: wrapper.data,
+                version: Some(wrapper.version),
+                out_point: Some(cell.outpoint.clone()),
+            };
+            Ok((state_key.clone(), state))
+        })
+        .collect()
+}
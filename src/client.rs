@@ -0,0 +1,160 @@
+use std::fmt;
+
+use ckb_jsonrpc_types::OutputsValidator;
+use ckb_sdk::rpc::ckb_indexer::{Order, ScriptSearchMode, ScriptType, SearchKey, SearchKeyFilter};
+use ckb_sdk::traits::DefaultCellDepResolver;
+use ckb_sdk::{CkbRpcClient, IndexerRpcClient};
+use ckb_types::core::{BlockView, TransactionView};
+use ckb_types::packed::{Byte32, CellDep, Script};
+use ckb_types::prelude::Pack;
+
+use crate::app::Cell;
+
+// Cells fetched from the indexer in a request
+const PAGE_SIZE: u32 = 100;
+
+// What the app needs from a CKB node.
+pub trait Client {
+    fn get_tip_block_number(&mut self) -> Result<u64, ClientError>;
+
+    fn send_transaction(&mut self, tx: TransactionView) -> Result<Byte32, ClientError>;
+
+    // All the live cells whose type script is `type_script`, or the one of a
+    // keyed instance whose args start with the ones of `type_script`
+    fn get_cells(&mut self, type_script: &Script) -> Result<Vec<Cell>, ClientError>;
+
+    // The live cells of `lock` with neither type script nor data, which only
+    // hold capacity
+    fn get_capacity_cells(&mut self, lock: &Script) -> Result<Vec<Cell>, ClientError>;
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    // The node cannot be reached or answers with an error
+    Rpc(String),
+    // The tx is refused by the chain
+    Rejected(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Rpc(msg) => write!(f, "rpc error: {}", msg),
+            ClientError::Rejected(msg) => write!(f, "tx is rejected: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<ckb_sdk::RpcError> for ClientError {
+    fn from(e: ckb_sdk::RpcError) -> Self {
+        ClientError::Rpc(e.to_string())
+    }
+}
+
+pub struct RpcClient {
+    ckb: CkbRpcClient,
+    // The indexer built in the node
+    indexer: IndexerRpcClient,
+}
+
+impl RpcClient {
+    // No request is sent until the client is used.
+    pub fn new(url: &str) -> Self {
+        RpcClient {
+            ckb: CkbRpcClient::new(url),
+            indexer: IndexerRpcClient::new(url),
+        }
+    }
+
+    pub fn get_genesis_block(&mut self) -> Result<BlockView, ClientError> {
+        let block = self
+            .ckb
+            .get_block_by_number(0.into())?
+            .ok_or_else(|| ClientError::Rpc(String::from("genesis block is not found")))?;
+        Ok(block.into())
+    }
+
+    // The dep group of the secp256k1 sighash lock, which is created in the
+    // genesis block.
+    pub fn get_sighash_dep(&mut self) -> Result<CellDep, ClientError> {
+        let genesis = self.get_genesis_block()?;
+        let resolver = DefaultCellDepResolver::from_genesis(&genesis)
+            .map_err(|e| ClientError::Rpc(e.to_string()))?;
+        let (dep, _) = resolver
+            .sighash_dep()
+            .ok_or_else(|| ClientError::Rpc(String::from("sighash dep group is not found")))?;
+        Ok(dep.clone())
+    }
+}
+
+impl Client for RpcClient {
+    fn get_tip_block_number(&mut self) -> Result<u64, ClientError> {
+        Ok(self.ckb.get_tip_block_number()?.value())
+    }
+
+    fn send_transaction(&mut self, tx: TransactionView) -> Result<Byte32, ClientError> {
+        let hash = self
+            .ckb
+            .send_transaction(tx.data().into(), Some(OutputsValidator::Passthrough))?;
+        Ok(hash.pack())
+    }
+
+    fn get_cells(&mut self, type_script: &Script) -> Result<Vec<Cell>, ClientError> {
+        let search_key = SearchKey {
+            script: type_script.clone().into(),
+            script_type: ScriptType::Type,
+            script_search_mode: Some(ScriptSearchMode::Prefix),
+            filter: None,
+            with_data: Some(true),
+            group_by_transaction: None,
+        };
+        self.search_cells(search_key)
+    }
+
+    fn get_capacity_cells(&mut self, lock: &Script) -> Result<Vec<Cell>, ClientError> {
+        let empty = [0u64.into(), 1u64.into()];
+        let search_key = SearchKey {
+            script: lock.clone().into(),
+            script_type: ScriptType::Lock,
+            script_search_mode: Some(ScriptSearchMode::Exact),
+            filter: Some(SearchKeyFilter {
+                script: None,
+                script_len_range: Some(empty),
+                output_data_len_range: Some(empty),
+                output_capacity_range: None,
+                block_range: None,
+            }),
+            with_data: Some(false),
+            group_by_transaction: None,
+        };
+        self.search_cells(search_key)
+    }
+}
+
+impl RpcClient {
+    // Every page of the indexer
+    fn search_cells(&mut self, search_key: SearchKey) -> Result<Vec<Cell>, ClientError> {
+        let mut cells = vec![];
+        let mut after = None;
+        loop {
+            let page =
+                self.indexer
+                    .get_cells(search_key.clone(), Order::Asc, PAGE_SIZE.into(), after)?;
+            let len = page.objects.len();
+            page.objects.into_iter().for_each(|cell| {
+                let data = cell
+                    .output_data
+                    .map(|d| d.into_bytes().to_vec())
+                    .unwrap_or_default();
+                cells.push(Cell::new(cell.out_point.into(), cell.output.into(), data));
+            });
+            if len < PAGE_SIZE as usize {
+                break;
+            }
+            after = Some(page.last_cursor);
+        }
+        Ok(cells)
+    }
+}
@@ -1,3 +1,18 @@
 pub trait __CodeStr__ {
     fn __get_code_str__() -> &'static str;
+
+    // The type in the JSON schema of the app, see `AppSchema`
+    fn __get_type_schema__() -> crate::generators::contract::TypeSchema;
+
+    // The `impl Migrate` of the type, see `#[migration]`
+    fn __get_migration_code_str__() -> Option<&'static str> {
+        None
+    }
+
+    // The Molecule schema of the type, for `#[onchain(encoding = "molecule")]`
+    fn __get_molecule_decls__(_decls: &mut Vec<String>) {}
+
+    fn __is_molecule__() -> bool {
+        false
+    }
 }
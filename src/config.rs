@@ -0,0 +1,224 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use secp256k1::SecretKey;
+use serde::Deserialize;
+
+pub const CONFIG_FILE: &str = "ckboots.toml";
+
+// Takes precedence over `chain.secret_key` in `Config::load` so that the key
+// does not have to be written into `ckboots.toml`.
+pub const SECRET_KEY_ENV: &str = "CKBOOTS_SECRET_KEY";
+
+const SHANNONS_PER_CKB: u64 = 100_000_000;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub chain: ChainConfig,
+    pub boots: BootsConfig,
+    pub dev: DevConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    pub rpc: String,
+    pub secret_key: SecretKey,
+}
+
+#[derive(Debug, Clone)]
+pub struct BootsConfig {
+    pub app_name: String,
+    // In shannons. It is written in CKB in `ckboots.toml`.
+    pub initial_capacity: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DevConfig {
+    pub debug: bool,
+    pub contracts_dir: Option<PathBuf>,
+}
+
+impl Config {
+    // Relative paths in the file are resolved against the directory of the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let env_key = std::env::var(SECRET_KEY_ENV).ok();
+        let mut config = Config::parse(&content, env_key)?;
+        if let (Some(dir), Some(base)) = (config.dev.contracts_dir.as_mut(), path.parent()) {
+            if dir.is_relative() {
+                *dir = base.join(&dir);
+            }
+        }
+        Ok(config)
+    }
+
+    // `secret_key` takes precedence over `chain.secret_key`, see
+    // `SECRET_KEY_ENV`
+    pub fn parse(s: &str, secret_key: Option<String>) -> Result<Self, ConfigError> {
+        let raw: RawConfig = toml::from_str(s).map_err(ConfigError::Parse)?;
+        raw.validate(secret_key)
+    }
+}
+
+// The environment is left to `Config::load`
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Config::parse(s, None)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    // The file is not valid TOML or a key has the wrong type
+    Parse(toml::de::Error),
+    Missing(&'static str),
+    Invalid { key: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(e) => write!(f, "malformed {}: {}", CONFIG_FILE, e),
+            ConfigError::Missing(key) => write!(f, "missing `{}` in {}", key, CONFIG_FILE),
+            ConfigError::Invalid { key, reason } => {
+                write!(f, "invalid `{}` in {}: {}", key, CONFIG_FILE, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    chain: Option<RawChain>,
+    boots: Option<RawBoots>,
+    dev: Option<RawDev>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawChain {
+    rpc: Option<String>,
+    // `secreat_key` is how the key was spelled in the first README
+    #[serde(alias = "secreat_key")]
+    secret_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBoots {
+    app_name: Option<String>,
+    initial_capacity: Option<toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDev {
+    debug: Option<bool>,
+    contracts_dir: Option<PathBuf>,
+}
+
+impl RawConfig {
+    fn validate(self, env_key: Option<String>) -> Result<Config, ConfigError> {
+        let chain = self.chain.ok_or(ConfigError::Missing("chain"))?;
+        let boots = self.boots.ok_or(ConfigError::Missing("boots"))?;
+
+        let rpc = chain.rpc.ok_or(ConfigError::Missing("chain.rpc"))?;
+        if !rpc.starts_with("http://") && !rpc.starts_with("https://") {
+            return Err(ConfigError::Invalid {
+                key: "chain.rpc",
+                reason: format!("{} is not an http(s) url", rpc),
+            });
+        }
+        let secret_key = match env_key {
+            Some(key) => parse_secret_key(&key).map_err(|reason| ConfigError::Invalid {
+                key: SECRET_KEY_ENV,
+                reason,
+            })?,
+            None => {
+                let key = chain
+                    .secret_key
+                    .ok_or(ConfigError::Missing("chain.secret_key"))?;
+                parse_secret_key(&key).map_err(|reason| ConfigError::Invalid {
+                    key: "chain.secret_key",
+                    reason,
+                })?
+            }
+        };
+
+        let app_name = boots
+            .app_name
+            .ok_or(ConfigError::Missing("boots.app_name"))?;
+        if app_name.is_empty() {
+            return Err(ConfigError::Invalid {
+                key: "boots.app_name",
+                reason: String::from("it should not be empty"),
+            });
+        }
+        let initial_capacity = boots
+            .initial_capacity
+            .ok_or(ConfigError::Missing("boots.initial_capacity"))?;
+        let initial_capacity =
+            parse_capacity(&initial_capacity).map_err(|reason| ConfigError::Invalid {
+                key: "boots.initial_capacity",
+                reason,
+            })?;
+
+        let dev = match self.dev {
+            Some(dev) => DevConfig {
+                debug: dev.debug.unwrap_or(false),
+                contracts_dir: dev.contracts_dir,
+            },
+            None => DevConfig::default(),
+        };
+        if dev.debug && dev.contracts_dir.is_none() {
+            return Err(ConfigError::Missing("dev.contracts_dir"));
+        }
+
+        Ok(Config {
+            chain: ChainConfig { rpc, secret_key },
+            boots: BootsConfig {
+                app_name,
+                initial_capacity,
+            },
+            dev,
+        })
+    }
+}
+
+fn parse_secret_key(s: &str) -> Result<SecretKey, String> {
+    let s = s.trim();
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    if hex.len() != 64 {
+        return Err(String::from("expected 32 bytes in hex"));
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| String::from("expected 32 bytes in hex"))?;
+    SecretKey::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+// The capacity is given in CKB, either as an integer or a string.
+fn parse_capacity(value: &toml::Value) -> Result<u64, String> {
+    let ckb = match value {
+        toml::Value::Integer(i) => u64::try_from(*i).map_err(|_| format!("{} is negative", i))?,
+        toml::Value::String(s) => s
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("{:?} is not a number of CKB", s))?,
+        v => return Err(format!("expected a number of CKB, found {}", v.type_str())),
+    };
+    ckb.checked_mul(SHANNONS_PER_CKB)
+        .ok_or_else(|| format!("{} CKB is too large", ckb))
+}
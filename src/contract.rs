@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct ContractResult {
     // Ids of the on-chain statuses that will be read while executing this contract
     pub deps: Vec<&'static str>,
@@ -6,4 +7,20 @@ pub struct ContractResult {
     // The id of this contract
     pub contract_id: &'static str,
     pub user_input: Option<Vec<u8>>,
+    // The `_version()` of the overridden statuses, which the output cells are
+    // written with
+    pub versions: Vec<(&'static str, u8)>,
+    // The instance keys of the keyed statuses which are read or overridden,
+    // see `#[onchain(keyed)]`. The others are the instance of the empty key.
+    pub keys: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl ContractResult {
+    // The instance key of the status `id`
+    pub fn key(&self, id: &str) -> &[u8] {
+        self.keys
+            .iter()
+            .find(|(k_id, _)| *k_id == id)
+            .map_or(&[], |(_, key)| key.as_slice())
+    }
 }
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use ckb_hash::blake2b_256;
+use ckb_script::{ScriptError, ScriptGroupType, TransactionScriptsVerifier};
+use ckb_traits::{CellDataProvider, HeaderProvider};
+use ckb_types::bytes::Bytes;
+use ckb_types::core::cell::{CellMeta, CellMetaBuilder, ResolvedTransaction};
+use ckb_types::core::{HeaderView, ScriptHashType, TransactionView};
+use ckb_types::packed::{Byte32, CellOutput, OutPoint, Script};
+use ckb_types::prelude::{Builder, Entity, Pack};
+
+use crate::app::{registry_data, BuildError, Cell, Deployment, StateKey, REGISTRY_ID};
+
+// The max cycles of a block on the mainnet, a tx cannot consume more
+pub const MAX_CYCLES: u64 = 3_500_000_000;
+
+// The `Error` returned by the generated `_entry` and contracts, which is the
+// exit code of the script. Keep it in sync with `get_error_code()` of the
+// generators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i8)]
+pub enum ContractError {
+    IndexOutOfBound = 1,
+    ItemMissing,
+    LengthNotEnough,
+    TypeError,
+    Encoding,
+    NotEqual,
+    Version,
+    Key,
+    Lock,
+    Exec,
+}
+
+impl ContractError {
+    pub fn from_exit_code(code: i8) -> Option<Self> {
+        let error = match code {
+            1 => ContractError::IndexOutOfBound,
+            2 => ContractError::ItemMissing,
+            3 => ContractError::LengthNotEnough,
+            4 => ContractError::TypeError,
+            5 => ContractError::Encoding,
+            6 => ContractError::NotEqual,
+            7 => ContractError::Version,
+            8 => ContractError::Key,
+            9 => ContractError::Lock,
+            10 => ContractError::Exec,
+            _ => return None,
+        };
+        Some(error)
+    }
+}
+
+#[derive(Debug)]
+pub enum DryRunError {
+    // `dev.contracts_dir` is not set in `ckboots.toml`
+    NoContractsDir,
+    // The compiled contract cannot be read, perhaps `capsule build` is not run
+    Binary(PathBuf, std::io::Error),
+    // The script exits with a non-zero code, `error` is `None` if the code is
+    // not one of the generated `Error`, like a panic
+    Failed {
+        exit_code: i8,
+        error: Option<ContractError>,
+    },
+    // The script cannot be run at all, e.g. it exceeds the max cycles
+    Script(String),
+    // The tx to run cannot be built
+    Build(BuildError),
+}
+
+impl fmt::Display for DryRunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DryRunError::NoContractsDir => write!(f, "dev.contracts_dir is not set"),
+            DryRunError::Binary(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            DryRunError::Failed {
+                exit_code,
+                error: Some(error),
+            } => write!(f, "script exits with {} ({:?})", exit_code, error),
+            DryRunError::Failed {
+                exit_code,
+                error: None,
+            } => write!(f, "script exits with {}", exit_code),
+            DryRunError::Script(msg) => write!(f, "script error: {}", msg),
+            DryRunError::Build(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DryRunError {}
+
+impl From<BuildError> for DryRunError {
+    fn from(e: BuildError) -> Self {
+        DryRunError::Build(e)
+    }
+}
+
+impl From<ScriptError> for DryRunError {
+    fn from(e: ScriptError) -> Self {
+        match e {
+            ScriptError::ValidationFailure(_, exit_code) => DryRunError::Failed {
+                exit_code,
+                error: ContractError::from_exit_code(exit_code),
+            },
+            e => DryRunError::Script(e.to_string()),
+        }
+    }
+}
+
+// Code cells made up for the compiled `_entry` and contracts. The binaries are
+// not the deployed cells, so their type hashes are made up as well, and the
+// state cells are rewritten to point at the made up `_entry`.
+pub(crate) struct MockDeployment {
+    pub deployment: Deployment,
+    code_cells: Vec<CellMeta>,
+}
+
+impl MockDeployment {
+    pub fn new(entry: Vec<u8>, contracts: Vec<(&str, Vec<u8>)>) -> Self {
+        let (out_point, entry_hash, entry_cell) = mock_code_cell("_entry", entry);
+        let mut deployment = Deployment {
+            entry: (out_point, entry_hash),
+            ..Default::default()
+        };
+        let mut code_cells = vec![entry_cell];
+        contracts.into_iter().for_each(|(name, binary)| {
+            let (out_point, type_hash, cell) = mock_code_cell(name, binary);
+            deployment
+                .contracts
+                .insert(name.to_string(), (out_point, type_hash));
+            code_cells.push(cell);
+        });
+        MockDeployment {
+            deployment,
+            code_cells,
+        }
+    }
+
+    // The state cells whose type script runs the made up `_entry`, and the
+    // registry listing the made up type hashes of `registered`
+    pub fn state_cells(
+        &self,
+        cells: &HashMap<StateKey, Cell>,
+        registered: &[&str],
+    ) -> HashMap<StateKey, Cell> {
+        let (_, entry_hash) = &self.deployment.entry;
+        let hashes = registered
+            .iter()
+            .map(|name| mock_type_script(name).calc_script_hash())
+            .collect::<Vec<_>>();
+        cells
+            .iter()
+            .map(|(state_key, cell)| {
+                let mut cell = cell.clone();
+                if let Some(script) = cell.output.type_().to_opt() {
+                    let script = script.as_builder().code_hash(entry_hash.clone()).build();
+                    cell.output = cell.output.as_builder().type_(Some(script).pack()).build();
+                }
+                if state_key.0 == REGISTRY_ID {
+                    cell.data = registry_data(hashes.iter());
+                }
+                (state_key.clone(), cell)
+            })
+            .collect()
+    }
+
+    // Run the type script of the state cells, which is `_entry` exec'ing the
+    // contract. Lock scripts are not run since the tx is not signed. Returns
+    // the consumed cycles.
+    pub fn verify(
+        &self,
+        tx: TransactionView,
+        cells: &HashMap<StateKey, Cell>,
+    ) -> Result<u64, DryRunError> {
+        let by_out_point = cells
+            .values()
+            .map(|cell| (cell.outpoint.clone(), cell))
+            .collect::<HashMap<_, _>>();
+        let resolve = |out_point: OutPoint| {
+            by_out_point
+                .get(&out_point)
+                .map(|cell| cell_meta(cell))
+                .or_else(|| {
+                    self.code_cells
+                        .iter()
+                        .find(|meta| meta.out_point == out_point)
+                        .cloned()
+                })
+                .ok_or_else(|| DryRunError::Script(format!("{} is not found", out_point)))
+        };
+        let resolved_inputs = tx
+            .input_pts_iter()
+            .map(resolve)
+            .collect::<Result<Vec<_>, _>>()?;
+        let resolved_cell_deps = tx
+            .cell_deps_iter()
+            .map(|dep| resolve(dep.out_point()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let rtx = ResolvedTransaction {
+            transaction: tx,
+            resolved_cell_deps,
+            resolved_inputs,
+            resolved_dep_groups: vec![],
+        };
+
+        let verifier = TransactionScriptsVerifier::new(&rtx, &NoStore);
+        let groups = verifier
+            .groups_with_type()
+            .filter(|(group_type, _, _)| *group_type == ScriptGroupType::Type)
+            .map(|(_, hash, _)| hash.clone())
+            .collect::<Vec<_>>();
+        groups.iter().try_fold(0, |cycles, hash| {
+            let consumed = verifier.verify_single(ScriptGroupType::Type, hash, MAX_CYCLES)?;
+            Ok(cycles + consumed)
+        })
+    }
+}
+
+fn mock_code_cell(name: &str, binary: Vec<u8>) -> (OutPoint, Byte32, CellMeta) {
+    let out_point = OutPoint::new(blake2b_256(name.as_bytes()).pack(), 0);
+    let type_script = mock_type_script(name);
+    let type_hash = type_script.calc_script_hash();
+    let output = CellOutput::new_builder()
+        .type_(Some(type_script).pack())
+        .build();
+    let meta = CellMetaBuilder::from_cell_output(output, Bytes::from(binary))
+        .out_point(out_point.clone())
+        .build();
+    (out_point, type_hash, meta)
+}
+
+fn mock_type_script(name: &str) -> Script {
+    Script::new_builder()
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(name.as_bytes().to_vec()).pack())
+        .build()
+}
+
+fn cell_meta(cell: &Cell) -> CellMeta {
+    CellMetaBuilder::from_cell_output(cell.output.clone(), Bytes::from(cell.data.clone()))
+        .out_point(cell.outpoint.clone())
+        .build()
+}
+
+// Every cell of the mock tx carries its data, so nothing is loaded from a store.
+struct NoStore;
+
+impl CellDataProvider for NoStore {
+    fn get_cell_data(&self, _out_point: &OutPoint) -> Option<Bytes> {
+        None
+    }
+
+    fn get_cell_data_hash(&self, _out_point: &OutPoint) -> Option<Byte32> {
+        None
+    }
+}
+
+impl HeaderProvider for NoStore {
+    fn get_header(&self, _hash: &Byte32) -> Option<HeaderView> {
+        None
+    }
+}
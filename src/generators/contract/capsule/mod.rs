@@ -28,7 +28,7 @@ name = "_entry"
 template_type = "Rust"
 "#;
     contracts
-        .into_iter()
+        .iter()
         .fold(String::from(prelude), |mut prev, c| {
             let contract = format!(
                 r#"[[contracts]]
@@ -42,7 +42,7 @@ template_type = "Rust"
 }
 
 fn get_cargo_toml(contracts: &[&str]) -> String {
-    let members = contracts.into_iter().fold(
+    let members = contracts.iter().fold(
         String::from(r#""contracts/types", "contracts/_entry","#),
         |mut prev, c| {
             let member = format!(r#""contracts/{}","#, c);
@@ -53,6 +53,10 @@ pub enum Error {
     TypeError,
     Encoding,
     NotEqual,
+    Version,
+    Key,
+    Lock,
+    Exec,
 }
 
 impl From<SysError> for Error {
@@ -68,6 +72,15 @@ impl From<SysError> for Error {
     }
 }
 
+impl From<types::DecodeError> for Error {
+    fn from(err: types::DecodeError) -> Self {
+        match err {
+            types::DecodeError::UnknownVersion(_) => Self::Version,
+            _ => Self::Encoding,
+        }
+    }
+}
+
     "#
 }
 
@@ -122,7 +135,7 @@ use core::result::Result;
 // https://doc.rust-lang.org/alloc/index.html
 use alloc::{{vec, vec::Vec}};
 use crate::error::Error;
-use types::OnChain;
+use types::*;
 
 pub fn main() -> Result<(), Error> {{
     {content}
@@ -136,7 +149,7 @@ pub fn main() -> Result<(), Error> {{
 
 fn load_cell_deps(data: &[(String, String)]) -> String {
     let string =
-        data.into_iter()
+        data.iter()
             .enumerate()
             .fold("".to_string(), |mut prev, (idx, (ident, type_path))| {
                 let ident = ident.trim_matches('"');
@@ -144,9 +157,9 @@ fn load_cell_deps(data: &[(String, String)]) -> String {
                 let s = format!(
                     "
 let bytes = types::load_cell_deps_data({idx})?;
-let wrapper = <types::OnChainWrapper as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let wrapper = types::decode::<types::OnChainWrapper>(&bytes)?;
 let bytes = wrapper.data;
-let {ident} = <types::{type_path} as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let {ident} = types::decode_versioned::<{type_path}>(wrapper.version, &bytes)?;
 "
                 );
                 prev.push_str(&s);
@@ -163,15 +176,17 @@ fn load_user_input(data: Option<(String, String)>) -> String {
     let ident = data.0.trim_matches('"');
     let type_path = data.1.trim_matches('"');
 
-    format!("
-let bytes = types::load_user_input();
-let {ident} = <types::{type_path} as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
-")
+    format!(
+        "
+let bytes = types::load_user_input()?;
+let {ident} = types::decode::<{type_path}>(&bytes)?;
+"
+    )
 }
 
 fn load_input(data: &[(String, String)]) -> String {
     let string =
-        data.into_iter()
+        data.iter()
             .enumerate()
             .fold("".to_string(), |mut prev, (idx, (ident, type_path))| {
                 let ident = ident.trim_matches('"');
@@ -179,10 +194,10 @@ fn load_input(data: &[(String, String)]) -> String {
                 let s = format!(
                     "
 let bytes = types::load_input_data({idx})?;
-let wrapper = <types::OnChainWrapper as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let wrapper = types::decode::<types::OnChainWrapper>(&bytes)?;
 let input_id = wrapper.idx;
 let bytes = wrapper.data;
-let mut {ident} = <types::{type_path} as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let mut {ident} = types::decode_versioned::<{type_path}>(wrapper.version, &bytes)?;
 "
                 );
                 prev.push_str(&s);
@@ -193,7 +208,7 @@ let mut {ident} = <types::{type_path} as types::OnChain>::_from_bytes(&bytes).ok
 
 fn load_output(data: &[(String, String)]) -> String {
     let string =
-        data.into_iter()
+        data.iter()
             .enumerate()
             .fold("".to_string(), |mut prev, (idx, (ident, type_path))| {
                 let ident = ident.trim_matches('"');
@@ -201,12 +216,16 @@ fn load_output(data: &[(String, String)]) -> String {
                 let s = format!(
                     "
 let bytes = types::load_output_data({idx})?;
-let wrapper = <types::OnChainWrapper as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+let wrapper = types::decode::<types::OnChainWrapper>(&bytes)?;
 let output_id = wrapper.idx;
 if input_id != output_id {{
     return Err(crate::error::Error::TypeError);
 }}
-let {ident}_output = <types::{type_path} as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
+if wrapper.version != <{type_path} as types::OnChain>::_version() {{
+    return Err(crate::error::Error::Version);
+}}
+let bytes = wrapper.data;
+let {ident}_output = types::decode::<{type_path}>(&bytes)?;
 if !{ident}._eq(&{ident}_output) {{
     return Err(crate::error::Error::NotEqual);
 }}
@@ -38,14 +38,60 @@ use core::result::Result;
 // Import heap related library from `alloc`
 // https://doc.rust-lang.org/alloc/index.html
 use alloc::{{vec, vec::Vec}};
+use ckb_std::error::SysError;
 use crate::error::Error;
-use types::OnChain;
+use types::*;
 
 pub fn main() -> Result<(), Error> {{
     let script = types::load_exec_script()?;
     if script.len() == 0 {{
-        {output}
+        // Spending state cells always runs a contract
+        if types::has_group_inputs()? {{
+            return Err(Error::Exec);
+        }}
+        if !types::check_instance_locks()? {{
+            return Err(Error::Lock);
+        }}
+        // New state cells, which are the genesis or new keyed instances,
+        // hold the default value, once per id
+        if !types::check_new_instances()? {{
+            return Err(Error::Key);
+        }}
+        let keyed = !types::load_instance_key()?.is_empty();
+        let mut created: Vec<u8> = vec![];
+        let mut idx = 0;
+        loop {{
+            let bytes = match types::load_group_output_data(idx) {{
+                Ok(bytes) => bytes,
+                Err(SysError::IndexOutOfBound) => break,
+                Err(err) => return Err(err.into()),
+            }};
+            let wrapper = types::decode::<types::OnChainWrapper>(&bytes)?;
+            if created.contains(&wrapper.idx) {{
+                return Err(Error::Key);
+            }}
+            created.push(wrapper.idx);
+            match wrapper.idx {{
+                {output}
+                types::REGISTRY_IDX if !keyed => {{
+                    types::decode::<Vec<[u8; 32]>>(&wrapper.data)?;
+                }}
+                _ => return Err(Error::TypeError),
+            }}
+            idx += 1;
+        }}
     }} else {{
+        if !types::check_instance_keys()? {{
+            return Err(Error::Key);
+        }}
+        if !types::check_instance_locks()? {{
+            return Err(Error::Lock);
+        }}
+        // Only the contracts of the app change its state cells, and the
+        // registry listing them never changes
+        if types::has_group_registry()? || !types::is_registered(&script)? {{
+            return Err(Error::Exec);
+        }}
         types::exec_script(&script)?;
     }}
     Ok(())
@@ -55,24 +101,34 @@ pub fn main() -> Result<(), Error> {{
     s
 }
 
+// A match arm per state cell, whose wrapper idx is the position of its id in
+// the sorted ids
 fn load_output(data: &[(String, String)]) -> String {
-    let string =
-        data.into_iter()
-            .enumerate()
-            .fold("".to_string(), |mut prev, (idx, (ident, type_path))| {
-                let ident = ident.trim_matches('"');
-                let type_path = type_path.trim_matches('"');
-                let s = format!("
-let bytes = types::load_output_data({idx});
-let {ident}_output = <types::{type_path} as types::OnChain>::_from_bytes(&bytes).ok_or(crate::error::Error::Encoding)?;
-let _default = <types::{type_path} as types::OnChain>::_default();
-if !{ident}_output.eq(&{ident}_output) {{
-    return Err(crate::error::Error::NotEqual);
+    let string = data
+        .iter()
+        .enumerate()
+        .fold("".to_string(), |mut prev, (idx, (_, type_path))| {
+            let type_path = type_path.trim_matches('"');
+            let s = format!(
+                "
+{idx} => {{
+    if keyed && !<{type_path} as types::OnChain>::_keyed() {{
+        return Err(Error::Key);
+    }}
+    if wrapper.version != <{type_path} as types::OnChain>::_version() {{
+        return Err(Error::Version);
+    }}
+    let output = types::decode::<{type_path}>(&wrapper.data)?;
+    let _default = <{type_path} as types::OnChain>::_default();
+    if !types::OnChain::_eq(&output, &_default) {{
+        return Err(Error::NotEqual);
+    }}
 }}
-");
-        prev.push_str(&s);
-        prev
-            });
+"
+            );
+            prev.push_str(&s);
+            prev
+        });
     string
 }
 
@@ -86,8 +142,13 @@ pub enum Error {
     IndexOutOfBound = 1,
     ItemMissing,
     LengthNotEnough,
+    TypeError,
     Encoding,
     NotEqual,
+    Version,
+    Key,
+    Lock,
+    Exec,
 }
 
 impl From<SysError> for Error {
@@ -103,6 +164,15 @@ impl From<SysError> for Error {
     }
 }
 
+impl From<types::DecodeError> for Error {
+    fn from(err: types::DecodeError) -> Self {
+        match err {
+            types::DecodeError::UnknownVersion(_) => Self::Version,
+            _ => Self::Encoding,
+        }
+    }
+}
+
     "#
 }
 
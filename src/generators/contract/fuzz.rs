@@ -0,0 +1,85 @@
+// A cargo-fuzz crate next to the contracts, which builds the types crate
+// natively and feeds the decoders of the registered types with cell data like
+// the contracts read, so that a panic shows up before the contracts ship.
+// Run it with `cargo fuzz run types` in `proj-name-contracts/fuzz`.
+pub fn write_fuzz(save_path: &str, types: &[&str]) {
+    let p = env!("CARGO_MANIFEST_DIR");
+    let target = std::path::Path::new(p);
+    let fuzz_dir = target.join(save_path).join("fuzz");
+    let _ = std::fs::remove_dir_all(fuzz_dir.clone());
+    std::fs::create_dir_all(fuzz_dir.clone()).unwrap();
+
+    std::fs::write(
+        fuzz_dir.join("Cargo.toml"),
+        r#"[package]
+name = "types-fuzz"
+version = "0.0.0"
+publish = false
+edition = "2021"
+
+[package.metadata]
+cargo-fuzz = true
+
+[dependencies]
+libfuzzer-sys = "0.4"
+types = {path = "../contracts/types"}
+
+# Built natively, out of the workspace of the contracts
+[workspace]
+members = ["."]
+
+[[bin]]
+name = "types"
+path = "fuzz_targets/types.rs"
+test = false
+doc = false
+"#,
+    )
+    .unwrap();
+    std::fs::write(
+        fuzz_dir.join(".gitignore"),
+        "target\ncorpus\nartifacts\ncoverage\n",
+    )
+    .unwrap();
+
+    let targets_dir = fuzz_dir.join("fuzz_targets");
+    std::fs::create_dir_all(targets_dir.clone()).unwrap();
+    std::fs::write(targets_dir.join("types.rs"), get_target_code(types)).unwrap();
+}
+
+fn get_target_code(types: &[&str]) -> String {
+    let checks = types.iter().fold(String::new(), |mut prev, t| {
+        prev.push_str(&format!("    check::<{t}>,\n"));
+        prev
+    });
+    format!(
+        r#"#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use types::*;
+
+// Decode `data` like the contracts do with the data of a state cell, and as
+// the current version of the state, whose encoding has to be `data` then
+fn check<T: OnChain>(data: &[u8]) {{
+    if let Ok(wrapper) = decode::<OnChainWrapper>(data) {{
+        if let Ok(state) = decode_versioned::<T>(wrapper.version, &wrapper.data) {{
+            assert_eq!(state._capacity(), state._to_bytes().len() as u64);
+        }}
+    }}
+    if let Ok(state) = decode::<T>(data) {{
+        assert_eq!(state._to_bytes(), data, "the encoding is not canonical");
+    }}
+}}
+
+// The first byte picks the registered type
+const CHECKS: &[fn(&[u8])] = &[
+{checks}];
+
+fuzz_target!(|data: &[u8]| {{
+    if let Some((selector, data)) = data.split_first() {{
+        CHECKS[*selector as usize % CHECKS.len()](data);
+    }}
+}});
+"#
+    )
+}
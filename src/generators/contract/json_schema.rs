@@ -0,0 +1,86 @@
+use ckb_jsonrpc_types::JsonBytes;
+use serde::{Serialize, Serializer};
+
+// The description of the registered types and contracts for clients that are
+// not written in Rust, like a web front-end building user inputs
+#[derive(Serialize)]
+pub struct AppSchema {
+    pub types: Vec<TypeSchema>,
+    pub contracts: Vec<ContractSchema>,
+}
+
+#[derive(Serialize)]
+pub struct TypeSchema {
+    pub name: String,
+    pub id: Option<&'static str>,
+    pub version: u8,
+    // The state cells are instances keyed by the type script args, see
+    // `#[onchain(keyed)]`
+    pub keyed: bool,
+    // The instances are locked by their owners, see `#[onchain(owned)]`
+    pub owned: bool,
+    // "default" or "molecule"
+    pub encoding: &'static str,
+    // None if the bytes are prefixed with their length
+    pub fixed_size: Option<u64>,
+    // The encoded default value, as the genesis tx writes it
+    #[serde(serialize_with = "serialize_bytes")]
+    pub default: Vec<u8>,
+    #[serde(flatten)]
+    pub layout: LayoutSchema,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayoutSchema {
+    Struct { fields: Vec<FieldSchema> },
+    Enum { variants: Vec<VariantSchema> },
+}
+
+// Tuple fields are named by their index
+#[derive(Serialize)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub fixed_size: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct VariantSchema {
+    pub name: &'static str,
+    pub tag: u8,
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Serialize)]
+pub struct ContractSchema {
+    pub id: &'static str,
+    // The states the contract updates
+    pub mutates: Vec<ArgSchema>,
+    // The states the contract reads as cell deps
+    pub deps: Vec<ArgSchema>,
+    pub user_input: Option<ArgSchema>,
+}
+
+#[derive(Serialize)]
+pub struct ArgSchema {
+    pub name: &'static str,
+    pub id: Option<&'static str>,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+}
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    JsonBytes::from_vec(bytes.to_vec()).serialize(serializer)
+}
+
+pub fn write_json_schema(save_path: &str, schema: &AppSchema) {
+    let content = serde_json::to_string_pretty(schema).unwrap();
+
+    let p = env!("CARGO_MANIFEST_DIR");
+    let target = std::path::Path::new(p);
+    let schemas_dir = target.join(save_path).join("schemas");
+    std::fs::create_dir_all(schemas_dir.clone()).unwrap();
+    std::fs::write(schemas_dir.join("app.json"), content).unwrap();
+}
@@ -1,10 +1,20 @@
 mod capsule;
+#[allow(clippy::module_inception)]
 mod contract;
 mod entry;
+mod fuzz;
+mod json_schema;
+mod schema;
 mod types;
 
 pub use capsule::write_capsule;
 pub use contract::get_contract_code;
 pub use contract::write_contract;
 pub use entry::write_entry;
+pub use fuzz::write_fuzz;
+pub use json_schema::{
+    write_json_schema, AppSchema, ArgSchema, ContractSchema, FieldSchema, LayoutSchema, TypeSchema,
+    VariantSchema,
+};
+pub use schema::{declare_default_encoding, molecule_schema, write_schema};
 pub use types::write_types;
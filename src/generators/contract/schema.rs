@@ -0,0 +1,68 @@
+use crate::{molecule_declare, Molecule, OnChainWrapper};
+
+// The Molecule schema of the registered types, so that other tools can read
+// their cells, see `declare_default_encoding` for the types without
+// `#[onchain(encoding = "molecule")]`.
+//
+// The data of a cell is the `OnChainWrapper` table declared first, whose
+// `data` is the state of the type at `idx` in the sorted ids.
+pub fn molecule_schema(decls: Vec<String>) -> String {
+    let mut all = vec![];
+    OnChainWrapper::_molecule_decls(&mut all);
+    decls
+        .into_iter()
+        .for_each(|decl| molecule_declare(&mut all, decl));
+    let mut content = String::from("// Generated by ckboots, do not edit\n");
+    all.into_iter().for_each(|decl| {
+        content.push('\n');
+        content.push_str(&decl);
+        content.push('\n');
+    });
+    content
+}
+
+// Write `molecule_schema` to `schemas/types.mol`. Nothing is written without
+// types.
+pub fn write_schema(save_path: &str, decls: Vec<String>) {
+    if decls.is_empty() {
+        return;
+    }
+    let content = molecule_schema(decls);
+
+    let p = env!("CARGO_MANIFEST_DIR");
+    let target = std::path::Path::new(p);
+    let schemas_dir = target.join(save_path).join("schemas");
+    std::fs::create_dir_all(schemas_dir.clone()).unwrap();
+    std::fs::write(schemas_dir.join("types.mol"), content).unwrap();
+}
+
+// Declare a type in the default encoding of ckboots, whose fields Molecule
+// cannot describe. A fixed-size type is as many bytes, and the others are
+// only noted. `name` is the registered one, like `Pair<u8, Item>`, and
+// becomes `PairU8Item`.
+pub fn declare_default_encoding(decls: &mut Vec<String>, name: &str, fixed_size: Option<u64>) {
+    let molecule_name = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    let decl = match fixed_size {
+        Some(size) => format!(
+            "// {} is in the default encoding of ckboots\narray {} [byte; {}];",
+            name, molecule_name, size
+        ),
+        None => format!(
+            "// {} is in the default encoding of ckboots, which has a length prefix\n\
+             // of 8 bytes, see app.json for its layout",
+            name
+        ),
+    };
+    if !decls.contains(&decl) {
+        decls.push(decl);
+    }
+}
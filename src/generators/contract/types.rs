@@ -3,8 +3,14 @@ pub fn write_types(save_path: &str, types: Vec<&str>) {
     let on_chain = include_str!("../../on_chain.rs");
     content.push_str(on_chain);
     content.push_str(get_utils());
+    content.push_str(include_str!("../../witness.rs"));
+    // The instances of a generic type share its code
+    let mut written: Vec<&str> = vec![];
     types.into_iter().for_each(|s| {
-        content.push_str(s);
+        if !written.contains(&s) {
+            content.push_str(s);
+            written.push(s);
+        }
     });
 
     let p = env!("CARGO_MANIFEST_DIR");
@@ -24,6 +30,7 @@ edition = "2021"
 [dependencies]
 ckb-std = "=0.10.0"
 ckb-standalone-types = {version = "=0.1.2", default-features = false}
+blake2b-rs = "0.2"
 "#
         .trim_start(),
     )
@@ -38,11 +45,17 @@ pub fn get_utils() -> &'static str {
     r#"
 use ckb_std::ckb_constants::Source;
 use ckb_std::high_level::load_cell_data;
+use ckb_std::high_level::load_cell;
+use ckb_std::high_level::load_cell_type;
+use ckb_std::high_level::load_input;
+use ckb_std::high_level::load_script;
 use ckb_std::high_level::exec_cell;
 use ckb_std::high_level::load_witness_args;
 use ckb_std::syscalls::SysError;
+use ckb_standalone_types::packed::{CellOutput, WitnessArgs};
 use ckb_standalone_types::prelude::Entity;
 use ckb_standalone_types::core::ScriptHashType;
+use blake2b_rs::Blake2bBuilder;
 
 pub fn load_cell_deps_data(idx: usize) -> Result<Vec<u8>, SysError> {
     load_cell_data(idx, Source::CellDep)
@@ -56,22 +69,183 @@ pub fn load_output_data(idx: usize) -> Result<Vec<u8>, SysError> {
     load_cell_data(idx, Source::Output)
 }
 
+pub fn load_group_output_data(idx: usize) -> Result<Vec<u8>, SysError> {
+    load_cell_data(idx, Source::GroupOutput)
+}
+
+// Every state cell of the app in the inputs or the outputs is paired with the
+// same instance at the same index on the other side, so that a contract can
+// neither swap keyed instances nor burn or mint state cells
+pub fn check_instance_keys() -> Result<bool, SysError> {
+    check_instances(true, |input, output| {
+        input.type_().as_slice() == output.type_().as_slice()
+    })
+}
+
+// The outputs keep the locks of the inputs, so that the operator cannot take
+// the cells of an owner
+pub fn check_instance_locks() -> Result<bool, SysError> {
+    check_instances(false, |input, output| {
+        input.lock().as_slice() == output.lock().as_slice()
+    })
+}
+
+// Whether `same` holds for every state cell of the app in the inputs, or in
+// the outputs too if `both_sides`, and the cell at its index on the other side
+fn check_instances(
+    both_sides: bool,
+    same: impl Fn(&CellOutput, &CellOutput) -> bool,
+) -> Result<bool, SysError> {
+    let code_hash = load_script()?.code_hash();
+    let is_state = |cell: &Option<CellOutput>| {
+        cell.as_ref()
+            .and_then(|c| c.type_().to_opt())
+            .map_or(false, |s| s.code_hash().as_slice() == code_hash.as_slice())
+    };
+    let mut idx = 0;
+    loop {
+        let input = load_cell_at(idx, Source::Input)?;
+        let output = load_cell_at(idx, Source::Output)?;
+        if input.is_none() && output.is_none() {
+            return Ok(true);
+        }
+        if is_state(&input) || (both_sides && is_state(&output)) {
+            match (input, output) {
+                (Some(input), Some(output)) if same(&input, &output) => {}
+                _ => return Ok(false),
+            }
+        }
+        idx += 1;
+    }
+}
+
+// Whether the registry is among the cells of the group, which only the genesis
+// creates and nothing spends
+pub fn has_group_registry() -> Result<bool, SysError> {
+    for source in [Source::GroupInput, Source::GroupOutput] {
+        let mut idx = 0;
+        loop {
+            let data = match load_cell_data(idx, source) {
+                Ok(data) => data,
+                Err(SysError::IndexOutOfBound) => break,
+                Err(err) => return Err(err),
+            };
+            if decode::<OnChainWrapper>(&data).map_or(false, |w| w.idx == REGISTRY_IDX) {
+                return Ok(true);
+            }
+            idx += 1;
+        }
+    }
+    Ok(false)
+}
+
+// Whether `code_hash` is one of the contracts in the registry of the app. The
+// registry is the cell dep whose type script is the one of the app without an
+// instance key, and whose wrapper idx is `REGISTRY_IDX`. Only the genesis can
+// create such a cell, see `check_new_instances`.
+pub fn is_registered(code_hash: &[u8]) -> Result<bool, SysError> {
+    let script = load_script()?;
+    let args = script.args().raw_data();
+    let app_args = match args.get(..32) {
+        Some(app_args) => app_args,
+        None => return Ok(false),
+    };
+    let mut idx = 0;
+    loop {
+        let dep = match load_cell_type(idx, Source::CellDep) {
+            Ok(dep) => dep,
+            Err(SysError::IndexOutOfBound) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let is_app = dep.map_or(false, |s| {
+            s.code_hash().as_slice() == script.code_hash().as_slice()
+                && s.hash_type().as_slice() == script.hash_type().as_slice()
+                && s.args().raw_data().as_ref() == app_args
+        });
+        if is_app {
+            let data = load_cell_data(idx, Source::CellDep)?;
+            if let Ok(wrapper) = decode::<OnChainWrapper>(&data) {
+                if wrapper.idx == REGISTRY_IDX {
+                    let hashes = decode::<Vec<[u8; 32]>>(&wrapper.data).unwrap_or_default();
+                    return Ok(hashes.iter().any(|hash| hash.as_slice() == code_hash));
+                }
+            }
+        }
+        idx += 1;
+    }
+}
+
+// Whether the tx spends state cells of the group
+pub fn has_group_inputs() -> Result<bool, SysError> {
+    Ok(load_cell_at(0, Source::GroupInput)?.is_some())
+}
+
+fn load_cell_at(idx: usize, source: Source) -> Result<Option<CellOutput>, SysError> {
+    match load_cell(idx, source) {
+        Ok(cell) => Ok(Some(cell)),
+        Err(SysError::IndexOutOfBound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+// The instance key after the 32 bytes of the app args, empty unless the
+// state cells of the group are a keyed instance
+pub fn load_instance_key() -> Result<Vec<u8>, SysError> {
+    let args = load_script()?.args().raw_data();
+    Ok(args.get(32..).map_or(vec![], |key| key.to_vec()))
+}
+
+// New state cells cannot take the ids or keys of existing ones. The app args
+// are the hash of the first input of the genesis and the app name in its user
+// input, and that input is spent for good. A key is the hash of the first input
+// of the tx creating the instance and the output index, like the type id, and
+// a tx creates one cell of a key.
+pub fn check_new_instances() -> Result<bool, SysError> {
+    let script = load_script()?;
+    let args = script.args().raw_data();
+    if args.len() < 32 {
+        return Ok(false);
+    }
+    let (app_args, key) = args.split_at(32);
+    let first_input = load_input(0, Source::Input)?;
+    if key.is_empty() {
+        let input_hash = blake2b_256(&[first_input.as_slice()]);
+        let app_name = load_user_input()?;
+        return Ok(blake2b_256(&[&input_hash, &app_name]) == app_args);
+    }
+    if load_cell_at(1, Source::GroupOutput)?.is_some() {
+        return Ok(false);
+    }
+    let mut idx = 0;
+    loop {
+        match load_cell_type(idx, Source::Output)? {
+            Some(s) if s.as_slice() == script.as_slice() => break,
+            _ => idx += 1,
+        }
+    }
+    let index = (idx as u64).to_le_bytes();
+    Ok(blake2b_256(&[first_input.as_slice(), &index]) == key)
+}
+
+// The blake2b of CKB
+fn blake2b_256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut blake2b = Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build();
+    parts.iter().for_each(|part| blake2b.update(part));
+    let mut hash = [0u8; 32];
+    blake2b.finalize(&mut hash);
+    hash
+}
+
 pub fn load_user_input() -> Result<Vec<u8>, SysError> {
     let witness_arg = load_witness_args(0, Source::Input)?;
-    if let Some(b) = witness_arg.input_type().to_opt() {
-        Ok(b.as_slice().to_vec())
-    } else {
-        Ok(vec![])
-    }
+    Ok(witness_user_input(&witness_arg))
 }
 
 pub fn load_exec_script() -> Result<Vec<u8>, SysError> {
     let witness_arg = load_witness_args(0, Source::Input)?;
-    if let Some(b) = witness_arg.output_type().to_opt() {
-        Ok(b.as_slice().to_vec())
-    } else {
-        Ok(vec![])
-    }
+    Ok(witness_exec_script(&witness_arg))
 }
 
 pub fn exec_script(code_hash: &[u8]) -> Result<u64, SysError> {
@@ -95,11 +269,15 @@ use core::clone::Clone;
 use core::iter::Extend;
 use core::iter::Iterator;
 
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 
 #[macro_use]
 extern crate alloc;
 
+// The generated impls refer to `ckboots::OnChain` and friends
+use crate as ckboots;
+
 "#
 }
@@ -0,0 +1,292 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use ckb_jsonrpc_types::JsonBytes;
+use serde_json::Value;
+
+use crate::on_chain::{Bytes, DecodeError, OnChain};
+
+pub use serde;
+pub use serde_json;
+
+// The human-readable form of an `OnChain` type, which the derived
+// `Serialize` and `Deserialize` impls go through. Integers wider than 64 bits
+// and `Bytes` are strings, maps are arrays of `[key, value]` pairs, and enums
+// are tagged by the variant name like serde does.
+pub trait OnChainJson: Sized {
+    fn _to_json(&self) -> Value;
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    // The kind of the value which is expected
+    Expected(&'static str),
+    // A number does not fit the integer type
+    OutOfRange(String),
+    MissingField(&'static str),
+    UnknownVariant(String),
+    // The cell data is not the encoding of the registered type
+    Decode(DecodeError),
+    // No registered type is at the wrapper idx of the cell
+    UnknownIdx(u8),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Expected(kind) => write!(f, "expected {}", kind),
+            JsonError::OutOfRange(n) => write!(f, "{} is out of range", n),
+            JsonError::MissingField(field) => write!(f, "missing field `{}`", field),
+            JsonError::UnknownVariant(v) => write!(f, "unknown variant `{}`", v),
+            JsonError::Decode(e) => write!(f, "cannot decode: {}", e),
+            JsonError::UnknownIdx(idx) => write!(f, "no type is registered at {}", idx),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<DecodeError> for JsonError {
+    fn from(e: DecodeError) -> Self {
+        JsonError::Decode(e)
+    }
+}
+
+// The field of a derived struct or struct-like variant
+pub fn json_field<'a>(object: &'a Value, field: &'static str) -> Result<&'a Value, JsonError> {
+    object
+        .as_object()
+        .ok_or(JsonError::Expected("an object"))?
+        .get(field)
+        .ok_or(JsonError::MissingField(field))
+}
+
+// The items of an array of exactly `len` items
+pub fn json_items(value: &Value, len: usize) -> Result<&[Value], JsonError> {
+    match value.as_array() {
+        Some(items) if items.len() == len => Ok(items),
+        _ => Err(JsonError::Expected("an array of the same length")),
+    }
+}
+
+// The variant name and the fields of a derived enum value, which are `Null`
+// for a unit variant
+pub fn json_variant(value: &Value) -> Result<(&str, &Value), JsonError> {
+    match value {
+        Value::String(name) => Ok((name, &Value::Null)),
+        Value::Object(object) if object.len() == 1 => {
+            let (name, fields) = object.iter().next().unwrap();
+            Ok((name, fields))
+        }
+        _ => Err(JsonError::Expected(
+            "a variant name or an object with one key",
+        )),
+    }
+}
+
+// Decode the data of a state cell and turn it into JSON, upgrading the older
+// versions first
+pub fn decode_to_json<T: OnChain + OnChainJson>(
+    version: u8,
+    bytes: &[u8],
+) -> Result<Value, JsonError> {
+    Ok(T::_from_versioned_bytes(version, bytes)?._to_json())
+}
+
+macro_rules! impl_on_chain_json_for_int {
+    ($t:ty) => {
+        impl OnChainJson for $t {
+            fn _to_json(&self) -> Value {
+                Value::from(*self)
+            }
+
+            fn _from_json(value: &Value) -> Result<Self, JsonError> {
+                let n = match value {
+                    Value::Number(n) => n,
+                    _ => return Err(JsonError::Expected("a number")),
+                };
+                let n = if let Some(n) = n.as_u64() {
+                    <$t>::try_from(n).ok()
+                } else {
+                    n.as_i64().and_then(|n| <$t>::try_from(n).ok())
+                };
+                n.ok_or_else(|| JsonError::OutOfRange(value.to_string()))
+            }
+        }
+    };
+}
+
+impl_on_chain_json_for_int!(u8);
+impl_on_chain_json_for_int!(u16);
+impl_on_chain_json_for_int!(u32);
+impl_on_chain_json_for_int!(u64);
+impl_on_chain_json_for_int!(i8);
+impl_on_chain_json_for_int!(i16);
+impl_on_chain_json_for_int!(i32);
+impl_on_chain_json_for_int!(i64);
+
+// JSON numbers are not precise beyond 64 bits
+macro_rules! impl_on_chain_json_for_wide_int {
+    ($t:ty) => {
+        impl OnChainJson for $t {
+            fn _to_json(&self) -> Value {
+                Value::String(self.to_string())
+            }
+
+            fn _from_json(value: &Value) -> Result<Self, JsonError> {
+                let s = value
+                    .as_str()
+                    .ok_or(JsonError::Expected("a decimal string"))?;
+                s.parse()
+                    .map_err(|_| JsonError::OutOfRange(String::from(s)))
+            }
+        }
+    };
+}
+
+impl_on_chain_json_for_wide_int!(u128);
+impl_on_chain_json_for_wide_int!(i128);
+
+impl OnChainJson for bool {
+    fn _to_json(&self) -> Value {
+        Value::Bool(*self)
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        value.as_bool().ok_or(JsonError::Expected("a bool"))
+    }
+}
+
+impl OnChainJson for String {
+    fn _to_json(&self) -> Value {
+        Value::String(self.clone())
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        value
+            .as_str()
+            .map(String::from)
+            .ok_or(JsonError::Expected("a string"))
+    }
+}
+
+// `0x` and the hex of the bytes, like the CKB RPC
+impl OnChainJson for Bytes {
+    fn _to_json(&self) -> Value {
+        serde_json::to_value(JsonBytes::from_vec(self.0.clone())).unwrap()
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        let bytes: JsonBytes = serde_json::from_value(value.clone())
+            .map_err(|_| JsonError::Expected("a 0x-prefixed hex string"))?;
+        Ok(Bytes(bytes.into_bytes().to_vec()))
+    }
+}
+
+impl<T: OnChainJson> OnChainJson for Option<T> {
+    fn _to_json(&self) -> Value {
+        match self {
+            Some(t) => t._to_json(),
+            None => Value::Null,
+        }
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        match value {
+            Value::Null => Ok(None),
+            value => Ok(Some(T::_from_json(value)?)),
+        }
+    }
+}
+
+impl<T: OnChainJson> OnChainJson for Vec<T> {
+    fn _to_json(&self) -> Value {
+        Value::Array(self.iter().map(T::_to_json).collect())
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        value
+            .as_array()
+            .ok_or(JsonError::Expected("an array"))?
+            .iter()
+            .map(T::_from_json)
+            .collect()
+    }
+}
+
+impl<T: OnChainJson, const N: usize> OnChainJson for [T; N] {
+    fn _to_json(&self) -> Value {
+        Value::Array(self.iter().map(T::_to_json).collect())
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        let items = json_items(value, N)?
+            .iter()
+            .map(T::_from_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(items
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("the array has N items")))
+    }
+}
+
+impl<K: OnChainJson + Ord, V: OnChainJson> OnChainJson for BTreeMap<K, V> {
+    fn _to_json(&self) -> Value {
+        Value::Array(
+            self.iter()
+                .map(|(k, v)| Value::Array(vec![k._to_json(), v._to_json()]))
+                .collect(),
+        )
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        value
+            .as_array()
+            .ok_or(JsonError::Expected("an array of [key, value]"))?
+            .iter()
+            .map(|pair| {
+                let pair = json_items(pair, 2)?;
+                Ok((K::_from_json(&pair[0])?, V::_from_json(&pair[1])?))
+            })
+            .collect()
+    }
+}
+
+impl<T: OnChainJson + Ord> OnChainJson for BTreeSet<T> {
+    fn _to_json(&self) -> Value {
+        Value::Array(self.iter().map(T::_to_json).collect())
+    }
+
+    fn _from_json(value: &Value) -> Result<Self, JsonError> {
+        value
+            .as_array()
+            .ok_or(JsonError::Expected("an array"))?
+            .iter()
+            .map(T::_from_json)
+            .collect()
+    }
+}
+
+macro_rules! impl_on_chain_json_for_tuple {
+    ($($t:ident $idx:tt),+; $len:expr) => {
+        impl<$($t: OnChainJson),+> OnChainJson for ($($t,)+) {
+            fn _to_json(&self) -> Value {
+                Value::Array(vec![$(self.$idx._to_json()),+])
+            }
+
+            fn _from_json(value: &Value) -> Result<Self, JsonError> {
+                let items = json_items(value, $len)?;
+                Ok(($($t::_from_json(&items[$idx])?,)+))
+            }
+        }
+    };
+}
+
+impl_on_chain_json_for_tuple!(A 0; 1);
+impl_on_chain_json_for_tuple!(A 0, B 1; 2);
+impl_on_chain_json_for_tuple!(A 0, B 1, C 2; 3);
+impl_on_chain_json_for_tuple!(A 0, B 1, C 2, D 3; 4);
+impl_on_chain_json_for_tuple!(A 0, B 1, C 2, D 3, E 4; 5);
+impl_on_chain_json_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5; 6);
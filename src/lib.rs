@@ -1,18 +1,47 @@
+// `on_chain.rs` is shared with the no_std types crate, so it takes the
+// collections from `alloc`
+extern crate alloc;
+
 #[cfg(feature = "contract-generator")]
 mod code_str;
 
 mod app;
 mod cell_manager;
+mod client;
+mod config;
 mod contract;
+mod dry_run;
 pub mod generators;
+#[cfg(feature = "serde")]
+mod json;
 mod on_chain;
 mod prelude;
-mod utils;
+#[cfg(feature = "roundtrip")]
+mod roundtrip;
+pub mod testing;
+pub mod utils;
 
-pub use cell_manager::CellManager;
+pub use app::{
+    App, BootstrapError, BuildError, Cell, Deployment, FileError, Genesis, GenesisRecord,
+    InstanceTx, LoadError, StateKey, REGISTRY_ID,
+};
+pub use cell_manager::{CellManager, StateError};
+pub use client::{Client, ClientError, RpcClient};
+pub use config::{
+    BootsConfig, ChainConfig, Config, ConfigError, DevConfig, CONFIG_FILE, SECRET_KEY_ENV,
+};
 pub use contract::ContractResult;
+pub use dry_run::{ContractError, DryRunError, MAX_CYCLES};
+#[cfg(feature = "serde")]
+pub use json::*;
 pub use on_chain::*;
 pub use prelude::*;
+#[cfg(feature = "roundtrip")]
+pub use roundtrip::*;
+
+// `on_chain.rs` is shared with the no_std types crate, which has no
+// `std::error::Error`
+impl std::error::Error for DecodeError {}
 
 #[cfg(feature = "contract-generator")]
 pub use code_str::__CodeStr__;
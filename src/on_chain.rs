@@ -1,4 +1,47 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // The bytes end before the value does
+    Truncated,
+    // The length prefix claims more bytes than there are
+    BadLengthPrefix,
+    // No variant of the enum is encoded with the tag
+    UnknownTag(u8),
+    // No variant of the Molecule union is encoded with the item id
+    UnknownUnionId(u32),
+    // The number of bytes left after the value
+    TrailingBytes(usize),
+    // A `String` holds bytes which are not UTF-8
+    InvalidUtf8,
+    // A `bool` is encoded as neither 0 nor 1
+    InvalidBool(u8),
+    // The keys of a map or set are not strictly ascending, so the encoding
+    // is not canonical
+    UnsortedKeys,
+    // The bytes are written by a version of the type which cannot be
+    // migrated to the current one
+    UnknownVersion(u8),
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "bytes are truncated"),
+            DecodeError::BadLengthPrefix => write!(f, "length prefix is out of bounds"),
+            DecodeError::UnknownTag(tag) => write!(f, "unknown enum tag {}", tag),
+            DecodeError::UnknownUnionId(id) => write!(f, "unknown union item id {}", id),
+            DecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes", n),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::InvalidBool(b) => write!(f, "{} is not a bool", b),
+            DecodeError::UnsortedKeys => write!(f, "keys are unsorted or duplicated"),
+            DecodeError::UnknownVersion(v) => write!(f, "cannot migrate from version {}", v),
+        }
+    }
+}
+
 pub trait OnChain: Sized {
+    // The exact length of `_to_bytes()`, length prefix included
     fn _capacity(&self) -> u64;
 
     fn _id() -> Option<&'static str> {
@@ -7,13 +50,60 @@ pub trait OnChain: Sized {
 
     fn _to_bytes(&self) -> Vec<u8>;
 
-    fn _from_bytes(bytes: &[u8]) -> Option<Self>;
+    // `bytes` is the content without the length prefix, which must be
+    // consumed entirely
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError>;
 
     fn _fixed_size() -> Option<u64>;
 
+    // The length of the value at the head of `bytes` if its encoding tells
+    // it, like a Molecule table does, so that `_to_bytes` needs no length
+    // prefix. Only asked of the types without a fixed size.
+    fn _delimited_len(_bytes: &[u8]) -> Result<Option<u64>, DecodeError> {
+        Ok(None)
+    }
+
     fn _eq(&self, other: &Self) -> bool;
 
     fn _default() -> Self;
+
+    // Whether the type has a state cell per instance key rather than one,
+    // see `#[onchain(keyed)]`
+    fn _keyed() -> bool {
+        false
+    }
+
+    // Whether the instances are locked by their owners rather than the
+    // operator, see `#[onchain(owned)]`
+    fn _owned() -> bool {
+        false
+    }
+
+    // The layout version, which is stored in the `OnChainWrapper` header
+    fn _version() -> u8 {
+        1
+    }
+
+    // Decode `bytes` written by the `version` of the type. Types with a
+    // `Migrate` impl upgrade the older versions, see `#[onchain(migrate)]`.
+    fn _from_versioned_bytes(version: u8, bytes: &[u8]) -> Result<Self, DecodeError> {
+        if version != Self::_version() {
+            return Err(DecodeError::UnknownVersion(version));
+        }
+        decode::<Self>(bytes)
+    }
+
+    // The id of the generated contract upgrading the state cell in place
+    fn _migration_id() -> Option<&'static str> {
+        None
+    }
+}
+
+// How the previous version of a type is upgraded to it
+pub trait Migrate: OnChain {
+    type Previous: OnChain;
+
+    fn _migrate(previous: Self::Previous) -> Self;
 }
 
 macro_rules! impl_on_chain_for_builtin {
@@ -27,12 +117,12 @@ macro_rules! impl_on_chain_for_builtin {
                 self.to_le_bytes().into()
             }
 
-            fn _from_bytes(bytes: &[u8]) -> Option<Self> {
-                let mut slice = [0u8; $c];
-                for i in 0..$c {
-                    slice[i] = bytes.get(i)?.clone();
+            fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                if bytes.len() > $c {
+                    return Err(DecodeError::TrailingBytes(bytes.len() - $c));
                 }
-                Some(<$t>::from_le_bytes(slice))
+                let slice: [u8; $c] = bytes.try_into().map_err(|_| DecodeError::Truncated)?;
+                Ok(<$t>::from_le_bytes(slice))
             }
 
             fn _fixed_size() -> Option<u64> {
@@ -61,6 +151,290 @@ impl_on_chain_for_builtin!(i32, 4);
 impl_on_chain_for_builtin!(i64, 8);
 impl_on_chain_for_builtin!(i128, 16);
 
+impl OnChain for bool {
+    fn _capacity(&self) -> u64 {
+        1
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match <u8 as OnChain>::_from_bytes(bytes)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(DecodeError::InvalidBool(b)),
+        }
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        Some(1)
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn _default() -> Self {
+        false
+    }
+}
+
+// Tagged like an enum, 0 for `None` and 1 for `Some`
+impl<T: OnChain> OnChain for Option<T> {
+    fn _capacity(&self) -> u64 {
+        let prefix = 8 + 1;
+        match self {
+            Some(item) => item._capacity() + prefix,
+            None => prefix,
+        }
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let content = match self {
+            Some(item) => {
+                let mut content = vec![1u8];
+                content.extend(item._to_bytes());
+                content
+            }
+            None => vec![0u8],
+        };
+        with_prefix(content)
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (tag, left) = consume_and_decode::<u8>(bytes)?;
+        let (item, left) = match tag {
+            0 => (None, left),
+            1 => {
+                let (item, left) = consume_and_decode::<T>(left)?;
+                (Some(item), left)
+            }
+            tag => return Err(DecodeError::UnknownTag(tag)),
+        };
+        if !left.is_empty() {
+            return Err(DecodeError::TrailingBytes(left.len()));
+        }
+        Ok(item)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a._eq(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    fn _default() -> Self {
+        None
+    }
+}
+
+// The items one after another, without a length prefix if the items are
+// fixed-size
+impl<T: OnChain, const N: usize> OnChain for [T; N] {
+    fn _capacity(&self) -> u64 {
+        let size = self.iter().fold(0, |prev, item| prev + item._capacity());
+        if Self::_fixed_size().is_some() {
+            size
+        } else {
+            size + 8
+        }
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        self.iter().for_each(|item| result.extend(item._to_bytes()));
+        if Self::_fixed_size().is_some() {
+            result
+        } else {
+            with_prefix(result)
+        }
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut items = Vec::with_capacity(N);
+        let mut left = bytes;
+        for _ in 0..N {
+            let (item, rest) = consume_and_decode::<T>(left)?;
+            items.push(item);
+            left = rest;
+        }
+        if !left.is_empty() {
+            return Err(DecodeError::TrailingBytes(left.len()));
+        }
+        items.try_into().map_err(|_| DecodeError::Truncated)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        Some(T::_fixed_size()? * N as u64)
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self.iter().zip(other.iter()).all(|(a, b)| a._eq(b))
+    }
+
+    fn _default() -> Self {
+        core::array::from_fn(|_| T::_default())
+    }
+}
+
+// The entries in ascending key order, which is the only accepted order so
+// that equal maps are always encoded into the same bytes
+impl<K: OnChain + Ord, V: OnChain> OnChain for BTreeMap<K, V> {
+    fn _capacity(&self) -> u64 {
+        let prefix = 8;
+        self.iter()
+            .fold(0, |prev, (k, v)| prev + k._capacity() + v._capacity())
+            + prefix
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        self.iter().for_each(|(k, v)| {
+            result.extend(k._to_bytes());
+            result.extend(v._to_bytes());
+        });
+        with_prefix(result)
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut result = BTreeMap::new();
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let (k, left) = consume_and_decode::<K>(bytes)?;
+            let (v, left) = consume_and_decode::<V>(left)?;
+            // `is_some_and` is too new for the toolchain of the contracts
+            if let Some(last) = result.keys().next_back() {
+                if k <= *last {
+                    return Err(DecodeError::UnsortedKeys);
+                }
+            }
+            result.insert(k, v);
+            bytes = left;
+        }
+        Ok(result)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|((k1, v1), (k2, v2))| k1._eq(k2) && v1._eq(v2))
+    }
+
+    fn _default() -> Self {
+        BTreeMap::new()
+    }
+}
+
+// The items in ascending order, like the keys of `BTreeMap`
+impl<T: OnChain + Ord> OnChain for BTreeSet<T> {
+    fn _capacity(&self) -> u64 {
+        let prefix = 8;
+        self.iter().fold(0, |prev, item| prev + item._capacity()) + prefix
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        self.iter().for_each(|item| result.extend(item._to_bytes()));
+        with_prefix(result)
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut result = BTreeSet::new();
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let (item, left) = consume_and_decode::<T>(bytes)?;
+            if let Some(last) = result.iter().next_back() {
+                if item <= *last {
+                    return Err(DecodeError::UnsortedKeys);
+                }
+            }
+            result.insert(item);
+            bytes = left;
+        }
+        Ok(result)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a._eq(b))
+    }
+
+    fn _default() -> Self {
+        BTreeSet::new()
+    }
+}
+
+// Encoded like a struct whose fields are the elements
+macro_rules! impl_on_chain_for_tuple {
+    ($($t:ident $v:ident $i:tt),+) => {
+        impl<$($t: OnChain),+> OnChain for ($($t,)+) {
+            fn _capacity(&self) -> u64 {
+                let size = 0 $(+ self.$i._capacity())+;
+                if Self::_fixed_size().is_some() {
+                    size
+                } else {
+                    size + 8
+                }
+            }
+
+            fn _to_bytes(&self) -> Vec<u8> {
+                let mut result = vec![];
+                $(result.extend(self.$i._to_bytes());)+
+                if Self::_fixed_size().is_some() {
+                    result
+                } else {
+                    with_prefix(result)
+                }
+            }
+
+            fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                let left = bytes;
+                $(let ($v, left) = consume_and_decode::<$t>(left)?;)+
+                if !left.is_empty() {
+                    return Err(DecodeError::TrailingBytes(left.len()));
+                }
+                Ok(($($v,)+))
+            }
+
+            fn _fixed_size() -> Option<u64> {
+                Some(0 $(+ $t::_fixed_size()?)+)
+            }
+
+            fn _eq(&self, other: &Self) -> bool {
+                $(self.$i._eq(&other.$i))&&+
+            }
+
+            fn _default() -> Self {
+                ($($t::_default(),)+)
+            }
+        }
+    };
+}
+
+impl_on_chain_for_tuple!(A a 0);
+impl_on_chain_for_tuple!(A a 0, B b 1);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2, D d 3);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2, D d 3, E e 4);
+impl_on_chain_for_tuple!(A a 0, B b 1, C c 2, D d 3, E e 4, F f 5);
+
 impl<T: OnChain> OnChain for Vec<T> {
     fn _capacity(&self) -> u64 {
         let prefix = 8;
@@ -68,26 +442,25 @@ impl<T: OnChain> OnChain for Vec<T> {
     }
 
     fn _to_bytes(&self) -> Vec<u8> {
-        let mut total_capacity: u64 = 0;
         let mut bytes = vec![];
-        self.iter().for_each(|element| {
-            total_capacity += element._capacity();
-            bytes.extend(element._to_bytes());
-        });
-        let mut res = total_capacity.to_le_bytes().to_vec();
-        res.extend(bytes);
-        res
+        self.iter()
+            .for_each(|element| bytes.extend(element._to_bytes()));
+        with_prefix(bytes)
     }
 
-    fn _from_bytes(bytes: &[u8]) -> Option<Self> {
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut result: Vec<T> = vec![];
         let mut bytes = bytes;
-        while bytes.len() > 0 {
+        while !bytes.is_empty() {
             let (item, left) = consume_and_decode::<T>(bytes)?;
+            // Zero-sized items would be decoded from the same bytes forever
+            if left.len() == bytes.len() {
+                return Err(DecodeError::TrailingBytes(left.len()));
+            }
             result.push(item);
             bytes = left;
         }
-        Some(result)
+        Ok(result)
     }
 
     fn _fixed_size() -> Option<u64> {
@@ -107,7 +480,7 @@ impl<T: OnChain> OnChain for Vec<T> {
             }
         }
 
-        return true;
+        true
     }
 
     fn _default() -> Self {
@@ -117,36 +490,36 @@ impl<T: OnChain> OnChain for Vec<T> {
 
 // It is almost the same as
 // #[derive(OnChain)]
+// #[onchain(encoding = "molecule")]
 pub struct OnChainWrapper {
     pub idx: u8,
+    // `_version()` of the type when `data` is written
+    pub version: u8,
     pub data: Vec<u8>,
 }
 
+// The wrapper idx of the registry, the cell created by the genesis besides the
+// state cells. Its data is the `Vec<[u8; 32]>` of the type hashes of the
+// contracts that `_entry` may exec, so an app has at most 255 types.
+pub const REGISTRY_IDX: u8 = u8::MAX;
+
+// The data of every cell of an app, which is the Molecule table
+// `OnChainWrapper`, see `write_schema`
 impl OnChain for OnChainWrapper {
     fn _capacity(&self) -> u64 {
-        self.idx._capacity() + self.data._capacity()
+        Self::encoded_len(self.data.len() as u64)
     }
     fn _to_bytes(&self) -> Vec<u8> {
-        let mut result = Vec::with_capacity(self._capacity() as usize);
-        result.extend(<u8 as OnChain>::_to_bytes(&self.idx));
-        result.extend(<Vec<u8> as OnChain>::_to_bytes(&self.data));
-        if let Some(_) = OnChainWrapper::_fixed_size() {
-            result
-        } else {
-            let mut prefix: Vec<u8> = result.len().to_le_bytes().to_vec();
-            prefix.extend(result);
-            prefix
-        }
+        self._to_molecule()
     }
-    fn _from_bytes(bytes: &[u8]) -> Option<Self> {
-        let left = bytes;
-        let (idx, left) = consume_and_decode::<u8>(left)?;
-        let (data, _) = consume_and_decode::<Vec<u8>>(left)?;
-        Some(Self { idx, data })
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::_from_molecule(bytes)
     }
     fn _fixed_size() -> Option<u64> {
-        let size = <u8 as OnChain>::_fixed_size()? + <Vec<u8> as OnChain>::_fixed_size()?;
-        Some(size)
+        None
+    }
+    fn _delimited_len(bytes: &[u8]) -> Result<Option<u64>, DecodeError> {
+        Ok(Some(molecule_u32(bytes, 0)? as u64))
     }
     fn _id() -> Option<&'static str> {
         None
@@ -155,6 +528,9 @@ impl OnChain for OnChainWrapper {
         if !self.idx._eq(&other.idx) {
             return false;
         }
+        if !self.version._eq(&other.version) {
+            return false;
+        }
         if !self.data._eq(&other.data) {
             return false;
         }
@@ -163,44 +539,601 @@ impl OnChain for OnChainWrapper {
     fn _default() -> Self {
         Self {
             idx: <u8 as OnChain>::_default(),
+            version: <u8 as OnChain>::_default(),
             data: <Vec<u8> as OnChain>::_default(),
         }
     }
 }
-// impl OnChain for String {
-//     fn _capacity(&self) -> u64 {
-//         self.as_bytes().len() as u64 + 8
-//     }
+impl OnChainWrapper {
+    // The length of the encoded wrapper of a state of `state_len` bytes,
+    // which is the cell data of the state cell: the total size and the 3
+    // offsets of the table, idx, version, and the item count of `data`
+    pub fn encoded_len(state_len: u64) -> u64 {
+        let header = 4 + 3 * 4;
+        header + 1 + 1 + 4 + state_len
+    }
+}
+
+impl OnChain for String {
+    fn _capacity(&self) -> u64 {
+        self.len() as u64 + 8
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut prefix = (self.len() as u64).to_le_bytes().to_vec();
+        prefix.extend(self.as_bytes());
+        prefix
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn _default() -> Self {
+        String::new()
+    }
+}
 
-//     fn _to_bytes(&self) -> Vec<u8> {
-//         let bytes = self.as_bytes().to_vec();
-//         let mut prefix = bytes.len().to_le_bytes().to_vec();
-//         prefix.extend(bytes);
-//         prefix
-//     }
+// A byte string. It is encoded the same as `Vec<u8>`, but it is copied as a
+// whole instead of byte by byte.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bytes(pub Vec<u8>);
 
-//     fn _from_bytes(bytes: &[u8]) -> Option<Self> {
-//         String::from_utf8(bytes.to_vec()).ok()
-//     }
+impl core::ops::Deref for Bytes {
+    type Target = [u8];
 
-//     fn _fixed_size() -> Option<u64> {
-//         None
-//     }
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
 
-//     fn _eq(&self, other: &Self) -> bool {
-//         self == other
-//     }
-// }
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
 
-pub fn consume_and_decode<T: OnChain>(bytes: &[u8]) -> Option<(T, &[u8])> {
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Self {
+        Bytes(bytes.to_vec())
+    }
+}
+
+impl OnChain for Bytes {
+    fn _capacity(&self) -> u64 {
+        self.0.len() as u64 + 8
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut prefix = (self.0.len() as u64).to_le_bytes().to_vec();
+        prefix.extend(&self.0);
+        prefix
+    }
+
+    fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Bytes(bytes.to_vec()))
+    }
+
+    fn _fixed_size() -> Option<u64> {
+        None
+    }
+
+    fn _eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn _default() -> Self {
+        Bytes(vec![])
+    }
+}
+
+fn with_prefix(content: Vec<u8>) -> Vec<u8> {
+    let mut prefix = (content.len() as u64).to_le_bytes().to_vec();
+    prefix.extend(content);
+    prefix
+}
+
+// Decode the value at the head of `bytes`, returning it and the bytes after it.
+pub fn consume_and_decode<T: OnChain>(bytes: &[u8]) -> Result<(T, &[u8]), DecodeError> {
     if let Some(capacity) = T::_fixed_size() {
         let end = capacity as usize;
-        let item = T::_from_bytes(&bytes[0..end])?;
-        Some((item, &bytes[end..]))
+        if bytes.len() < end {
+            return Err(DecodeError::Truncated);
+        }
+        let item = T::_from_bytes(&bytes[..end])?;
+        Ok((item, &bytes[end..]))
+    } else if let Some(size) = T::_delimited_len(bytes)? {
+        if size > bytes.len() as u64 {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+        let (content, left) = bytes.split_at(size as usize);
+        let item = T::_from_bytes(content)?;
+        Ok((item, left))
     } else {
-        let size: [u8; 8] = bytes[0..8].try_into().unwrap();
-        let end = usize::from_le_bytes(size);
-        let item = T::_from_bytes(&bytes[8..end + 8])?;
-        Some((item, &bytes[end + 8..]))
+        if bytes.len() < 8 {
+            return Err(DecodeError::Truncated);
+        }
+        let (size, left) = bytes.split_at(8);
+        let size = u64::from_le_bytes(size.try_into().map_err(|_| DecodeError::Truncated)?);
+        if size > left.len() as u64 {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+        let (content, left) = left.split_at(size as usize);
+        let item = T::_from_bytes(content)?;
+        Ok((item, left))
+    }
+}
+
+// Decode `bytes` holding exactly one value, like the data of a cell.
+pub fn decode<T: OnChain>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let (item, left) = consume_and_decode::<T>(bytes)?;
+    if !left.is_empty() {
+        return Err(DecodeError::TrailingBytes(left.len()));
+    }
+    Ok(item)
+}
+
+// Decode the `data` of an `OnChainWrapper` written with `version`, migrating
+// it to the current version of `T`
+pub fn decode_versioned<T: OnChain>(version: u8, bytes: &[u8]) -> Result<T, DecodeError> {
+    T::_from_versioned_bytes(version, bytes)
+}
+
+// The Molecule encoding of a type, which other CKB tools can read, see
+// `#[onchain(encoding = "molecule")]`. Tuples and maps have none.
+pub trait Molecule: Sized {
+    fn _to_molecule(&self) -> Vec<u8>;
+
+    // `bytes` must be consumed entirely
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError>;
+
+    // Some if the type is a Molecule byte, array or struct
+    fn _molecule_size() -> Option<usize>;
+
+    // The name in the `.mol` schema, like `Uint32`
+    fn _molecule_name() -> String;
+
+    // Add the declarations of the type and the ones it refers to, in the
+    // order the schema needs
+    fn _molecule_decls(_decls: &mut Vec<String>) {}
+}
+
+// Add `decl` if it is not yet declared
+pub fn molecule_declare(decls: &mut Vec<String>, decl: String) {
+    if !decls.contains(&decl) {
+        decls.push(decl);
+    }
+}
+
+// `byte` is the only name of the schema starting in lower case, so capitalize
+// names before composing them, like `ByteOpt`
+pub fn molecule_compose(name: &str, suffix: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => format!("{}{}{}", c.to_ascii_uppercase(), chars.as_str(), suffix),
+        None => String::from(suffix),
+    }
+}
+
+fn molecule_u32(bytes: &[u8], at: usize) -> Result<usize, DecodeError> {
+    let slice = bytes.get(at..at + 4).ok_or(DecodeError::Truncated)?;
+    let mut n = [0u8; 4];
+    n.copy_from_slice(slice);
+    Ok(u32::from_le_bytes(n) as usize)
+}
+
+// A table or a dynvec: the total size, the offsets of the items and the items
+pub fn molecule_table(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let header = 4 + 4 * items.len();
+    let total = header + items.iter().map(|i| i.len()).sum::<usize>();
+    let mut result = Vec::with_capacity(total);
+    result.extend((total as u32).to_le_bytes());
+    let mut offset = header;
+    items.iter().for_each(|item| {
+        result.extend((offset as u32).to_le_bytes());
+        offset += item.len();
+    });
+    items.into_iter().for_each(|item| result.extend(item));
+    result
+}
+
+// The items of a table or a dynvec. `count` is the expected number of fields
+// of a table, `None` for a dynvec.
+pub fn molecule_table_items(bytes: &[u8], count: Option<usize>) -> Result<Vec<&[u8]>, DecodeError> {
+    let total = molecule_u32(bytes, 0)?;
+    if total != bytes.len() {
+        return Err(DecodeError::BadLengthPrefix);
+    }
+    let header = if total == 4 {
+        4
+    } else {
+        molecule_u32(bytes, 4)?
+    };
+    if header % 4 != 0 || header < 4 || header > total {
+        return Err(DecodeError::BadLengthPrefix);
+    }
+    // Nothing but the total size without items
+    if header == 4 && total != 4 {
+        return Err(DecodeError::TrailingBytes(total - 4));
+    }
+    let n = header / 4 - 1;
+    if let Some(count) = count {
+        if n != count {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+    }
+    let mut offsets = Vec::with_capacity(n + 1);
+    for i in 0..n {
+        offsets.push(molecule_u32(bytes, 4 + 4 * i)?);
+    }
+    offsets.push(total);
+    // The first offset is the header, the others must not go back or past
+    // the end
+    let mut items = Vec::with_capacity(n);
+    for i in 0..n {
+        let (start, end) = (offsets[i], offsets[i + 1]);
+        if start > end || end > total {
+            return Err(DecodeError::BadLengthPrefix);
+        }
+        items.push(&bytes[start..end]);
+    }
+    Ok(items)
+}
+
+// A fixvec: the item count and the fixed-size items
+pub fn molecule_fixvec(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut result = (items.len() as u32).to_le_bytes().to_vec();
+    items.into_iter().for_each(|item| result.extend(item));
+    result
+}
+
+pub fn molecule_fixvec_items(bytes: &[u8], size: usize) -> Result<Vec<&[u8]>, DecodeError> {
+    let n = molecule_u32(bytes, 0)?;
+    // Like `Vec<T>`, zero-sized items cannot be told apart from no items
+    if size == 0 && n > 0 {
+        return Err(DecodeError::BadLengthPrefix);
+    }
+    let expected = n.checked_mul(size).and_then(|s| s.checked_add(4));
+    match expected {
+        Some(expected) if expected == bytes.len() => {}
+        Some(expected) if expected < bytes.len() => {
+            return Err(DecodeError::TrailingBytes(bytes.len() - expected))
+        }
+        _ => return Err(DecodeError::Truncated),
+    }
+    Ok((0..n)
+        .map(|i| &bytes[4 + i * size..4 + (i + 1) * size])
+        .collect())
+}
+
+// The fields of a struct or the items of an array, one after another
+pub fn molecule_struct_fields<'a>(
+    bytes: &'a [u8],
+    sizes: &[usize],
+) -> Result<Vec<&'a [u8]>, DecodeError> {
+    let total = sizes.iter().sum::<usize>();
+    if bytes.len() < total {
+        return Err(DecodeError::Truncated);
+    }
+    if bytes.len() > total {
+        return Err(DecodeError::TrailingBytes(bytes.len() - total));
+    }
+    let mut start = 0;
+    Ok(sizes
+        .iter()
+        .map(|size| {
+            let field = &bytes[start..start + size];
+            start += size;
+            field
+        })
+        .collect())
+}
+
+// The size of a struct with the fields of `sizes`, `None` if it has to be a
+// table. A struct has at least one field, all of them fixed-size.
+pub fn molecule_struct_size(sizes: &[Option<usize>]) -> Option<usize> {
+    if sizes.is_empty() {
+        return None;
+    }
+    sizes
+        .iter()
+        .try_fold(0, |total, size| Some(total + (*size)?))
+}
+
+// A union: the item id and the item
+pub fn molecule_union(id: u32, item: Vec<u8>) -> Vec<u8> {
+    let mut result = id.to_le_bytes().to_vec();
+    result.extend(item);
+    result
+}
+
+// The length of the struct of `size` or the table at the head of `bytes`
+pub fn molecule_item_len(bytes: &[u8], size: Option<usize>) -> Result<usize, DecodeError> {
+    match size {
+        Some(size) => Ok(size),
+        None => molecule_u32(bytes, 0),
+    }
+}
+
+pub fn molecule_union_item(bytes: &[u8]) -> Result<(u32, &[u8]), DecodeError> {
+    let id = molecule_u32(bytes, 0)?;
+    Ok((id as u32, &bytes[4..]))
+}
+
+// The fields of a derived struct, or a variant of a derived enum. It is a
+// Molecule struct if every field is fixed-size, or a table otherwise.
+pub fn molecule_fields_decl(name: &str, fields: &[(String, String)], fixed: bool) -> String {
+    let kind = if fixed { "struct" } else { "table" };
+    let mut decl = format!("{} {} {{\n", kind, name);
+    fields.iter().for_each(|(field, ty)| {
+        decl.push_str(&format!("    {}: {},\n", field, ty));
+    });
+    decl.push('}');
+    decl
+}
+
+macro_rules! impl_molecule_for_builtin {
+    ($t:ty, $name:literal) => {
+        impl Molecule for $t {
+            fn _to_molecule(&self) -> Vec<u8> {
+                <$t as OnChain>::_to_bytes(self)
+            }
+
+            fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+                <$t as OnChain>::_from_bytes(bytes)
+            }
+
+            fn _molecule_size() -> Option<usize> {
+                Some(core::mem::size_of::<$t>())
+            }
+
+            fn _molecule_name() -> String {
+                String::from($name)
+            }
+
+            fn _molecule_decls(decls: &mut Vec<String>) {
+                if $name != "byte" {
+                    let size = core::mem::size_of::<$t>();
+                    molecule_declare(decls, format!("array {} [byte; {}];", $name, size));
+                }
+            }
+        }
+    };
+}
+
+impl_molecule_for_builtin!(u8, "byte");
+impl_molecule_for_builtin!(u16, "Uint16");
+impl_molecule_for_builtin!(u32, "Uint32");
+impl_molecule_for_builtin!(u64, "Uint64");
+impl_molecule_for_builtin!(u128, "Uint128");
+impl_molecule_for_builtin!(i8, "byte");
+impl_molecule_for_builtin!(i16, "Int16");
+impl_molecule_for_builtin!(i32, "Int32");
+impl_molecule_for_builtin!(i64, "Int64");
+impl_molecule_for_builtin!(i128, "Int128");
+impl_molecule_for_builtin!(bool, "byte");
+
+impl Molecule for String {
+    fn _to_molecule(&self) -> Vec<u8> {
+        molecule_fixvec(self.bytes().map(|b| vec![b]).collect())
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = <Bytes as Molecule>::_from_molecule(bytes)?;
+        String::from_utf8(bytes.0).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        String::from("Bytes")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        <Bytes as Molecule>::_molecule_decls(decls)
+    }
+}
+
+impl Molecule for Bytes {
+    fn _to_molecule(&self) -> Vec<u8> {
+        let mut result = (self.len() as u32).to_le_bytes().to_vec();
+        result.extend(self.iter());
+        result
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let items = molecule_fixvec_items(bytes, 1)?;
+        Ok(Bytes(items.concat()))
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        String::from("Bytes")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        molecule_declare(decls, String::from("vector Bytes <byte>;"));
+    }
+}
+
+// A fixvec if the items are fixed-size, or a dynvec otherwise. `Vec<u8>` is
+// named `Bytes`.
+impl<T: Molecule> Molecule for Vec<T> {
+    fn _to_molecule(&self) -> Vec<u8> {
+        let items = self.iter().map(|t| t._to_molecule()).collect();
+        if T::_molecule_size().is_some() {
+            molecule_fixvec(items)
+        } else {
+            molecule_table(items)
+        }
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let items = match T::_molecule_size() {
+            Some(size) => molecule_fixvec_items(bytes, size)?,
+            None => molecule_table_items(bytes, None)?,
+        };
+        items.into_iter().map(T::_from_molecule).collect()
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        let item = T::_molecule_name();
+        if item == "byte" {
+            String::from("Bytes")
+        } else {
+            molecule_compose(&item, "Vec")
+        }
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        T::_molecule_decls(decls);
+        let decl = format!(
+            "vector {} <{}>;",
+            Self::_molecule_name(),
+            T::_molecule_name()
+        );
+        molecule_declare(decls, decl);
+    }
+}
+
+impl Molecule for OnChainWrapper {
+    fn _to_molecule(&self) -> Vec<u8> {
+        molecule_table(vec![
+            self.idx._to_molecule(),
+            self.version._to_molecule(),
+            self.data._to_molecule(),
+        ])
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let fields = molecule_table_items(bytes, Some(3))?;
+        Ok(Self {
+            idx: u8::_from_molecule(fields[0])?,
+            version: u8::_from_molecule(fields[1])?,
+            data: Vec::<u8>::_from_molecule(fields[2])?,
+        })
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        String::from("OnChainWrapper")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        <Vec<u8> as Molecule>::_molecule_decls(decls);
+        let fields = [("idx", "byte"), ("version", "byte"), ("data", "Bytes")]
+            .iter()
+            .map(|(field, ty)| (String::from(*field), String::from(*ty)))
+            .collect::<Vec<_>>();
+        molecule_declare(
+            decls,
+            molecule_fields_decl("OnChainWrapper", &fields, false),
+        );
+    }
+}
+
+impl<T: Molecule> Molecule for Option<T> {
+    fn _to_molecule(&self) -> Vec<u8> {
+        match self {
+            Some(t) => t._to_molecule(),
+            None => vec![],
+        }
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(T::_from_molecule(bytes)?))
+        }
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        None
+    }
+
+    fn _molecule_name() -> String {
+        molecule_compose(&T::_molecule_name(), "Opt")
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        T::_molecule_decls(decls);
+        let decl = format!(
+            "option {} ({});",
+            Self::_molecule_name(),
+            T::_molecule_name()
+        );
+        molecule_declare(decls, decl);
+    }
+}
+
+// An array if the items are fixed-size, like `Byte32`, or a dynvec otherwise
+impl<T: Molecule, const N: usize> Molecule for [T; N] {
+    fn _to_molecule(&self) -> Vec<u8> {
+        let items: Vec<Vec<u8>> = self.iter().map(|t| t._to_molecule()).collect();
+        if T::_molecule_size().is_some() {
+            items.concat()
+        } else {
+            molecule_table(items)
+        }
+    }
+
+    fn _from_molecule(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let items = match T::_molecule_size() {
+            Some(size) => molecule_struct_fields(bytes, &[size; N])?,
+            None => molecule_table_items(bytes, Some(N))?,
+        };
+        let result = items
+            .into_iter()
+            .map(T::_from_molecule)
+            .collect::<Result<Vec<T>, _>>()?;
+        result.try_into().map_err(|_| DecodeError::Truncated)
+    }
+
+    fn _molecule_size() -> Option<usize> {
+        Some(T::_molecule_size()? * N)
+    }
+
+    fn _molecule_name() -> String {
+        molecule_compose(&T::_molecule_name(), &format!("{}", N))
+    }
+
+    fn _molecule_decls(decls: &mut Vec<String>) {
+        T::_molecule_decls(decls);
+        let decl = if T::_molecule_size().is_some() {
+            format!(
+                "array {} [{}; {}];",
+                Self::_molecule_name(),
+                T::_molecule_name(),
+                N
+            )
+        } else {
+            format!(
+                "vector {} <{}>;",
+                Self::_molecule_name(),
+                T::_molecule_name()
+            )
+        };
+        molecule_declare(decls, decl);
     }
 }
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use proptest::collection::vec;
+use proptest::prelude::{any, BoxedStrategy, Strategy};
+use proptest::test_runner::TestCaseError;
+
+use crate::on_chain::{decode, Bytes, OnChain};
+
+pub use proptest;
+
+// Collections are generated with up to this many items
+const MAX_ITEMS: usize = 8;
+
+// A generated value, which is printed as its encoding when a case fails since
+// `OnChain` types are not required to be `Debug`
+pub struct Sample<T>(pub T);
+
+impl<T: OnChain> fmt::Debug for Sample<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x")?;
+        self.0
+            ._to_bytes()
+            .iter()
+            .try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+// The values of a type for property-based tests, see `#[onchain(test_roundtrip)]`.
+// Recursive types are not supported, their strategy never ends.
+pub trait OnChainArbitrary: OnChain + 'static {
+    fn _arbitrary() -> BoxedStrategy<Sample<Self>>;
+}
+
+// `_from_bytes` undoes `_to_bytes`, whose length is `_capacity()` and the
+// fixed size if any
+pub fn check_roundtrip<T: OnChain>(value: &T) -> Result<(), TestCaseError> {
+    let bytes = value._to_bytes();
+    if bytes.len() as u64 != value._capacity() {
+        return Err(TestCaseError::fail(format!(
+            "{} bytes are encoded but the capacity is {}",
+            bytes.len(),
+            value._capacity()
+        )));
+    }
+    if let Some(size) = T::_fixed_size() {
+        if bytes.len() as u64 != size {
+            return Err(TestCaseError::fail(format!(
+                "{} bytes are encoded but the fixed size is {}",
+                bytes.len(),
+                size
+            )));
+        }
+    }
+    match decode::<T>(&bytes) {
+        Ok(actual) if actual._eq(value) => Ok(()),
+        Ok(_) => Err(TestCaseError::fail("the decoded value is not equal")),
+        Err(e) => Err(TestCaseError::fail(format!("cannot decode: {}", e))),
+    }
+}
+
+macro_rules! impl_on_chain_arbitrary_for_builtin {
+    ($($t:ty),+) => {
+        $(impl OnChainArbitrary for $t {
+            fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+                any::<$t>().prop_map(Sample).boxed()
+            }
+        })+
+    };
+}
+
+impl_on_chain_arbitrary_for_builtin!(
+    u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, bool, String
+);
+
+impl OnChainArbitrary for Bytes {
+    fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+        vec(any::<u8>(), 0..MAX_ITEMS * 4)
+            .prop_map(|bytes| Sample(Bytes(bytes)))
+            .boxed()
+    }
+}
+
+impl<T: OnChainArbitrary> OnChainArbitrary for Option<T> {
+    fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+        proptest::option::of(T::_arbitrary())
+            .prop_map(|item| Sample(item.map(|s| s.0)))
+            .boxed()
+    }
+}
+
+impl<T: OnChainArbitrary> OnChainArbitrary for Vec<T> {
+    fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+        vec(T::_arbitrary(), 0..MAX_ITEMS)
+            .prop_map(|items| Sample(items.into_iter().map(|s| s.0).collect()))
+            .boxed()
+    }
+}
+
+impl<T: OnChainArbitrary, const N: usize> OnChainArbitrary for [T; N] {
+    fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+        vec(T::_arbitrary(), N)
+            .prop_map(|items| {
+                let items = items.into_iter().map(|s| s.0).collect::<Vec<_>>();
+                Sample(
+                    items
+                        .try_into()
+                        .unwrap_or_else(|_| unreachable!("the array has N items")),
+                )
+            })
+            .boxed()
+    }
+}
+
+impl<K: OnChainArbitrary + Ord, V: OnChainArbitrary> OnChainArbitrary for BTreeMap<K, V> {
+    fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+        vec((K::_arbitrary(), V::_arbitrary()), 0..MAX_ITEMS)
+            .prop_map(|entries| Sample(entries.into_iter().map(|(k, v)| (k.0, v.0)).collect()))
+            .boxed()
+    }
+}
+
+impl<T: OnChainArbitrary + Ord> OnChainArbitrary for BTreeSet<T> {
+    fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+        vec(T::_arbitrary(), 0..MAX_ITEMS)
+            .prop_map(|items| Sample(items.into_iter().map(|s| s.0).collect()))
+            .boxed()
+    }
+}
+
+macro_rules! impl_on_chain_arbitrary_for_tuple {
+    ($($t:ident $v:ident),+) => {
+        impl<$($t: OnChainArbitrary),+> OnChainArbitrary for ($($t,)+) {
+            fn _arbitrary() -> BoxedStrategy<Sample<Self>> {
+                ($($t::_arbitrary(),)+)
+                    .prop_map(|($($v,)+)| Sample(($($v.0,)+)))
+                    .boxed()
+            }
+        }
+    };
+}
+
+impl_on_chain_arbitrary_for_tuple!(A a);
+impl_on_chain_arbitrary_for_tuple!(A a, B b);
+impl_on_chain_arbitrary_for_tuple!(A a, B b, C c);
+impl_on_chain_arbitrary_for_tuple!(A a, B b, C c, D d);
+impl_on_chain_arbitrary_for_tuple!(A a, B b, C c, D d, E e);
+impl_on_chain_arbitrary_for_tuple!(A a, B b, C c, D d, E e, F f);
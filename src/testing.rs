@@ -0,0 +1,203 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use ckb_hash::blake2b_256;
+use ckb_types::core::{Capacity, TransactionView};
+use ckb_types::packed::{Byte32, CellOutput, OutPoint, Script};
+use ckb_types::prelude::{Builder, Entity, Pack, Unpack};
+
+use crate::app::{Cell, FEE_RATE};
+use crate::client::{Client, ClientError};
+use crate::utils::get_instance_key;
+
+// An in-process chain for testing apps without a node. It checks that the
+// inputs and cell deps of a tx are live, the capacity is balanced and the fee
+// is paid, but it does not run any script.
+//
+// Clones share the same chain, so keep one to look into the chain after
+// giving another one to the `App`.
+#[derive(Clone, Default)]
+pub struct SimChain {
+    state: Rc<RefCell<SimState>>,
+}
+
+#[derive(Default)]
+struct SimState {
+    block_number: u64,
+    live_cells: HashMap<OutPoint, Cell>,
+    // Keep the order in which the cells are created
+    created: Vec<OutPoint>,
+    // Cells made out of thin air, like `fund` and `deploy` do
+    minted: u64,
+}
+
+impl SimChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn block_number(&self) -> u64 {
+        self.state.borrow().block_number
+    }
+
+    pub fn get_live_cell(&self, out_point: &OutPoint) -> Option<Cell> {
+        self.state.borrow().live_cells.get(out_point).cloned()
+    }
+
+    pub fn live_cells(&self) -> Vec<Cell> {
+        let state = self.state.borrow();
+        state
+            .created
+            .iter()
+            .filter_map(|o| state.live_cells.get(o).cloned())
+            .collect()
+    }
+
+    // Create a cell owned by `lock`, which can pay for the genesis.
+    pub fn fund(&self, lock: &Script, capacity: u64) -> Cell {
+        let output = CellOutput::new_builder()
+            .capacity(capacity.pack())
+            .lock(lock.clone())
+            .build();
+        self.mint(output, vec![])
+    }
+
+    // Create a code cell standing for a compiled contract, whose binary is
+    // never run. Returns the out point and a made up type hash, which is what
+    // `Deployment` wants.
+    pub fn deploy(&self, name: &str) -> (OutPoint, Byte32) {
+        let data = name.as_bytes().to_vec();
+        let output = CellOutput::new_builder()
+            .build_exact_capacity(Capacity::bytes(data.len()).expect("name is too long"))
+            .expect("capacity overflow");
+        let cell = self.mint(output, data);
+        let type_hash = blake2b_256(name.as_bytes()).pack();
+        (cell.outpoint, type_hash)
+    }
+
+    fn mint(&self, output: CellOutput, data: Vec<u8>) -> Cell {
+        let mut state = self.state.borrow_mut();
+        state.minted += 1;
+        let tx_hash = blake2b_256(state.minted.to_le_bytes()).pack();
+        let cell = Cell::new(OutPoint::new(tx_hash, 0), output, data);
+        state.insert(cell.clone());
+        cell
+    }
+}
+
+impl SimState {
+    fn insert(&mut self, cell: Cell) {
+        self.created.push(cell.outpoint.clone());
+        self.live_cells.insert(cell.outpoint.clone(), cell);
+    }
+
+    fn verify(&self, tx: &TransactionView) -> Result<(), String> {
+        if tx.inputs().is_empty() {
+            return Err(String::from("tx has no input"));
+        }
+        let mut spent = HashSet::new();
+        let mut input_capacity = 0u64;
+        for out_point in tx.input_pts_iter() {
+            let cell = self
+                .live_cells
+                .get(&out_point)
+                .ok_or_else(|| format!("input {} is not live", out_point))?;
+            if !spent.insert(out_point.clone()) {
+                return Err(format!("input {} is spent twice", out_point));
+            }
+            let capacity: u64 = cell.output.capacity().unpack();
+            input_capacity += capacity;
+        }
+        if let Some(dep) = tx
+            .cell_deps_iter()
+            .find(|dep| !self.live_cells.contains_key(&dep.out_point()))
+        {
+            return Err(format!("cell dep {} is not live", dep.out_point()));
+        }
+
+        let mut output_capacity = 0u64;
+        for (idx, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+            let capacity: u64 = output.capacity().unpack();
+            let occupied = output
+                .occupied_capacity(Capacity::bytes(data.len()).map_err(|e| e.to_string())?)
+                .map_err(|e| e.to_string())?
+                .as_u64();
+            if capacity < occupied {
+                return Err(format!(
+                    "output {} has {} shannons but occupies {}",
+                    idx, capacity, occupied
+                ));
+            }
+            output_capacity += capacity;
+        }
+        if output_capacity > input_capacity {
+            return Err(format!(
+                "outputs have {} shannons but inputs only have {}",
+                output_capacity, input_capacity
+            ));
+        }
+        // Like the min fee rate of the tx pool
+        let size = tx.data().serialized_size_in_block() as u64;
+        let min_fee = (size * FEE_RATE).div_ceil(1000);
+        if input_capacity - output_capacity < min_fee {
+            return Err(format!(
+                "tx pays {} shannons but needs a fee of {}",
+                input_capacity - output_capacity,
+                min_fee
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Client for SimChain {
+    fn get_tip_block_number(&mut self) -> Result<u64, ClientError> {
+        Ok(self.block_number())
+    }
+
+    // Every accepted tx is committed in a new block right away.
+    fn send_transaction(&mut self, tx: TransactionView) -> Result<Byte32, ClientError> {
+        let mut state = self.state.borrow_mut();
+        state.verify(&tx).map_err(ClientError::Rejected)?;
+        tx.input_pts_iter().for_each(|out_point| {
+            state.live_cells.remove(&out_point);
+        });
+        let tx_hash = tx.hash();
+        tx.outputs_with_data_iter()
+            .enumerate()
+            .for_each(|(idx, (output, data))| {
+                let out_point = OutPoint::new(tx_hash.clone(), idx as u32);
+                state.insert(Cell::new(out_point, output, data.to_vec()));
+            });
+        state.block_number += 1;
+        Ok(tx_hash)
+    }
+
+    fn get_cells(&mut self, type_script: &Script) -> Result<Vec<Cell>, ClientError> {
+        let cells = self
+            .live_cells()
+            .into_iter()
+            .filter(|cell| {
+                cell.output
+                    .type_()
+                    .to_opt()
+                    .is_some_and(|s| get_instance_key(type_script, &s).is_some())
+            })
+            .collect();
+        Ok(cells)
+    }
+
+    fn get_capacity_cells(&mut self, lock: &Script) -> Result<Vec<Cell>, ClientError> {
+        let cells = self
+            .live_cells()
+            .into_iter()
+            .filter(|cell| {
+                cell.output.lock().as_slice() == lock.as_slice()
+                    && cell.output.type_().is_none()
+                    && cell.data.is_empty()
+            })
+            .collect();
+        Ok(cells)
+    }
+}
@@ -1,9 +1,14 @@
-use ckb_hash::new_blake2b;
+use ckb_hash::{blake2b_256, new_blake2b};
+use ckb_sdk::constants::SIGHASH_TYPE_HASH;
+use ckb_sdk::SECP256K1;
 use ckb_types::{
-    core::ScriptHashType,
-    packed::{Byte32, Bytes, Script},
+    core::{Capacity, ScriptHashType},
+    packed::{Byte32, Bytes, CellInput, CellOutput, Script, WitnessArgs},
     prelude::{Builder, Entity, Pack},
 };
+use secp256k1::{PublicKey, SecretKey};
+
+use crate::on_chain::{OnChain, OnChainWrapper};
 
 // Blake2b(input_hash | app_name) to calculate the args in type script
 pub fn get_onchain_type_args(input_hash: Byte32, app_name: &str) -> Bytes {
@@ -15,12 +20,89 @@ pub fn get_onchain_type_args(input_hash: Byte32, app_name: &str) -> Bytes {
     result.as_slice().pack()
 }
 
-pub fn get_type_script_hash(code_hash: Byte32, input_hash: Byte32, app_name: &str) -> Byte32 {
+// The type script shared by all the state cells of an app. `code_hash` is the
+// type hash of the `_entry` contract.
+pub fn get_onchain_type_script(code_hash: Byte32, input_hash: Byte32, app_name: &str) -> Script {
     let args = get_onchain_type_args(input_hash, app_name);
-    let script = Script::new_builder()
+    Script::new_builder()
         .code_hash(code_hash)
         .args(args)
         .hash_type(ScriptHashType::Type.into())
-        .build();
-    script.calc_script_hash()
+        .build()
+}
+
+// The type script of the keyed instance `key`, whose args are the ones of
+// the app followed by the key, see `#[onchain(keyed)]`. The empty key is the
+// type script of the app itself.
+pub fn get_instance_type_script(type_script: &Script, key: &[u8]) -> Script {
+    let mut args = type_script.args().raw_data().to_vec();
+    args.extend_from_slice(key);
+    type_script.clone().as_builder().args(args.pack()).build()
+}
+
+// The instance key in the args of `script`, `None` if it is not a type
+// script of the app `type_script`
+pub fn get_instance_key(type_script: &Script, script: &Script) -> Option<Vec<u8>> {
+    let prefix = type_script.args().raw_data();
+    let args = script.args().raw_data();
+    let same_code = script.code_hash() == type_script.code_hash()
+        && script.hash_type() == type_script.hash_type();
+    if !same_code || !args.starts_with(&prefix) {
+        return None;
+    }
+    Some(args[prefix.len()..].to_vec())
+}
+
+// The key of the instance created at `output_index` by the tx whose first
+// input is `first_input`, which makes it unique like the type id does
+pub fn get_new_instance_key(first_input: &CellInput, output_index: u64) -> Vec<u8> {
+    let mut blake2b = new_blake2b();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&output_index.to_le_bytes());
+    let mut key = [0; 32];
+    blake2b.finalize(&mut key);
+    key.to_vec()
+}
+
+pub fn get_type_script_hash(code_hash: Byte32, input_hash: Byte32, app_name: &str) -> Byte32 {
+    get_onchain_type_script(code_hash, input_hash, app_name).calc_script_hash()
+}
+
+// Blake2b of the first input of the genesis tx, which makes the type script
+// args unique like the type id does
+pub fn get_input_hash(input: &CellInput) -> Byte32 {
+    blake2b_256(input.as_slice()).pack()
+}
+
+// The secp256k1 sighash lock, whose args is the blake160 of the public key
+pub fn get_sighash_lock(key: &SecretKey) -> Script {
+    let pubkey = PublicKey::from_secret_key(&SECP256K1, key);
+    let args = &blake2b_256(pubkey.serialize())[0..20];
+    Script::new_builder()
+        .code_hash(SIGHASH_TYPE_HASH.pack())
+        .args(args.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .build()
 }
+
+// The minimum shannons of a cell, 1 CKB for every byte of the capacity field,
+// the scripts and the data
+pub fn get_min_capacity(lock: &Script, type_script: Option<&Script>, data_len: u64) -> u64 {
+    let data = Capacity::bytes(data_len as usize).expect("data is too large");
+    CellOutput::new_builder()
+        .lock(lock.clone())
+        .type_(type_script.cloned().pack())
+        .build()
+        .occupied_capacity(data)
+        .expect("capacity overflow")
+        .as_u64()
+}
+
+// The minimum shannons of the state cell holding `state`, whose type script
+// is the one of the app, see `get_onchain_type_script`
+pub fn get_state_capacity<T: OnChain>(state: &T, lock: &Script, type_script: &Script) -> u64 {
+    let data_len = OnChainWrapper::encoded_len(state._capacity());
+    get_min_capacity(lock, Some(type_script), data_len)
+}
+
+include!("witness.rs");
@@ -0,0 +1,19 @@
+// Where `build_tx` puts the user input and the type hash of the contract to
+// exec, which are the `input_type` and `output_type` of the first witness.
+// Included by `utils` and shipped to the types crate too, see `get_utils`, so
+// that both sides read the witness the same way. The molecule header of the
+// bytes is not a part of them.
+
+pub fn witness_user_input(witness: &WitnessArgs) -> Vec<u8> {
+    match witness.input_type().to_opt() {
+        Some(b) => b.raw_data().to_vec(),
+        None => Vec::new(),
+    }
+}
+
+pub fn witness_exec_script(witness: &WitnessArgs) -> Vec<u8> {
+    match witness.output_type().to_opt() {
+        Some(b) => b.raw_data().to_vec(),
+        None => Vec::new(),
+    }
+}
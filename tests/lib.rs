@@ -1,8 +1,108 @@
 #[cfg(test)]
 mod tests {
-    use ckboots::consume_and_decode;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use ckb_types::packed::{Byte32, CellInput, CellOutput, OutPoint, WitnessArgs};
+    use ckb_types::prelude::{Builder, Entity, Pack, Unpack};
     use ckboots::OnChain;
+    use ckboots::{consume_and_decode, decode, Bytes, DecodeError};
+    use ckboots::{
+        App, BuildError, Cell, Config, ConfigError, ContractResult, Deployment, FileError,
+        GenesisRecord, LoadError, OnChainWrapper, RpcClient,
+    };
     use ckboots_derives::OnChain;
+    use serde_json::{json, Value};
+
+    // `#[onchain(test_roundtrip)]` needs the types at the module level
+    #[cfg(feature = "roundtrip")]
+    mod roundtrip {
+        use std::collections::BTreeMap;
+
+        use ckboots::Bytes;
+        use ckboots_derives::OnChain;
+
+        #[derive(OnChain)]
+        #[onchain(test_roundtrip)]
+        pub struct Pos(u8, i16);
+
+        #[derive(OnChain)]
+        #[onchain(test_roundtrip)]
+        pub enum Action {
+            #[onchain(tag = 3)]
+            Move(Pos),
+            #[onchain(tag = 4, default)]
+            Stay,
+            #[onchain(tag = 5)]
+            Attack { target: Option<u32>, power: u128 },
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "hero", test_roundtrip)]
+        pub struct Hero {
+            pub name: String,
+            pub owner: [u8; 4],
+            pub bag: BTreeMap<u8, Bytes>,
+            pub actions: Vec<Action>,
+            pub last: (Action, bool),
+        }
+
+        #[derive(OnChain)]
+        #[onchain(encoding = "molecule", test_roundtrip)]
+        pub struct Frog {
+            pub pos: [u16; 2],
+            pub name: String,
+            pub items: Vec<u32>,
+        }
+    }
+
+    // Only the types of `test_roundtrip` and their fields need `OnChainArbitrary`
+    #[cfg(feature = "roundtrip")]
+    #[test]
+    fn arbitrary_only_for_roundtrip() {
+        // Hand-written, so it has no `OnChainArbitrary`
+        pub struct Raw(u8);
+
+        impl OnChain for Raw {
+            fn _capacity(&self) -> u64 {
+                self.0._capacity()
+            }
+            fn _to_bytes(&self) -> Vec<u8> {
+                self.0._to_bytes()
+            }
+            fn _from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                Ok(Raw(u8::_from_bytes(bytes)?))
+            }
+            fn _fixed_size() -> Option<u64> {
+                u8::_fixed_size()
+            }
+            fn _eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+            fn _default() -> Self {
+                Raw(7)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl ckboots::OnChainJson for Raw {
+            fn _to_json(&self) -> Value {
+                self.0._to_json()
+            }
+            fn _from_json(value: &Value) -> Result<Self, ckboots::JsonError> {
+                Ok(Raw(u8::_from_json(value)?))
+            }
+        }
+
+        #[derive(OnChain)]
+        pub struct Stored {
+            pub raw: Raw,
+        }
+
+        let stored = Stored::_default();
+        assert_eq!(Stored::_from_bytes(&stored._to_bytes()).unwrap().raw.0, 7);
+    }
 
     #[test]
     fn builtin_onchain_test() {
@@ -12,14 +112,23 @@ mod tests {
         assert_eq!(actual, 0);
     }
 
-    // #[test]
-    // fn string_onchain_test() {
-    //     let s = String::from("i am Tom");
-    //     let bytes = s._to_bytes();
-    //     let (actual, left) = consume_and_decode::<String>(&bytes).unwrap();
-    //     assert_eq!(actual, s);
-    //     assert_eq!(left.len(), 0);
-    // }
+    #[test]
+    fn string_onchain_test() {
+        let s = String::from("i am Tom");
+        let bytes = s._to_bytes();
+        let (actual, left) = consume_and_decode::<String>(&bytes).unwrap();
+        assert_eq!(actual, s);
+        assert_eq!(left.len(), 0);
+
+        let b = Bytes::from(vec![0xff, 0xfe]);
+        let bytes = b._to_bytes();
+        assert_eq!(bytes, vec![0xffu8, 0xfe]._to_bytes());
+        assert_eq!(decode::<Bytes>(&bytes).unwrap(), b);
+        assert_eq!(
+            decode::<String>(&bytes).err(),
+            Some(DecodeError::InvalidUtf8)
+        );
+    }
 
     #[test]
     fn vec_onchain_bytes() {
@@ -33,79 +142,678 @@ mod tests {
         assert_eq!(actual, v);
     }
 
-    // #[test]
-    // fn derive_onchain_struct() {
-    //     #[derive(OnChain)]
-    //     #[onchain(id = "person")]
-    //     pub struct Person {
-    //         pub age: u16,
-    //         pub name: String,
-    //     }
-
-    //     let tom = Person {
-    //         age: 12,
-    //         name: String::from("Tom"),
-    //     };
-    //     let bytes = tom._to_bytes();
-    //     let (actual, left) = consume_and_decode::<Person>(&bytes).unwrap();
-    //     assert_eq!(actual.age, 12);
-    //     assert_eq!(actual.name, "Tom");
-    //     assert_eq!(left.len(), 0);
-    //     assert_eq!(Person::_id(), Some("person"));
-    // }
-
-    // #[test]
-    // fn derive_onchain_enum() {
-    //     #[derive(OnChain)]
-    //     pub enum EnumTest {
-    //         AA(String),
-    //         BB(u32),
-    //         CC,
-    //     }
-    //     let aa = EnumTest::AA(String::from("1"));
-    //     let bytes = aa._to_bytes();
-    //     let (actual, left) = consume_and_decode::<EnumTest>(&bytes).unwrap();
-    //     assert_eq!(left.len(), 0);
-    //     match actual {
-    //         EnumTest::AA(s) => assert_eq!(s, "1"),
-    //         _ => panic!(),
-    //     }
-
-    //     let bb = EnumTest::BB(123);
-    //     let bytes = bb._to_bytes();
-    //     let (actual, left) = consume_and_decode::<EnumTest>(&bytes).unwrap();
-    //     assert_eq!(left.len(), 0);
-    //     match actual {
-    //         EnumTest::BB(n) => assert_eq!(n, 123),
-    //         _ => panic!(),
-    //     }
-
-    //     let cc = EnumTest::CC;
-    //     let bytes = cc._to_bytes();
-    //     let (actual, left) = consume_and_decode::<EnumTest>(&bytes).unwrap();
-    //     assert_eq!(left.len(), 0);
-    //     match actual {
-    //         EnumTest::CC => {}
-    //         _ => panic!(),
-    //     }
-    // }
+    #[test]
+    fn derive_onchain_tuple_and_unit_struct() {
+        #[derive(OnChain)]
+        #[onchain(id = "gold")]
+        pub struct Gold(u64);
+
+        #[derive(OnChain)]
+        pub struct Pos(#[onchain(default = 3)] u8, String);
+
+        #[derive(OnChain)]
+        pub struct Marker;
+
+        let gold = Gold(12);
+        assert_eq!(gold._to_bytes(), 12u64._to_bytes());
+        assert_eq!(Gold::_fixed_size(), Some(8));
+        assert_eq!(decode::<Gold>(&gold._to_bytes()).unwrap().0, 12);
+        assert_eq!(Gold::_id(), Some("gold"));
+
+        let pos = Pos::onchain_new(1, String::from("x"));
+        let actual = decode::<Pos>(&pos._to_bytes()).unwrap();
+        assert_eq!((actual.0, actual.1.as_str()), (1, "x"));
+        assert_eq!(Pos::_default().0, 3);
+
+        assert_eq!(Marker::_fixed_size(), Some(0));
+        assert!(Marker._to_bytes().is_empty());
+        assert!(decode::<Marker>(&[]).unwrap()._eq(&Marker::_default()));
+    }
+
+    #[test]
+    fn derive_onchain_multi_field_enum() {
+        #[derive(OnChain)]
+        pub enum Action {
+            #[onchain(tag = 0)]
+            Move { x: i32, y: i32 },
+            #[onchain(tag = 1)]
+            Attack(u8, u32),
+            #[onchain(tag = 2)]
+            Say(String),
+            #[onchain(tag = 3, default)]
+            Rest {
+                #[onchain(default = 5)]
+                turns: u8,
+                healing: bool,
+            },
+        }
+
+        let actions = [
+            Action::Move { x: -1, y: 2 },
+            Action::Attack(3, 400),
+            Action::Say(String::from("hi")),
+            Action::_default(),
+        ];
+        actions.iter().for_each(|action| {
+            let bytes = action._to_bytes();
+            assert_eq!(bytes.len() as u64, action._capacity());
+            let actual = decode::<Action>(&bytes).unwrap();
+            assert!(actual._eq(action));
+        });
+        assert!(!actions[0]._eq(&Action::Move { x: -1, y: 3 }));
+        assert!(!actions[1]._eq(&actions[2]));
+        match Action::_default() {
+            Action::Rest { turns, healing } => assert_eq!((turns, healing), (5, false)),
+            _ => panic!(""),
+        }
+    }
+
+    #[test]
+    fn derive_onchain_generic() {
+        #[derive(OnChain)]
+        pub struct Pair<A, B> {
+            pub a: A,
+            pub b: B,
+        }
+
+        #[derive(OnChain)]
+        pub enum Either<L, R> {
+            #[onchain(tag = 0, default)]
+            Left(L),
+            #[onchain(tag = 1)]
+            Right(R),
+        }
+
+        let pair = Pair::onchain_new(7u8, Either::<u16, String>::Right(String::from("hi")));
+        assert_eq!(<Pair<u8, u16> as OnChain>::_fixed_size(), Some(3));
+        assert_eq!(
+            <Pair<u8, Either<u16, String>> as OnChain>::_fixed_size(),
+            None
+        );
+        let bytes = pair._to_bytes();
+        let actual = decode::<Pair<u8, Either<u16, String>>>(&bytes).unwrap();
+        assert!(actual._eq(&pair));
+        match <Either<u16, String> as OnChain>::_default() {
+            Either::Left(l) => assert_eq!(l, 0),
+            Either::Right(_) => panic!("should be left"),
+        }
+    }
+
+    #[test]
+    fn versioned_migration() {
+        use ckboots::{decode_versioned, Migrate};
+        use ckboots_derives::migration;
+
+        #[derive(OnChain)]
+        pub struct HeroV1 {
+            pub hp: u8,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(version = 2, migrate)]
+        pub struct HeroV2 {
+            pub hp: u16,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "hero", version = 3, migrate)]
+        pub struct Hero {
+            pub hp: u16,
+            pub mana: u8,
+        }
+
+        #[migration]
+        impl Migrate for HeroV2 {
+            type Previous = HeroV1;
+
+            fn _migrate(previous: HeroV1) -> Self {
+                HeroV2 {
+                    hp: previous.hp as u16 * 10,
+                }
+            }
+        }
+
+        #[migration]
+        impl Migrate for Hero {
+            type Previous = HeroV2;
+
+            fn _migrate(previous: HeroV2) -> Self {
+                Hero {
+                    hp: previous.hp,
+                    mana: 5,
+                }
+            }
+        }
+
+        assert_eq!(HeroV1::_version(), 1);
+        assert_eq!(Hero::_version(), 3);
+        assert_eq!(Hero::_migration_id(), Some("migrate_hero"));
+        let v1 = HeroV1 { hp: 7 }._to_bytes();
+        let hero = decode_versioned::<Hero>(1, &v1).unwrap();
+        assert_eq!((hero.hp, hero.mana), (70, 5));
+        let v3 = Hero { hp: 1, mana: 2 }._to_bytes();
+        assert!(decode_versioned::<Hero>(3, &v3)
+            .unwrap()
+            ._eq(&Hero { hp: 1, mana: 2 }));
+        assert_eq!(
+            decode_versioned::<Hero>(4, &v3).err(),
+            Some(DecodeError::UnknownVersion(4))
+        );
+        assert_eq!(
+            decode_versioned::<HeroV1>(2, &v1).err(),
+            Some(DecodeError::UnknownVersion(2))
+        );
+    }
+
+    #[test]
+    fn cell_manager_typed_access() {
+        use ckboots::{CellManager, Migrate, StateError};
+        use ckboots_derives::migration;
+
+        #[derive(OnChain)]
+        pub struct HeroV1 {
+            pub hp: u8,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "hero", version = 2, migrate)]
+        pub struct Hero {
+            pub hp: u16,
+            pub mana: u8,
+        }
+
+        #[migration]
+        impl Migrate for Hero {
+            type Previous = HeroV1;
+
+            fn _migrate(previous: HeroV1) -> Self {
+                Hero {
+                    hp: previous.hp as u16 * 10,
+                    mana: 5,
+                }
+            }
+        }
+
+        let v1 = HeroV1 { hp: 7 }._to_bytes();
+        let out_point = OutPoint::new(Byte32::new([3; 32]), 0);
+        let mut manager = CellManager::from_cells(vec![("hero", 1, v1, out_point.clone())]);
+        let hero = manager.get::<Hero>().unwrap();
+        assert_eq!((hero.hp, hero.mana), (70, 5));
+
+        let mana = manager
+            .update(|hero: &mut Hero| {
+                hero.mana += 1;
+                hero.mana
+            })
+            .unwrap();
+        assert_eq!(mana, 6);
+        manager.update(|hero: &mut Hero| hero.mana += 1).unwrap();
+        assert_eq!(manager.get::<Hero>().unwrap().mana, 5);
+        manager.commit();
+        assert_eq!(manager.get::<Hero>().unwrap().mana, 7);
+        // Still in the same cell, now written by the current version
+        assert_eq!(manager.get_out_point("hero"), Some(&out_point));
+        assert_eq!(manager.get_version("hero"), Some(2));
+
+        assert_eq!(manager.get::<HeroV1>().err(), Some(StateError::NoId));
+        let manager = CellManager::new(vec![]);
+        assert_eq!(
+            manager.get::<Hero>().err(),
+            Some(StateError::Unregistered("hero"))
+        );
+        let manager = CellManager::new(vec![("hero", vec![1])]);
+        assert_eq!(
+            manager.get::<Hero>().err(),
+            Some(StateError::Decode(DecodeError::Truncated))
+        );
+    }
+
+    #[test]
+    fn molecule_encoding() {
+        use ckboots::Molecule;
+
+        #[derive(OnChain)]
+        #[onchain(encoding = "molecule")]
+        pub struct Point {
+            pub x: u16,
+            pub y: u16,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "frog", encoding = "molecule")]
+        pub struct Frog {
+            pub pos: Point,
+            pub name: String,
+            pub items: Vec<u32>,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(encoding = "molecule")]
+        pub enum Action {
+            #[onchain(tag = 0, default)]
+            Stay,
+            #[onchain(tag = 1)]
+            Move(Point),
+        }
+
+        let point = Point { x: 1, y: 2 };
+        assert_eq!(Point::_fixed_size(), Some(4));
+        assert_eq!(point._to_bytes(), vec![1, 0, 2, 0]);
+
+        let frog = Frog {
+            pos: point,
+            name: String::from("ab"),
+            items: vec![3],
+        };
+        let molecule = vec![
+            34, 0, 0, 0, 16, 0, 0, 0, 20, 0, 0, 0, 26, 0, 0, 0, // header
+            1, 0, 2, 0, // pos
+            2, 0, 0, 0, b'a', b'b', // name
+            1, 0, 0, 0, 3, 0, 0, 0, // items
+        ];
+        assert_eq!(frog._to_molecule(), molecule);
+        let bytes = frog._to_bytes();
+        assert_eq!(bytes, molecule);
+        assert!(decode::<Frog>(&bytes).unwrap()._eq(&frog));
+        assert_eq!(
+            Frog::_from_molecule(&molecule[..30]).err(),
+            Some(DecodeError::BadLengthPrefix)
+        );
+
+        let action = Action::Move(Point { x: 1, y: 2 });
+        assert_eq!(action._to_molecule(), vec![1, 0, 0, 0, 1, 0, 2, 0]);
+        assert_eq!(Action::Stay._to_molecule(), vec![0, 0, 0, 0, 4, 0, 0, 0]);
+        assert!(decode::<Action>(&action._to_bytes()).unwrap()._eq(&action));
+        // Without a length prefix, the Molecule header delimits them in the
+        // default encoding
+        let actions = vec![Action::Stay, action];
+        let decoded = decode::<Vec<Action>>(&actions._to_bytes()).unwrap();
+        assert!(decoded._eq(&actions));
+        assert_eq!(
+            Action::_from_molecule(&[2, 0, 0, 0]).err(),
+            Some(DecodeError::UnknownUnionId(2))
+        );
+        assert_eq!(
+            Action::_from_molecule(&[0, 1, 0, 0]).err(),
+            Some(DecodeError::UnknownUnionId(256))
+        );
+
+        let mut decls = vec![];
+        Frog::_molecule_decls(&mut decls);
+        Action::_molecule_decls(&mut decls);
+        assert_eq!(
+            decls,
+            vec![
+                "array Uint16 [byte; 2];",
+                "struct Point {\n    x: Uint16,\n    y: Uint16,\n}",
+                "vector Bytes <byte>;",
+                "array Uint32 [byte; 4];",
+                "vector Uint32Vec <Uint32>;",
+                "table Frog {\n    pos: Point,\n    name: Bytes,\n    items: Uint32Vec,\n}",
+                "table ActionStay {\n}",
+                "struct ActionMove {\n    f0: Point,\n}",
+                "union Action {\n    ActionStay: 0,\n    ActionMove: 1,\n}",
+            ]
+        );
+    }
+
+    #[test]
+    fn cell_manager_transactions() {
+        use ckboots::CellManager;
+
+        let mut manager = CellManager::new(vec![
+            ("hero", 1u8._to_bytes()),
+            ("gold", 10u32._to_bytes()),
+            ("bag", vec![]),
+        ]);
+        manager.snapshot("genesis");
+
+        manager.set_pending("gold", 20u32._to_bytes());
+        manager.set_pending("gold", 30u32._to_bytes());
+        assert!(manager.has_pending());
+        assert_eq!(manager.get_by_id("gold"), Some(&10u32._to_bytes()[..]));
+        assert_eq!(manager.get_pending("gold"), Some(&30u32._to_bytes()[..]));
+        manager.commit();
+        assert!(!manager.has_pending());
+        assert_eq!(manager.get_by_id("gold"), Some(&30u32._to_bytes()[..]));
+        assert_eq!(manager.get_by_id("hero"), Some(&1u8._to_bytes()[..]));
+        assert_eq!(manager.get_by_id("bag"), Some(&[][..]));
+
+        manager.set_pending("hero", 2u8._to_bytes());
+        manager.rollback();
+        manager.commit();
+        assert_eq!(manager.get_by_id("hero"), Some(&1u8._to_bytes()[..]));
+
+        manager.set_pending("hero", 3u8._to_bytes());
+        assert!(manager.restore("genesis"));
+        assert!(!manager.has_pending());
+        assert_eq!(manager.get_by_id("gold"), Some(&10u32._to_bytes()[..]));
+        assert!(!manager.restore("missing"));
+    }
+
+    // Cases found by the targets in `fuzz/`
+    #[test]
+    fn malformed_bytes() {
+        use ckboots::Molecule;
+
+        let bytes = [8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(
+            decode::<Vec<[u8; 0]>>(&bytes).err(),
+            Some(DecodeError::TrailingBytes(8))
+        );
+        // The offset of the second item is past the end
+        let table = [16, 0, 0, 0, 12, 0, 0, 0, 99, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(
+            Vec::<String>::_from_molecule(&table).err(),
+            Some(DecodeError::BadLengthPrefix)
+        );
+        // A table without fields followed by bytes which no field takes
+        let table = [8, 0, 0, 0, 4, 0, 0, 0];
+        assert_eq!(
+            Vec::<String>::_from_molecule(&table).err(),
+            Some(DecodeError::TrailingBytes(4))
+        );
+        assert_eq!(
+            Vec::<[u8; 0]>::_from_molecule(&[3, 0, 0, 0]).err(),
+            Some(DecodeError::BadLengthPrefix)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_bridge() {
+        use ckboots::serde_json;
+        use ckboots::JsonError;
+        use std::collections::BTreeMap;
+
+        #[derive(OnChain, Debug)]
+        pub struct Pos(u8, i16);
+
+        #[derive(OnChain, Debug)]
+        pub enum Action {
+            #[onchain(tag = 0, default)]
+            Stay,
+            #[onchain(tag = 1)]
+            Move(Pos),
+            #[onchain(tag = 2)]
+            Attack { target: u32, power: u128 },
+        }
+
+        #[derive(OnChain, Debug)]
+        pub struct Hero {
+            pub name: String,
+            pub bag: BTreeMap<u8, Bytes>,
+            pub actions: Vec<Action>,
+            pub pet: Option<u8>,
+        }
+
+        let hero = Hero {
+            name: String::from("tom"),
+            bag: BTreeMap::from([(1, Bytes::from(vec![0xab]))]),
+            actions: vec![
+                Action::Stay,
+                Action::Move(Pos(1, -2)),
+                Action::Attack {
+                    target: 3,
+                    power: 4,
+                },
+            ],
+            pet: None,
+        };
+        let value = json!({
+            "name": "tom",
+            "bag": [[1, "0xab"]],
+            "actions": ["Stay", {"Move": [1, -2]}, {"Attack": {"target": 3, "power": "4"}}],
+            "pet": null,
+        });
+        assert_eq!(serde_json::to_value(&hero).unwrap(), value);
+        let actual: Hero = serde_json::from_value(value).unwrap();
+        assert!(actual._eq(&hero));
+
+        let err = serde_json::from_value::<Action>(json!({"Jump": 1})).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `Jump`"));
+        assert_eq!(
+            <Pos as ckboots::OnChainJson>::_from_json(&json!([256, 0])).err(),
+            Some(JsonError::OutOfRange(String::from("256")))
+        );
+    }
+
+    #[test]
+    fn enum_tags() {
+        #[derive(OnChain)]
+        pub enum Before {
+            #[onchain(tag = 0, default)]
+            A,
+            #[onchain(tag = 1)]
+            B(u8),
+        }
+
+        #[derive(OnChain)]
+        pub enum After {
+            #[onchain(tag = 1)]
+            B(u8),
+            #[onchain(tag = 5)]
+            C,
+            #[onchain(tag = 6)]
+            D,
+            #[onchain(tag = 0, default)]
+            A,
+        }
+
+        let bytes = Before::B(7)._to_bytes();
+        assert!(matches!(decode::<After>(&bytes), Ok(After::B(7))));
+        let bytes = Before::A._to_bytes();
+        assert!(matches!(decode::<After>(&bytes), Ok(After::A)));
+        assert_eq!(After::C._to_bytes()[8], 5);
+        assert_eq!(After::D._to_bytes()[8], 6);
+        let err = decode::<Before>(&After::D._to_bytes()).err();
+        assert_eq!(err, Some(DecodeError::UnknownTag(6)));
+    }
+
+    #[test]
+    fn option_bool_array_tuple() {
+        #[derive(OnChain)]
+        pub struct Hero {
+            pub gold: Option<u64>,
+            pub alive: bool,
+            pub owner: [u8; 32],
+            pub pos: (u8, u32),
+            pub names: [String; 2],
+        }
+
+        assert_eq!(<[u8; 32]>::_fixed_size(), Some(32));
+        assert_eq!(<(u8, u32)>::_fixed_size(), Some(5));
+        assert_eq!(<(u8, String)>::_fixed_size(), None);
+        assert_eq!(<Option<u8>>::_fixed_size(), None);
+        assert_eq!(
+            decode::<bool>(&[2]).err(),
+            Some(DecodeError::InvalidBool(2))
+        );
+
+        let hero = Hero {
+            gold: Some(7),
+            alive: true,
+            owner: [3; 32],
+            pos: (1, 2),
+            names: [String::from("a"), String::from("bc")],
+        };
+        let actual = decode::<Hero>(&hero._to_bytes()).unwrap();
+        assert!(actual._eq(&hero));
+        assert_eq!(actual.gold, Some(7));
+        assert_eq!(actual.names[1], "bc");
+
+        let d = Hero::_default();
+        assert_eq!(d.gold, None);
+        assert!(!d.alive);
+        assert_eq!(d.owner, [0; 32]);
+        assert!(!d._eq(&hero));
+        assert!(decode::<Hero>(&d._to_bytes()).unwrap()._eq(&d));
+    }
+
+    #[test]
+    fn sorted_map_and_set() {
+        use std::collections::{BTreeMap, BTreeSet};
+
+        let mut items = BTreeMap::new();
+        items.insert(String::from("sword"), 1u32);
+        items.insert(String::from("axe"), 2u32);
+        let bytes = items._to_bytes();
+        assert_eq!(decode::<BTreeMap<String, u32>>(&bytes).unwrap(), items);
+
+        let entry = |k: u8, v: u8| [k._to_bytes(), v._to_bytes()].concat();
+        let map = |entries: Vec<Vec<u8>>| {
+            let content = entries.concat();
+            [(content.len() as u64).to_le_bytes().to_vec(), content].concat()
+        };
+        let sorted = map(vec![entry(1, 9), entry(2, 8)]);
+        assert_eq!(decode::<BTreeMap<u8, u8>>(&sorted).unwrap().len(), 2);
+        let unsorted = map(vec![entry(2, 8), entry(1, 9)]);
+        let err = decode::<BTreeMap<u8, u8>>(&unsorted).err();
+        assert_eq!(err, Some(DecodeError::UnsortedKeys));
+        let duplicated = map(vec![entry(1, 9), entry(1, 8)]);
+        let err = decode::<BTreeMap<u8, u8>>(&duplicated).err();
+        assert_eq!(err, Some(DecodeError::UnsortedKeys));
+
+        let set: BTreeSet<u16> = [3, 1, 2].into_iter().collect();
+        let bytes = set._to_bytes();
+        assert_eq!(bytes, vec![1u16, 2, 3]._to_bytes());
+        assert_eq!(decode::<BTreeSet<u16>>(&bytes).unwrap(), set);
+        let err = decode::<BTreeSet<u16>>(&vec![1u16, 1]._to_bytes()).err();
+        assert_eq!(err, Some(DecodeError::UnsortedKeys));
+    }
+
+    #[test]
+    fn decode_errors() {
+        #[derive(OnChain)]
+        pub struct Pair {
+            pub a: u16,
+            pub b: Vec<u8>,
+        }
+
+        #[derive(OnChain)]
+        pub enum Tagged {
+            #[onchain(tag = 0, default)]
+            A,
+            #[onchain(tag = 1)]
+            B(u32),
+        }
+
+        let bytes = 7u32._to_bytes();
+        let err = consume_and_decode::<u32>(&bytes[..3]).err();
+        assert_eq!(err, Some(DecodeError::Truncated));
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(
+            decode::<u32>(&long).err(),
+            Some(DecodeError::TrailingBytes(1))
+        );
+        assert_eq!(decode::<u32>(&bytes).unwrap(), 7);
+
+        let bytes = Pair {
+            a: 1,
+            b: vec![2, 3],
+        }
+        ._to_bytes();
+        let err = consume_and_decode::<Pair>(&bytes[..4]).err();
+        assert_eq!(err, Some(DecodeError::Truncated));
+        let err = consume_and_decode::<Pair>(&bytes[..bytes.len() - 1]).err();
+        assert_eq!(err, Some(DecodeError::BadLengthPrefix));
+        let mut prefix = u64::MAX.to_le_bytes().to_vec();
+        prefix.extend(&bytes[8..]);
+        let err = consume_and_decode::<Pair>(&prefix).err();
+        assert_eq!(err, Some(DecodeError::BadLengthPrefix));
+
+        let mut bytes = Tagged::B(5)._to_bytes();
+        bytes[8] = 9;
+        let err = consume_and_decode::<Tagged>(&bytes).err();
+        assert_eq!(err, Some(DecodeError::UnknownTag(9)));
+        let mut bytes = Tagged::A._to_bytes();
+        bytes[0] += 1;
+        bytes.push(0);
+        let err = consume_and_decode::<Tagged>(&bytes).err();
+        assert_eq!(err, Some(DecodeError::TrailingBytes(1)));
+    }
+
+    #[test]
+    fn derive_onchain_struct() {
+        #[derive(OnChain)]
+        #[onchain(id = "person")]
+        pub struct Person {
+            pub age: u16,
+            pub name: String,
+        }
+
+        let tom = Person {
+            age: 12,
+            name: String::from("Tom"),
+        };
+        let bytes = tom._to_bytes();
+        let (actual, left) = consume_and_decode::<Person>(&bytes).unwrap();
+        assert_eq!(actual.age, 12);
+        assert_eq!(actual.name, "Tom");
+        assert_eq!(left.len(), 0);
+        assert_eq!(Person::_id(), Some("person"));
+    }
+
+    #[test]
+    fn derive_onchain_enum() {
+        #[derive(OnChain)]
+        pub enum EnumTest {
+            #[onchain(tag = 0)]
+            AA(String),
+            #[onchain(tag = 1)]
+            BB(u32),
+            #[onchain(tag = 2, default)]
+            CC,
+        }
+        let aa = EnumTest::AA(String::from("1"));
+        let bytes = aa._to_bytes();
+        let (actual, left) = consume_and_decode::<EnumTest>(&bytes).unwrap();
+        assert_eq!(left.len(), 0);
+        match actual {
+            EnumTest::AA(s) => assert_eq!(s, "1"),
+            _ => panic!(),
+        }
+
+        let bb = EnumTest::BB(123);
+        let bytes = bb._to_bytes();
+        let (actual, left) = consume_and_decode::<EnumTest>(&bytes).unwrap();
+        assert_eq!(left.len(), 0);
+        match actual {
+            EnumTest::BB(n) => assert_eq!(n, 123),
+            _ => panic!(),
+        }
+
+        let cc = EnumTest::CC;
+        let bytes = cc._to_bytes();
+        let (actual, left) = consume_and_decode::<EnumTest>(&bytes).unwrap();
+        assert_eq!(left.len(), 0);
+        match actual {
+            EnumTest::CC => {}
+            _ => panic!(),
+        }
+    }
 
     #[test]
     fn enum_default() {
         #[derive(OnChain)]
         pub enum EnumTest1 {
+            #[onchain(tag = 0)]
             AA,
-            #[onchain(default)]
+            #[onchain(tag = 1, default)]
             BB,
+            #[onchain(tag = 2)]
             CC,
         }
         assert!(matches!(EnumTest1::_default(), EnumTest1::BB));
 
         #[derive(OnChain)]
         pub enum EnumTest2 {
+            #[onchain(tag = 0)]
             AA(u8),
-            #[onchain(default = 4)]
+            #[onchain(tag = 1, default = 4)]
             BB(u32),
+            #[onchain(tag = 2)]
             CC,
         }
 
@@ -116,9 +824,11 @@ mod tests {
 
         #[derive(OnChain)]
         pub enum EnumTest3 {
+            #[onchain(tag = 0)]
             AA(u8),
-            #[onchain(default)]
+            #[onchain(tag = 1, default)]
             BB(u32),
+            #[onchain(tag = 2)]
             CC,
         }
 
@@ -127,4 +837,1152 @@ mod tests {
             _ => panic!(""),
         }
     }
+
+    const CONFIG: &str = r#"
+[chain]
+rpc = "http://127.0.0.1:8114"
+secret_key = "0x0101010101010101010101010101010101010101010101010101010101010101"
+
+[boots]
+app_name = "counter"
+initial_capacity = "2000000"
+"#;
+
+    #[test]
+    fn parse_config() {
+        let config = CONFIG.parse::<Config>().unwrap();
+        assert_eq!(config.chain.rpc, "http://127.0.0.1:8114");
+        assert_eq!(config.boots.app_name, "counter");
+        assert_eq!(config.boots.initial_capacity, 2_000_000 * 100_000_000);
+        assert!(!config.dev.debug);
+
+        let config = CONFIG
+            .replace("secret_key", "secreat_key")
+            .replace("\"2000000\"", "2000000")
+            .parse::<Config>()
+            .unwrap();
+        assert_eq!(config.boots.initial_capacity, 2_000_000 * 100_000_000);
+
+        let err = CONFIG.replace("app_name", "name").parse::<Config>();
+        assert!(matches!(err, Err(ConfigError::Parse(_))));
+        let err = CONFIG.replace("rpc = ", "# rpc = ").parse::<Config>();
+        assert!(matches!(err, Err(ConfigError::Missing("chain.rpc"))));
+        let err = CONFIG.replace("\"2000000\"", "\"2M\"").parse::<Config>();
+        assert!(matches!(
+            err,
+            Err(ConfigError::Invalid {
+                key: "boots.initial_capacity",
+                ..
+            })
+        ));
+        let err = format!("{}\n[dev]\ndebug = true\n", CONFIG).parse::<Config>();
+        assert!(matches!(
+            err,
+            Err(ConfigError::Missing("dev.contracts_dir"))
+        ));
+    }
+
+    #[test]
+    fn secret_key_from_env() {
+        let key = "0202020202020202020202020202020202020202020202020202020202020202";
+        let config = Config::parse(&CONFIG.replace("0x01", "0xzz"), Some(key.to_string()));
+        assert_eq!(config.unwrap().chain.secret_key.secret_bytes(), [2; 32]);
+
+        let err = CONFIG.replace("0x01", "0xzz").parse::<Config>();
+        assert!(matches!(
+            err,
+            Err(ConfigError::Invalid {
+                key: "chain.secret_key",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn load_capsule_deployment() {
+        let dir = tempfile::tempdir().unwrap();
+        let migrations = dir.path().join("migrations").join("dev");
+        std::fs::create_dir_all(&migrations).unwrap();
+        let err = Deployment::load(dir.path());
+        assert!(matches!(err, Err(FileError::NoMigration(_))));
+        let recipe = |name: &str, index: u32, type_id: &str| {
+            format!(
+                r#"{{"name":"{name}","tx_hash":"0x{}","index":{index},"occupied_capacity":0,"data_hash":"0x{}","type_id":{type_id}}}"#,
+                "11".repeat(32),
+                "00".repeat(32),
+            )
+        };
+        let migration = format!(
+            r#"{{"cell_recipes":[{},{}],"dep_group_recipes":[]}}"#,
+            recipe("_entry", 0, &format!("\"0x{}\"", "33".repeat(32))),
+            recipe("travel", 1, &format!("\"0x{}\"", "22".repeat(32))),
+        );
+        std::fs::write(migrations.join("2023-01-01-000000.json"), "{}").unwrap();
+        let err = Deployment::load(dir.path());
+        assert!(matches!(err, Err(FileError::Malformed(_, _))));
+        std::fs::write(migrations.join("2023-02-01-000000.json"), migration).unwrap();
+
+        let deployment = Deployment::load(dir.path()).unwrap();
+        let (out_point, type_hash) = deployment.entry;
+        assert_eq!(out_point, OutPoint::new(Byte32::new([0x11; 32]), 0));
+        assert_eq!(type_hash, Byte32::new([0x33; 32]));
+        let (out_point, type_hash) = deployment.contracts.get("travel").unwrap();
+        assert_eq!(*out_point, OutPoint::new(Byte32::new([0x11; 32]), 1));
+        assert_eq!(*type_hash, Byte32::new([0x22; 32]));
+    }
+
+    #[test]
+    fn bootstrap_errors() {
+        use ckboots::{BootstrapError, ClientError};
+
+        let err = App::bootstrap_with(CONFIG.parse::<Config>().unwrap()).err();
+        assert!(matches!(
+            err,
+            Some(BootstrapError::Config(ConfigError::Invalid {
+                key: "dev.debug",
+                ..
+            }))
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = CONFIG.replace("127.0.0.1:8114", "127.0.0.1:1");
+        let config = format!(
+            "{}\n[dev]\ndebug = true\ncontracts_dir = {:?}\n",
+            config,
+            dir.path()
+        );
+        let err = App::bootstrap_with(config.parse::<Config>().unwrap()).err();
+        assert!(matches!(
+            err,
+            Some(BootstrapError::Deployment(FileError::Io(_, _)))
+        ));
+
+        // A node which is down is not a config error
+        let migrations = dir.path().join("migrations").join("dev");
+        std::fs::create_dir_all(&migrations).unwrap();
+        let migration = format!(
+            r#"{{"cell_recipes":[{{"name":"_entry","tx_hash":"0x{}","index":0,"occupied_capacity":0,"data_hash":"0x{}","type_id":"0x{}"}}],"dep_group_recipes":[]}}"#,
+            "11".repeat(32),
+            "00".repeat(32),
+            "33".repeat(32),
+        );
+        std::fs::write(migrations.join("2023-01-01-000000.json"), migration).unwrap();
+        let err = App::bootstrap_with(config.parse::<Config>().unwrap()).err();
+        assert!(matches!(
+            err,
+            Some(BootstrapError::Client(ClientError::Rpc(_)))
+        ));
+    }
+
+    #[test]
+    fn build_tx_from_contract_result() {
+        use ckboots::testing::SimChain;
+
+        #[derive(OnChain)]
+        #[onchain(id = "counter")]
+        pub struct Counter {
+            pub value: u32,
+        }
+
+        let out_point = |n: u8| OutPoint::new(Byte32::new([n; 32]), 0);
+        let state_cell = |n: u8, idx: u8, value: u32| {
+            let data = OnChainWrapper {
+                idx,
+                version: 1,
+                data: Counter { value }._to_bytes(),
+            }
+            ._to_bytes();
+            let output = CellOutput::new_builder()
+                .capacity((1000 * 100_000_000u64).pack())
+                .build();
+            Cell::new(out_point(n), output, data)
+        };
+        let mut cells = HashMap::new();
+        cells.insert(("counter", vec![]), state_cell(1, 0, 1));
+        cells.insert(("config", vec![]), state_cell(2, 1, 7));
+        cells.insert(
+            (ckboots::REGISTRY_ID, vec![]),
+            registry_cell(out_point(8), vec![[5; 32]]),
+        );
+        let mut contracts = HashMap::new();
+        contracts.insert(String::from("incr"), (out_point(4), Byte32::new([5; 32])));
+        contracts.insert(String::from("drain"), (out_point(7), Byte32::new([7; 32])));
+        let config = CONFIG.parse::<Config>().unwrap();
+        let chain = SimChain::new();
+        let mut app = App::new(
+            config,
+            Box::new(chain.clone()),
+            Deployment {
+                entry: (out_point(3), Byte32::new([6; 32])),
+                contracts,
+                lock_dep: None,
+            },
+            cells,
+        );
+        let funding = chain.fund(app.lock(), 100 * 100_000_000);
+
+        let res = ContractResult {
+            deps: vec!["config"],
+            input_output_data: vec![(
+                "counter",
+                Counter { value: 1 }._to_bytes(),
+                Counter { value: 2 }._to_bytes(),
+            )],
+            contract_id: "incr",
+            user_input: Some(vec![9]),
+            versions: vec![("counter", 2)],
+            keys: vec![],
+        };
+        let err = app.build_tx(ContractResult {
+            contract_id: "decr",
+            ..res.clone()
+        });
+        assert!(matches!(err, Err(BuildError::NotDeployed("decr"))));
+        let err = app.build_tx(ContractResult {
+            contract_id: "drain",
+            ..res.clone()
+        });
+        assert!(matches!(err, Err(BuildError::Unregistered("drain"))));
+        let err = app.build_tx(ContractResult {
+            deps: vec!["missing"],
+            ..res.clone()
+        });
+        assert!(matches!(err, Err(BuildError::MissingCell("missing", _))));
+        let (tx, new_cells) = app.build_tx(res).unwrap();
+
+        // The counter and the funding cell which pays the fee
+        assert_eq!(tx.inputs().len(), 2);
+        assert_eq!(tx.inputs().get(0).unwrap().previous_output(), out_point(1));
+        assert_eq!(
+            tx.inputs().get(1).unwrap().previous_output(),
+            funding.outpoint
+        );
+        let fee = 1100 * 100_000_000 - tx.outputs_capacity().unwrap().as_u64();
+        assert!(fee > 0 && fee < 100_000);
+        let cell_deps = tx
+            .cell_deps_iter()
+            .map(|d| d.out_point())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cell_deps,
+            vec![out_point(2), out_point(3), out_point(8), out_point(4)]
+        );
+
+        // Read like `load_user_input` and `load_exec_script` of the contracts
+        let witness = WitnessArgs::from_slice(&tx.witnesses().get(0).unwrap().raw_data()).unwrap();
+        assert_eq!(ckboots::utils::witness_user_input(&witness), vec![9]);
+        assert_eq!(ckboots::utils::witness_exec_script(&witness), vec![5; 32]);
+        let empty = WitnessArgs::default();
+        assert!(ckboots::utils::witness_user_input(&empty).is_empty());
+        assert!(ckboots::utils::witness_exec_script(&empty).is_empty());
+
+        let cell = new_cells.get(&("counter", vec![])).unwrap();
+        assert_eq!(cell.outpoint, OutPoint::new(tx.hash(), 0));
+        let (wrapper, _) = consume_and_decode::<OnChainWrapper>(&cell.data).unwrap();
+        assert_eq!((wrapper.idx, wrapper.version), (0, 2));
+        let (counter, _) = consume_and_decode::<Counter>(&wrapper.data).unwrap();
+        assert_eq!(counter.value, 2);
+        assert_eq!(
+            tx.outputs_data().get(0).unwrap().raw_data().to_vec(),
+            cell.data
+        );
+    }
+
+    #[test]
+    fn grown_state_is_topped_up() {
+        use ckboots::testing::SimChain;
+        use ckboots::utils::get_state_capacity;
+        use ckboots::BuildError;
+
+        #[derive(OnChain)]
+        pub struct Item {
+            pub name: String,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "bag")]
+        pub enum Bag {
+            #[onchain(tag = 0, default)]
+            Items(Vec<Item>),
+        }
+
+        let bag = Bag::Items(vec![Item {
+            name: String::from("sword"),
+        }]);
+        assert_eq!(bag._capacity(), bag._to_bytes().len() as u64);
+        let wrapper = OnChainWrapper {
+            idx: 0,
+            version: 1,
+            data: bag._to_bytes(),
+        };
+        assert_eq!(wrapper._capacity(), wrapper._to_bytes().len() as u64);
+        assert_eq!(
+            OnChainWrapper::encoded_len(bag._capacity()),
+            wrapper._capacity()
+        );
+        assert!(decode::<Bag>(&bag._to_bytes()).unwrap()._eq(&bag));
+
+        let config = CONFIG
+            .replace("\"2000000\"", "\"200\"")
+            .parse::<Config>()
+            .unwrap();
+        let chain = SimChain::new();
+        let mut contracts = HashMap::new();
+        contracts.insert(String::from("loot"), chain.deploy("loot"));
+        let deployment = Deployment {
+            entry: chain.deploy("_entry"),
+            contracts,
+            lock_dep: None,
+        };
+        let mut app = App::new(config, Box::new(chain.clone()), deployment, HashMap::new());
+        let funding = chain.fund(app.lock(), 500 * 100_000_000);
+        let states = vec![("bag", 1, Bag::_default()._to_bytes())];
+        let (tx, genesis) = app.build_genesis_tx(states, vec![funding]).unwrap();
+        app.send_tx(tx).unwrap();
+        app.update_cells(genesis.cells.clone());
+
+        let loot = |n: usize| {
+            let items = (0..n)
+                .map(|_| Item {
+                    name: String::from("sword"),
+                })
+                .collect();
+            ContractResult {
+                deps: vec![],
+                input_output_data: vec![(
+                    "bag",
+                    Bag::_default()._to_bytes(),
+                    Bag::Items(items)._to_bytes(),
+                )],
+                contract_id: "loot",
+                user_input: None,
+                versions: vec![],
+                keys: vec![],
+            }
+        };
+        let (tx, cells) = app.build_tx(loot(2)).unwrap();
+        assert_eq!((tx.inputs().len(), tx.outputs().len()), (2, 2));
+        let cell = cells.get(&("bag", vec![])).unwrap();
+        assert_eq!(cell.outpoint, OutPoint::new(tx.hash(), 0));
+        let capacity: u64 = cell.output.capacity().unpack();
+        let type_script = genesis.type_script.clone();
+        let state = Bag::Items(vec![
+            Item {
+                name: String::from("sword"),
+            },
+            Item {
+                name: String::from("sword"),
+            },
+        ]);
+        assert_eq!(
+            capacity,
+            get_state_capacity(&state, app.lock(), &type_script)
+        );
+        app.send_tx(tx).unwrap();
+        app.update_cells(cells);
+
+        let err = app.build_tx(loot(100)).err();
+        assert!(matches!(err, Some(BuildError::NotEnoughCapacity { .. })));
+    }
+
+    #[test]
+    fn build_genesis_tx() {
+        #[derive(OnChain)]
+        #[onchain(id = "hero")]
+        pub struct Hero {
+            #[onchain(default = 100)]
+            pub hp: u8,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "gold")]
+        pub struct Gold {
+            #[onchain(default = 7)]
+            pub amount: u64,
+        }
+
+        let config = CONFIG
+            .replace("\"2000000\"", "\"1000\"")
+            .parse::<Config>()
+            .unwrap();
+        let client = RpcClient::new(&config.chain.rpc);
+        let entry_hash = Byte32::new([6; 32]);
+        let app = App::new(
+            config,
+            Box::new(client),
+            Deployment {
+                entry: (OutPoint::new(Byte32::new([3; 32]), 0), entry_hash.clone()),
+                ..Default::default()
+            },
+            HashMap::new(),
+        );
+        let states = vec![
+            ("hero", 2, Hero::_default()._to_bytes()),
+            ("gold", 1, Gold::_default()._to_bytes()),
+        ];
+        let funding_cell = |capacity: u64| {
+            let output = CellOutput::new_builder()
+                .capacity(capacity.pack())
+                .lock(app.lock().clone())
+                .build();
+            Cell::new(OutPoint::new(Byte32::new([1; 32]), 0), output, vec![])
+        };
+
+        let err = app.build_genesis_tx(states.clone(), vec![funding_cell(1000 * 100_000_000)]);
+        assert!(matches!(
+            err,
+            Err(BuildError::NotEnoughCapacity {
+                available: 100_000_000_000,
+                ..
+            })
+        ));
+        let err = app.build_genesis_tx(states.clone(), vec![]);
+        assert!(matches!(
+            err,
+            Err(BuildError::NotEnoughCapacity { available: 0, .. })
+        ));
+        let err = app.build_genesis_tx(vec![], vec![funding_cell(2000 * 100_000_000)]);
+        assert!(matches!(err, Err(BuildError::StateCount(0))));
+        let big = vec![("gold", 1, vec![0u8; 1000])];
+        let err = app.build_genesis_tx(big, vec![funding_cell(2000 * 100_000_000)]);
+        assert!(matches!(err, Err(BuildError::InitialCapacity("gold", _))));
+
+        let funding = funding_cell(2000 * 100_000_000);
+        let (tx, genesis) = app.build_genesis_tx(states, vec![funding.clone()]).unwrap();
+        let input_hash = ckboots::utils::get_input_hash(&CellInput::new(funding.outpoint, 0));
+        let args = ckboots::utils::get_onchain_type_args(input_hash, "counter");
+        assert_eq!(genesis.type_script.args().as_slice(), args.as_slice());
+        assert_eq!(genesis.type_script.code_hash(), entry_hash);
+
+        // gold, hero, the registry and the change
+        assert_eq!(tx.outputs().len(), 4);
+        let input: u64 = 2000 * 100_000_000;
+        let output: u64 = tx.outputs_capacity().unwrap().as_u64();
+        assert!(input > output && input - output < 100_000);
+        let gold = genesis.cells.get(&("gold", vec![])).unwrap();
+        assert_eq!(gold.outpoint, OutPoint::new(tx.hash(), 0));
+        assert_eq!(
+            gold.output.type_().to_opt(),
+            Some(genesis.type_script.clone())
+        );
+        let capacity: u64 = gold.output.capacity().unpack();
+        assert_eq!(capacity, 500 * 100_000_000);
+        let (wrapper, _) = consume_and_decode::<OnChainWrapper>(&gold.data).unwrap();
+        assert_eq!(wrapper.idx, 0);
+        let hero = genesis.cells.get(&("hero", vec![])).unwrap();
+        let (wrapper, _) = consume_and_decode::<OnChainWrapper>(&hero.data).unwrap();
+        assert_eq!((wrapper.idx, wrapper.version), (1, 2));
+        let registry = genesis.cells.get(&(ckboots::REGISTRY_ID, vec![])).unwrap();
+        assert_eq!(registry.outpoint, OutPoint::new(tx.hash(), 2));
+        let (wrapper, _) = consume_and_decode::<OnChainWrapper>(&registry.data).unwrap();
+        assert_eq!(wrapper.idx, ckboots::REGISTRY_IDX);
+        assert!(decode::<Vec<[u8; 32]>>(&wrapper.data).unwrap().is_empty());
+
+        let manager = genesis.cell_manager().unwrap();
+        let (hero, _) = consume_and_decode::<Hero>(manager.get_by_id("hero").unwrap()).unwrap();
+        assert_eq!(hero.hp, 100);
+        let (gold, _) = consume_and_decode::<Gold>(manager.get_by_id("gold").unwrap()).unwrap();
+        assert_eq!(gold.amount, 7);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("genesis.json");
+        let record = genesis.record();
+        record.save(&path).unwrap();
+        let loaded = GenesisRecord::load(&path).unwrap();
+        let err = GenesisRecord::load(dir.path().join("missing.json"));
+        assert!(matches!(err, Err(FileError::Io(_, _))));
+        assert_eq!(loaded, record);
+        assert_eq!(loaded.out_points.len(), 3);
+    }
+
+    // A local stand-in of the node, answering json-rpc requests with `handle`
+    // Read `bytes` as the type `name` of a `.mol` schema into JSON, apart
+    // from the `Molecule` impls
+    #[cfg(feature = "contract-generator")]
+    fn read_molecule(schema: &str, name: &str, bytes: &[u8]) -> Value {
+        let decl = |name: &str| {
+            schema
+                .split("\n\n")
+                .map(|decl| {
+                    decl.lines()
+                        .filter(|line| !line.starts_with("//"))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .find(|decl| decl.split_whitespace().nth(1) == Some(name))
+                .unwrap_or_else(|| panic!("{} is not declared", name))
+        };
+        let inner = |decl: &str, open: char, close: char| {
+            let start = decl.find(open).unwrap() + 1;
+            decl[start..decl.rfind(close).unwrap()].to_string()
+        };
+        let fields = |decl: &str| {
+            decl.lines()
+                .filter_map(|line| line.trim().trim_end_matches(',').split_once(": "))
+                .map(|(field, ty)| (field.to_string(), ty.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        fn size_of(schema: &str, name: &str) -> Option<usize> {
+            if name == "byte" {
+                return Some(1);
+            }
+            let decl = schema
+                .split("\n\n")
+                .find(|decl| decl.split_whitespace().nth(1) == Some(name))?;
+            if decl.starts_with("array") {
+                let (item, n) = decl[decl.find('[')? + 1..decl.find(']')?].split_once("; ")?;
+                Some(size_of(schema, item)? * n.parse::<usize>().ok()?)
+            } else if decl.starts_with("struct") {
+                decl.lines()
+                    .filter_map(|line| line.trim().trim_end_matches(',').split_once(": "))
+                    .map(|(_, ty)| size_of(schema, ty))
+                    .sum()
+            } else {
+                None
+            }
+        }
+        // The offsets of a table or a dynvec
+        let items = || {
+            assert_eq!(u32_at(0), bytes.len());
+            let header = if bytes.len() == 4 { 4 } else { u32_at(4) };
+            let mut offsets = (1..header / 4).map(|i| u32_at(4 * i)).collect::<Vec<_>>();
+            offsets.push(bytes.len());
+            offsets
+                .windows(2)
+                .map(|w| &bytes[w[0]..w[1]])
+                .collect::<Vec<_>>()
+        };
+
+        if name == "byte" {
+            assert_eq!(bytes.len(), 1);
+            return json!(bytes[0]);
+        }
+        let decl = decl(name);
+        match decl.split_whitespace().next().unwrap() {
+            "array" | "vector" => {
+                let (item, count) = if decl.starts_with("array") {
+                    let inner = inner(&decl, '[', ']');
+                    let (item, n) = inner.split_once("; ").unwrap();
+                    (item.to_string(), Some(n.parse::<usize>().unwrap()))
+                } else {
+                    (inner(&decl, '<', '>'), None)
+                };
+                let parts = match (size_of(schema, &item), count) {
+                    (Some(size), Some(n)) => {
+                        assert_eq!(bytes.len(), size * n);
+                        bytes.chunks(size).collect()
+                    }
+                    (Some(size), None) => {
+                        assert_eq!(bytes.len(), 4 + size * u32_at(0));
+                        bytes[4..].chunks(size).collect()
+                    }
+                    _ => items(),
+                };
+                Value::Array(
+                    parts
+                        .into_iter()
+                        .map(|part| read_molecule(schema, &item, part))
+                        .collect(),
+                )
+            }
+            "option" if bytes.is_empty() => Value::Null,
+            "option" => read_molecule(schema, &inner(&decl, '(', ')'), bytes),
+            "union" => {
+                let (item, _) = fields(&decl)
+                    .into_iter()
+                    .find(|(_, id)| id.parse::<usize>().unwrap() == u32_at(0))
+                    .unwrap();
+                json!({ item.clone(): read_molecule(schema, &item, &bytes[4..]) })
+            }
+            kind => {
+                let fields = fields(&decl);
+                let parts = if kind == "struct" {
+                    let mut left = bytes;
+                    let parts = fields
+                        .iter()
+                        .map(|(_, ty)| {
+                            let (part, rest) = left.split_at(size_of(schema, ty).unwrap());
+                            left = rest;
+                            part
+                        })
+                        .collect::<Vec<_>>();
+                    assert!(left.is_empty());
+                    parts
+                } else {
+                    items()
+                };
+                assert_eq!(parts.len(), fields.len());
+                let object = fields
+                    .iter()
+                    .zip(parts)
+                    .map(|((field, ty), part)| (field.clone(), read_molecule(schema, ty, part)))
+                    .collect::<serde_json::Map<_, _>>();
+                Value::Object(object)
+            }
+        }
+    }
+
+    #[cfg(feature = "contract-generator")]
+    #[test]
+    fn molecule_cell_schema() {
+        use ckboots::Molecule;
+
+        #[derive(OnChain)]
+        #[onchain(encoding = "molecule")]
+        pub enum Mood {
+            #[onchain(tag = 0, default)]
+            Calm,
+            #[onchain(tag = 1)]
+            Angry(u8),
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "frog", encoding = "molecule")]
+        pub struct Frog {
+            #[onchain(default = 100)]
+            pub physical: u16,
+            #[onchain(default = "String::from(\"tom\")")]
+            pub name: String,
+            pub mood: Mood,
+        }
+
+        let config = CONFIG
+            .replace("\"2000000\"", "\"1000\"")
+            .parse::<Config>()
+            .unwrap();
+        let client = RpcClient::new(&config.chain.rpc);
+        let app = App::new(
+            config,
+            Box::new(client),
+            Deployment::default(),
+            HashMap::new(),
+        );
+        let output = CellOutput::new_builder()
+            .capacity((2000 * 100_000_000u64).pack())
+            .lock(app.lock().clone())
+            .build();
+        let funding = Cell::new(OutPoint::new(Byte32::new([1; 32]), 0), output, vec![]);
+        let states = vec![("frog", 1, Frog::_default()._to_bytes())];
+        let (_, genesis) = app.build_genesis_tx(states, vec![funding]).unwrap();
+        let frog = genesis.cells.get(&("frog", vec![])).unwrap();
+
+        let mut decls = vec![];
+        Frog::_molecule_decls(&mut decls);
+        let schema = ckboots::generators::contract::molecule_schema(decls);
+        let wrapper = read_molecule(&schema, "OnChainWrapper", &frog.data);
+        assert_eq!(wrapper["idx"], json!(0));
+        assert_eq!(wrapper["version"], json!(1));
+        let data = wrapper["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| b.as_u64().unwrap() as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            read_molecule(&schema, "Frog", &data),
+            json!({
+                "physical": [100, 0],
+                "name": [b't', b'o', b'm'],
+                "mood": { "MoodCalm": {} },
+            })
+        );
+    }
+
+    fn serve_json_rpc<F>(handle: F) -> String
+    where
+        F: Fn(&str, &Value) -> Value + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(l) = line.strip_prefix("content-length:") {
+                        len = l.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                let req: Value = serde_json::from_slice(&body).unwrap();
+                let result = handle(req["method"].as_str().unwrap(), &req["params"]);
+                let resp = json!({"jsonrpc": "2.0", "id": req["id"], "result": result}).to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    resp.len(),
+                    resp
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    fn indexer_cells(cells: &[Cell]) -> Value {
+        let objects = cells
+            .iter()
+            .map(|cell| {
+                json!({
+                    "output": ckb_jsonrpc_types::CellOutput::from(cell.output.clone()),
+                    "output_data": ckb_jsonrpc_types::JsonBytes::from_vec(cell.data.clone()),
+                    "out_point": ckb_jsonrpc_types::OutPoint::from(cell.outpoint.clone()),
+                    "block_number": "0x1",
+                    "tx_index": "0x0",
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({"objects": objects, "last_cursor": "0x"})
+    }
+
+    #[test]
+    fn load_cells_from_indexer() {
+        #[derive(OnChain)]
+        #[onchain(id = "hero")]
+        pub struct Hero {
+            #[onchain(default = 100)]
+            pub hp: u8,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "gold")]
+        pub struct Gold {
+            pub amount: u64,
+        }
+
+        let app_with = |url: &str| {
+            let config = CONFIG
+                .replace("\"2000000\"", "\"1000\"")
+                .parse::<Config>()
+                .unwrap();
+            App::new(
+                config,
+                Box::new(RpcClient::new(url)),
+                Deployment {
+                    entry: (OutPoint::new(Byte32::new([3; 32]), 0), Byte32::new([6; 32])),
+                    ..Default::default()
+                },
+                HashMap::new(),
+            )
+        };
+        let app = app_with("http://127.0.0.1:1");
+        let funding = CellOutput::new_builder()
+            .capacity((2000 * 100_000_000u64).pack())
+            .lock(app.lock().clone())
+            .build();
+        let funding = Cell::new(OutPoint::new(Byte32::new([1; 32]), 0), funding, vec![]);
+        let states = vec![
+            ("hero", 2, Hero::_default()._to_bytes()),
+            ("gold", 1, Gold::_default()._to_bytes()),
+        ];
+        let (_, genesis) = app.build_genesis_tx(states, vec![funding]).unwrap();
+        let mut cells = genesis.cells.values().cloned().collect::<Vec<_>>();
+        let type_script = ckb_jsonrpc_types::Script::from(genesis.type_script.clone());
+
+        let served = cells.clone();
+        let url = serve_json_rpc(move |method, params| {
+            assert_eq!(method, "get_cells");
+            let script: ckb_jsonrpc_types::Script =
+                serde_json::from_value(params[0]["script"].clone()).unwrap();
+            if script == type_script {
+                indexer_cells(&served)
+            } else {
+                indexer_cells(&[])
+            }
+        });
+        let mut app = app_with(&url);
+        let manager = app
+            .load_cells(genesis.input_hash.clone(), vec!["hero", "gold"])
+            .unwrap();
+        let (hero, _) = consume_and_decode::<Hero>(manager.get_by_id("hero").unwrap()).unwrap();
+        assert_eq!(hero.hp, 100);
+        let gold = genesis.cells.get(&("gold", vec![])).unwrap();
+        assert_eq!(manager.get_out_point("gold"), Some(&gold.outpoint));
+        assert_eq!(app.get_cell("gold").unwrap().outpoint, gold.outpoint);
+
+        let err = app.load_cells(Byte32::new([9; 32]), vec!["hero", "gold"]);
+        assert!(matches!(err, Err(LoadError::Missing("gold"))));
+
+        cells.push(cells[0].clone());
+        let url = serve_json_rpc(move |_, _| indexer_cells(&cells));
+        let mut app = app_with(&url);
+        let err = app.load_cells(genesis.input_hash.clone(), vec!["hero", "gold"]);
+        assert!(matches!(err, Err(LoadError::Duplicated(_))));
+    }
+
+    #[test]
+    fn sim_chain_checks_txs() {
+        use ckb_types::core::TransactionBuilder;
+        use ckboots::testing::SimChain;
+        use ckboots::{Client, ClientError};
+
+        let mut chain = SimChain::new();
+        let lock = CONFIG
+            .parse::<Config>()
+            .map(|c| {
+                App::new(
+                    c,
+                    Box::new(chain.clone()),
+                    Deployment::default(),
+                    HashMap::new(),
+                )
+            })
+            .unwrap()
+            .lock()
+            .clone();
+        let cell = chain.fund(&lock, 100 * 100_000_000);
+        let output = |capacity: u64| {
+            CellOutput::new_builder()
+                .capacity(capacity.pack())
+                .lock(lock.clone())
+                .build()
+        };
+        let tx = |capacity: u64| {
+            TransactionBuilder::default()
+                .input(CellInput::new(cell.outpoint.clone(), 0))
+                .output(output(capacity))
+                .output_data(Default::default())
+                .build()
+        };
+
+        let err = chain.send_transaction(tx(101 * 100_000_000));
+        assert!(matches!(err, Err(ClientError::Rejected(_))));
+        // No fee
+        let err = chain.send_transaction(tx(100 * 100_000_000));
+        assert!(matches!(err, Err(ClientError::Rejected(_))));
+        let err = chain.send_transaction(tx(60 * 100_000_000));
+        assert!(matches!(err, Err(ClientError::Rejected(_))));
+        assert_eq!(chain.get_tip_block_number().unwrap(), 0);
+
+        let tx = tx(99 * 100_000_000);
+        let hash = chain.send_transaction(tx.clone()).unwrap();
+        assert_eq!(hash, tx.hash());
+        assert_eq!(chain.get_tip_block_number().unwrap(), 1);
+        assert!(chain.get_live_cell(&cell.outpoint).is_none());
+        assert!(chain.get_live_cell(&OutPoint::new(hash, 0)).is_some());
+        let err = chain.send_transaction(tx);
+        assert!(matches!(err, Err(ClientError::Rejected(_))));
+    }
+
+    #[test]
+    fn keyed_instances() {
+        use ckboots::testing::SimChain;
+        use ckboots::StateError;
+
+        #[derive(OnChain)]
+        #[onchain(id = "hero", keyed)]
+        pub struct Hero {
+            #[onchain(default = 100)]
+            pub hp: u8,
+        }
+
+        #[derive(OnChain)]
+        #[onchain(id = "gold")]
+        pub struct Gold {
+            pub amount: u64,
+        }
+
+        assert!(Hero::_keyed());
+        assert!(!Gold::_keyed());
+
+        let chain = SimChain::new();
+        let mut contracts = HashMap::new();
+        contracts.insert(String::from("heal"), chain.deploy("heal"));
+        let deployment = Deployment {
+            entry: chain.deploy("_entry"),
+            contracts,
+            lock_dep: None,
+        };
+        let config = CONFIG
+            .replace("\"2000000\"", "\"1000\"")
+            .parse::<Config>()
+            .unwrap();
+        let mut app = App::new(config, Box::new(chain.clone()), deployment, HashMap::new());
+        let states = vec![
+            ("hero", 1, Hero::_default()._to_bytes()),
+            ("gold", 1, Gold::_default()._to_bytes()),
+        ];
+        let funding = chain.fund(app.lock(), 2000 * 100_000_000);
+        let (tx, genesis) = app.build_genesis_tx(states, vec![funding]).unwrap();
+        app.send_tx(tx).unwrap();
+        chain.fund(app.lock(), 1000 * 100_000_000);
+        let ids = vec!["hero", "gold"];
+        app.load_cells(genesis.input_hash.clone(), ids.clone())
+            .unwrap();
+
+        let state = ("hero", 1, Hero::_default()._to_bytes());
+        let (tx, alice, cells) = app.build_instance_tx(state).unwrap();
+        let first_input = tx.inputs().get(0).unwrap();
+        assert_eq!(alice, ckboots::utils::get_new_instance_key(&first_input, 0));
+        app.send_tx(tx).unwrap();
+        app.update_cells(cells);
+        let cell = app.get_instance_cell("hero", &alice).unwrap();
+        let type_script = cell.output.type_().to_opt().unwrap();
+        let key = ckboots::utils::get_instance_key(&genesis.type_script, &type_script);
+        assert_eq!(key, Some(alice.clone()));
+        let (wrapper, _) = consume_and_decode::<OnChainWrapper>(&cell.data).unwrap();
+        assert_eq!(wrapper.idx, 1);
+
+        let mut manager = app.load_cells(genesis.input_hash.clone(), ids).unwrap();
+        assert_eq!(manager.get_keys("hero"), vec![&[][..], &alice[..]]);
+        assert_eq!(manager.get_keys("gold").len(), 1);
+        manager
+            .update_instance(&alice, |hero: &mut Hero| hero.hp -= 30)
+            .unwrap();
+        manager.commit();
+        assert_eq!(manager.get_instance::<Hero>(&alice).unwrap().hp, 70);
+        assert_eq!(manager.get::<Hero>().unwrap().hp, 100);
+        assert_eq!(
+            manager.get_instance::<Gold>(&alice).err(),
+            Some(StateError::NotKeyed("gold"))
+        );
+        assert_eq!(
+            manager.get_instance::<Hero>(b"bob").err(),
+            Some(StateError::Unregistered("hero"))
+        );
+
+        let out_point = app
+            .get_instance_cell("hero", &alice)
+            .unwrap()
+            .outpoint
+            .clone();
+        let res = ContractResult {
+            deps: vec!["gold"],
+            input_output_data: vec![("hero", vec![70], vec![100])],
+            contract_id: "heal",
+            user_input: None,
+            versions: vec![],
+            keys: vec![("hero", alice.clone())],
+        };
+        let (tx, cells) = app.build_tx(res).unwrap();
+        assert_eq!(tx.inputs().get(0).unwrap().previous_output(), out_point);
+        assert_eq!(
+            tx.outputs().get(0).unwrap().type_().to_opt(),
+            Some(type_script)
+        );
+        app.send_tx(tx).unwrap();
+        app.update_cells(cells);
+        let cell = app.get_instance_cell("hero", &alice).unwrap();
+        let (wrapper, _) = consume_and_decode::<OnChainWrapper>(&cell.data).unwrap();
+        assert_eq!(wrapper.data, vec![100]);
+        assert!(chain.get_live_cell(&cell.outpoint).is_some());
+    }
+
+    #[test]
+    fn owned_instances() {
+        use ckboots::testing::SimChain;
+        use ckboots::utils::get_sighash_lock;
+
+        #[derive(OnChain)]
+        #[onchain(id = "item", keyed, owned)]
+        pub struct Item {
+            pub count: u8,
+        }
+
+        assert!(Item::_owned());
+
+        let chain = SimChain::new();
+        let mut contracts = HashMap::new();
+        contracts.insert(String::from("craft"), chain.deploy("craft"));
+        let deployment = Deployment {
+            entry: chain.deploy("_entry"),
+            contracts,
+            lock_dep: None,
+        };
+        let config = CONFIG
+            .replace("\"2000000\"", "\"1000\"")
+            .parse::<Config>()
+            .unwrap();
+        let mut app = App::new(config, Box::new(chain.clone()), deployment, HashMap::new());
+        let state = ("item", 1, Item::_default()._to_bytes());
+        let funding = chain.fund(app.lock(), 2000 * 100_000_000);
+        let (tx, genesis) = app
+            .build_genesis_tx(vec![state.clone()], vec![funding])
+            .unwrap();
+        app.send_tx(tx).unwrap();
+        app.update_cells(genesis.cells);
+        chain.fund(app.lock(), 1000 * 100_000_000);
+
+        let player_key = secp256k1::SecretKey::from_slice(&[2; 32]).unwrap();
+        let player_lock = get_sighash_lock(&player_key);
+        let (tx, key, cells) = app
+            .build_owned_instance_tx(state, player_lock.clone())
+            .unwrap();
+        assert_eq!(key.len(), 32);
+        app.send_tx(tx).unwrap();
+        app.update_cells(cells);
+        let cell = app.get_instance_cell("item", &key).unwrap().clone();
+        assert_eq!(cell.output.lock(), player_lock);
+
+        // The state grows, so the operator pays for it after the item
+        let res = ContractResult {
+            deps: vec![],
+            input_output_data: vec![("item", vec![0], vec![7; 200])],
+            contract_id: "craft",
+            user_input: None,
+            versions: vec![],
+            keys: vec![("item", key.clone())],
+        };
+        let (tx, cells) = app.build_tx(res).unwrap();
+        assert_eq!(tx.inputs().get(0).unwrap().previous_output(), cell.outpoint);
+        assert_eq!(tx.outputs().get(0).unwrap().lock(), player_lock);
+        let lock_of = |tx: &ckb_types::core::TransactionView, idx: usize| {
+            let witness = tx.witnesses().get(idx).unwrap();
+            let witness = WitnessArgs::from_slice(&witness.raw_data()).unwrap();
+            witness.lock().to_opt().unwrap().raw_data().to_vec()
+        };
+        let placeholder = vec![0u8; 65];
+        assert_eq!(lock_of(&tx, 0), placeholder);
+        assert_eq!(lock_of(&tx, 1), placeholder);
+
+        let tx = app.sign_tx(tx).unwrap();
+        assert_eq!(lock_of(&tx, 0), placeholder);
+        assert_ne!(lock_of(&tx, 1), placeholder);
+        let tx = app.sign_tx_as(tx, &player_key).unwrap();
+        assert_ne!(lock_of(&tx, 0), placeholder);
+        let witness = WitnessArgs::from_slice(&tx.witnesses().get(0).unwrap().raw_data()).unwrap();
+        assert!(witness.output_type().to_opt().is_some());
+        app.send_tx(tx).unwrap();
+        app.update_cells(cells);
+        let cell = app.get_instance_cell("item", &key).unwrap();
+        assert!(chain.get_live_cell(&cell.outpoint).is_some());
+        assert_eq!(cell.output.lock(), player_lock);
+    }
+
+    // The registry of the contracts `hashes`, see `REGISTRY_IDX`
+    fn registry_cell(out_point: OutPoint, hashes: Vec<[u8; 32]>) -> Cell {
+        let data = OnChainWrapper {
+            idx: ckboots::REGISTRY_IDX,
+            version: 0,
+            data: hashes._to_bytes(),
+        }
+        ._to_bytes();
+        let output = CellOutput::new_builder()
+            .capacity((1000 * 100_000_000u64).pack())
+            .build();
+        Cell::new(out_point, output, data)
+    }
+
+    // A RISC-V program doing nothing but `exit(code)`
+    fn exit_elf(code: i8) -> Vec<u8> {
+        let addi = |rd: u32, imm: i32| ((imm as u32 & 0xfff) << 20) | (rd << 7) | 0x13;
+        let text = [addi(10, code as i32), addi(17, 93), 0x73];
+        let vaddr = 0x10000u64;
+        let len = 64 + 56 + 4 * text.len() as u64;
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+        elf.resize(16, 0);
+        elf.extend(2u16.to_le_bytes());
+        elf.extend(243u16.to_le_bytes());
+        elf.extend(1u32.to_le_bytes());
+        elf.extend((vaddr + 64 + 56).to_le_bytes());
+        elf.extend(64u64.to_le_bytes());
+        elf.extend(0u64.to_le_bytes());
+        elf.extend(0u32.to_le_bytes());
+        [64u16, 56, 1, 64, 0, 0]
+            .iter()
+            .for_each(|n| elf.extend(n.to_le_bytes()));
+        elf.extend(1u32.to_le_bytes());
+        elf.extend(5u32.to_le_bytes());
+        [0, vaddr, vaddr, len, len, 0x1000]
+            .iter()
+            .for_each(|n| elf.extend(n.to_le_bytes()));
+        text.iter().for_each(|i| elf.extend(i.to_le_bytes()));
+        elf
+    }
+
+    #[test]
+    fn dry_run_contract() {
+        use ckb_types::core::ScriptHashType;
+        use ckb_types::packed::Script;
+        use ckboots::{ContractError, DryRunError};
+
+        let dir = tempfile::tempdir().unwrap();
+        let build = dir.path().join("build").join("release");
+        std::fs::create_dir_all(&build).unwrap();
+        std::fs::write(build.join("incr"), exit_elf(0)).unwrap();
+        std::fs::write(build.join("drain"), exit_elf(0)).unwrap();
+
+        let type_script = Script::new_builder()
+            .code_hash(Byte32::new([6; 32]))
+            .hash_type(ScriptHashType::Type.into())
+            .build();
+        let output = CellOutput::new_builder()
+            .type_(Some(type_script).pack())
+            .build();
+        let data = OnChainWrapper {
+            idx: 0,
+            version: 1,
+            data: 1u32._to_bytes(),
+        }
+        ._to_bytes();
+        let mut cells = HashMap::new();
+        let out_point = OutPoint::new(Byte32::new([1; 32]), 0);
+        cells.insert(("counter", vec![]), Cell::new(out_point, output, data));
+        let registry = registry_cell(OutPoint::new(Byte32::new([2; 32]), 0), vec![[4; 32]]);
+        cells.insert((ckboots::REGISTRY_ID, vec![]), registry);
+        let mut contracts = HashMap::new();
+        let out_point = OutPoint::new(Byte32::new([3; 32]), 0);
+        contracts.insert(
+            String::from("incr"),
+            (out_point.clone(), Byte32::new([4; 32])),
+        );
+        contracts.insert(String::from("drain"), (out_point, Byte32::new([5; 32])));
+        let deployment = Deployment {
+            contracts,
+            ..Default::default()
+        };
+        let config = format!("{}\n[dev]\ncontracts_dir = {:?}\n", CONFIG, dir.path());
+        let config = config.parse::<Config>().unwrap();
+        let client = RpcClient::new(&config.chain.rpc);
+        let app = App::new(config, Box::new(client), deployment, cells);
+        let res = ContractResult {
+            deps: vec![],
+            input_output_data: vec![("counter", 1u32._to_bytes(), 2u32._to_bytes())],
+            contract_id: "incr",
+            user_input: Some(vec![9]),
+            versions: vec![],
+            keys: vec![],
+        };
+
+        let err = app.dry_run(&res);
+        assert!(matches!(err, Err(DryRunError::Binary(_, _))));
+
+        std::fs::write(build.join("_entry"), exit_elf(0)).unwrap();
+        // The `_entry` reads the witness like this
+        let tx = app.dry_run_tx(&res).unwrap();
+        let witness = WitnessArgs::from_slice(&tx.witnesses().get(0).unwrap().raw_data()).unwrap();
+        assert_eq!(ckboots::utils::witness_user_input(&witness), vec![9]);
+        assert_eq!(ckboots::utils::witness_exec_script(&witness).len(), 32);
+        let cycles = app.dry_run(&res).unwrap();
+        assert!(cycles > 0);
+
+        // `_entry` only execs the contracts in the registry of the app
+        let err = app.dry_run(&ContractResult {
+            contract_id: "drain",
+            ..res.clone()
+        });
+        assert!(matches!(
+            err,
+            Err(DryRunError::Build(BuildError::Unregistered("drain")))
+        ));
+
+        std::fs::write(build.join("_entry"), exit_elf(5)).unwrap();
+        let err = app.dry_run(&res);
+        assert!(matches!(
+            err,
+            Err(DryRunError::Failed {
+                exit_code: 5,
+                error: Some(ContractError::Encoding),
+            })
+        ));
+    }
 }